@@ -0,0 +1,136 @@
+use crate::aabb::AABB;
+use crate::hittable::HitRecord;
+use crate::rtweekend::random_double;
+use crate::texture::Texture;
+use crate::{Hittable, Ray};
+use std::sync::Arc;
+
+const MAX_REJECTED_HITS: i32 = 16;
+
+//rejects hits where the texture's alpha is low, the ray then continues through the primitive
+pub struct AlphaMask {
+    ptr: Arc<dyn Hittable>,
+    alpha: Arc<dyn Texture>,
+    //None means stochastic transparency, a hit is kept with probability alpha
+    threshold: Option<f64>,
+}
+
+impl AlphaMask {
+    pub fn new(p: Arc<dyn Hittable>, alpha: Arc<dyn Texture>, threshold: f64) -> Self {
+        Self {
+            ptr: p,
+            alpha,
+            threshold: Some(threshold),
+        }
+    }
+
+    pub fn new_stochastic(p: Arc<dyn Hittable>, alpha: Arc<dyn Texture>) -> Self {
+        Self {
+            ptr: p,
+            alpha,
+            threshold: None,
+        }
+    }
+
+    fn opaque(&self, rec: &HitRecord) -> bool {
+        let a = self.alpha.alpha_at(rec);
+        match self.threshold {
+            Some(threshold) => a >= threshold,
+            None => a >= 1.0 || (a > 0.0 && random_double(0.0, 1.0) < a),
+        }
+    }
+}
+
+impl Hittable for AlphaMask {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        //rejected hits stay out of rec, callers take a miss to leave it as it was
+        let mut tmp_rec = HitRecord::new();
+        let mut t_lo = t_min;
+        for _ in 0..MAX_REJECTED_HITS {
+            if !self.ptr.hit(r, t_lo, t_max, &mut tmp_rec) {
+                return false;
+            }
+            if self.opaque(&tmp_rec) {
+                *rec = tmp_rec;
+                return true;
+            }
+            t_lo = tmp_rec.t + 0.0001;
+        }
+        false
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.ptr.bounding_box(time0, time1, output_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::BvhNode;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ImageTexture;
+    use crate::vec3::{Color, Point3};
+    use crate::{HittableList, Vec3};
+
+    fn sphere_with_alpha(alpha: f32, threshold: Option<f64>) -> AlphaMask {
+        let sphere = Arc::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new(Color::ones())),
+        ));
+        let texture = Arc::new(ImageTexture::from_data(1, 1, vec![1.0, 1.0, 1.0, alpha]));
+        match threshold {
+            Some(threshold) => AlphaMask::new(sphere, texture, threshold),
+            None => AlphaMask::new_stochastic(sphere, texture),
+        }
+    }
+
+    #[test]
+    fn test_cutout_skips_transparent_hits() {
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(sphere_with_alpha(0.8, Some(0.5)).hit(r, 0.001, 100.0, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert!(!sphere_with_alpha(0.2, Some(0.5)).hit(r, 0.001, 100.0, &mut rec));
+    }
+
+    #[test]
+    fn test_masked_miss_keeps_the_hit_behind_it() {
+        //the opaque sphere has the lower bounds on every axis, so the bvh visits it first
+        let opaque: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(-0.1, -0.1, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::ones())),
+        ));
+        let mut objects = HittableList::new_default();
+        objects.add(opaque.clone());
+        objects.add(Arc::new(sphere_with_alpha(0.0, Some(0.5))));
+        let bvh = BvhNode::new_(&mut objects, 0.0, 0.0);
+
+        //the masked sphere sits between the camera and the opaque one
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut expected = HitRecord::new();
+        assert!(opaque.hit(r, 0.001, 100.0, &mut expected));
+        let mut rec = HitRecord::new();
+        assert!(bvh.hit(r, 0.001, 100.0, &mut rec));
+        assert!((rec.t - expected.t).abs() < 1e-12);
+        assert!((rec.p - expected.p).length() < 1e-12);
+        assert!((rec.normal - expected.normal).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_stochastic_alpha_keeps_hits_with_probability_alpha() {
+        let mask = sphere_with_alpha(0.25, None);
+        let r = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let n = 100_000;
+        let mut rec = HitRecord::new();
+        let hits = (0..n)
+            .filter(|_| mask.hit(r, 0.001, 100.0, &mut rec))
+            .count();
+        //either side of the sphere can stop the ray
+        let expected = 1.0 - 0.75 * 0.75;
+        assert!((hits as f64 / n as f64 - expected).abs() < 0.01);
+    }
+}
@@ -1,6 +1,10 @@
 use crate::aabb::AABB;
+use crate::color::luminance;
 use crate::hittable::HitRecord;
+use crate::light::{cosine_direction, EmissionSample, Light, LightSample};
 use crate::material::Material;
+use crate::onb::Onb;
+use crate::rtweekend::{INFINITY, PI};
 use crate::vec3::Point3;
 use crate::{Hittable, Ray, Vec3};
 use std::sync::Arc;
@@ -40,11 +44,16 @@ impl Hittable for XYRect {
         }
         rec.u = (x - self.x0) / (self.x1 - self.x0);
         rec.v = (y - self.y0) / (self.y1 - self.y0);
+        rec.dpdu = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, self.y1 - self.y0, 0.0);
+        rec.dndu = Vec3::zero();
+        rec.dndv = Vec3::zero();
         rec.t = t;
         let mut outward_normal = Vec3::new(0.0, 0.0, 1.0);
         rec.set_face_normal(&r, &mut outward_normal);
         rec.mat_ptr = self.mp.clone();
         rec.p = r.at(t);
+        rec.p_object = rec.p;
         true
     }
 
@@ -92,11 +101,16 @@ impl Hittable for XZRect {
         }
         rec.u = (x - self.x0) / (self.x1 - self.x0);
         rec.v = (z - self.z0) / (self.z1 - self.z0);
+        rec.dpdu = Vec3::new(self.x1 - self.x0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 0.0, self.z1 - self.z0);
+        rec.dndu = Vec3::zero();
+        rec.dndv = Vec3::zero();
         rec.t = t;
         let mut outward_normal = Vec3::new(0.0, 1.0, 0.0);
         rec.set_face_normal(&r, &mut outward_normal);
         rec.mat_ptr = self.mp.clone();
         rec.p = r.at(t);
+        rec.p_object = rec.p;
         true
     }
 
@@ -144,11 +158,16 @@ impl Hittable for YZRect {
         }
         rec.u = (y - self.y0) / (self.y1 - self.y0);
         rec.v = (z - self.z0) / (self.z1 - self.z0);
+        rec.dpdu = Vec3::new(0.0, self.y1 - self.y0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 0.0, self.z1 - self.z0);
+        rec.dndu = Vec3::zero();
+        rec.dndv = Vec3::zero();
         rec.t = t;
         let mut outward_normal = Vec3::new(1.0, 0.0, 0.0);
         rec.set_face_normal(&r, &mut outward_normal);
         rec.mat_ptr = self.mp.clone();
         rec.p = r.at(t);
+        rec.p_object = rec.p;
         true
     }
 
@@ -160,3 +179,250 @@ impl Hittable for YZRect {
         true
     }
 }
+
+//RectLight
+//emissive rectangle that can also be sampled as a light, registered through LightList::add_rect
+//both faces may emit, emission picks a face in proportion to what it gives off
+pub struct RectLight {
+    rect: Arc<dyn Hittable>,
+    corner: Point3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    normal: Vec3,
+    area: f64,
+    index: usize,
+    //emitted power of the face along normal and of the one against it
+    power: [f64; 2],
+}
+
+impl RectLight {
+    pub fn new(min: Point3, max: Point3, material: Arc<dyn Material>, index: usize) -> Self {
+        let d = max - min;
+        let (rect, edge_u, edge_v): (Arc<dyn Hittable>, Vec3, Vec3) = if d.z == 0.0 {
+            (
+                Arc::new(XYRect::new(min.x, max.x, min.y, max.y, min.z, material)),
+                Vec3::new(d.x, 0.0, 0.0),
+                Vec3::new(0.0, d.y, 0.0),
+            )
+        } else if d.y == 0.0 {
+            (
+                Arc::new(XZRect::new(min.x, max.x, min.z, max.z, min.y, material)),
+                Vec3::new(0.0, 0.0, d.z),
+                Vec3::new(d.x, 0.0, 0.0),
+            )
+        } else if d.x == 0.0 {
+            (
+                Arc::new(YZRect::new(min.y, max.y, min.z, max.z, min.x, material)),
+                Vec3::new(0.0, d.y, 0.0),
+                Vec3::new(0.0, 0.0, d.z),
+            )
+        } else {
+            panic!("rect light corners must share one coordinate");
+        };
+        let area = Vec3::cross(edge_u, edge_v).length();
+        let mut light = Self {
+            rect,
+            corner: min,
+            edge_u,
+            edge_v,
+            normal: Vec3::cross(edge_u, edge_v).unit(),
+            area,
+            index,
+            power: [0.0; 2],
+        };
+        //average the emission over a grid of points looking straight at either face
+        let n = 8;
+        for (k, side) in [1.0, -1.0].iter().enumerate() {
+            let mut sum = 0.0;
+            for i in 0..n {
+                for j in 0..n {
+                    let q = light.point((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                    let ray = Ray::new(q + light.normal * *side, -light.normal * *side, 0.0);
+                    if let Some(rec) = light.trace(ray) {
+                        sum += luminance(rec.mat_ptr.emitted(ray, &rec));
+                    }
+                }
+            }
+            light.power[k] = sum / (n * n) as f64 * PI * area;
+        }
+        light
+    }
+
+    fn point(&self, u: f64, v: f64) -> Point3 {
+        self.corner + self.edge_u * u + self.edge_v * v
+    }
+
+    fn trace(&self, ray: Ray) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        if !self.rect.hit(ray, 1e-6, INFINITY, &mut rec) {
+            return None;
+        }
+        Some(rec)
+    }
+
+    //probability that emission leaves through the face dir points away from
+    fn side_pmf(&self, dir: Vec3) -> f64 {
+        let total = self.power[0] + self.power[1];
+        if total <= 0.0 {
+            return 0.0;
+        }
+        if Vec3::dot(self.normal, dir) >= 0.0 {
+            self.power[0] / total
+        } else {
+            self.power[1] / total
+        }
+    }
+}
+
+impl Hittable for RectLight {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.rect.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+        rec.light = Some(self.index);
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.rect.bounding_box(time0, time1, output_box)
+    }
+}
+
+impl Light for RectLight {
+    //uniform over the area, converted to solid angle
+    fn sample_li(&self, p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let wi = (self.point(u1, u2) - p).unit();
+        let ray = Ray::new(p, wi, 0.0);
+        let rec = self.trace(ray)?;
+        let pdf = self.pdf_li(p, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: rec.mat_ptr.emitted(ray, &rec),
+            pdf,
+            dist: rec.t,
+        })
+    }
+
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        let wi = wi.unit();
+        match self.trace(Ray::new(p, wi, 0.0)) {
+            Some(rec) => {
+                let cosine = Vec3::dot(self.normal, wi).abs();
+                if cosine <= 0.0 {
+                    return 0.0;
+                }
+                rec.t * rec.t / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn bounds(&self) -> Option<AABB> {
+        let mut output_box = AABB::default_new();
+        self.rect.bounding_box(0.0, 0.0, &mut output_box);
+        Some(output_box)
+    }
+
+    fn power(&self) -> f64 {
+        self.power[0] + self.power[1]
+    }
+
+    //uniform over the area, cosine weighted around the normal of a face picked by its power
+    fn sample_le(&self, u: [f64; 4]) -> Option<EmissionSample> {
+        let total = self.power();
+        if total <= 0.0 {
+            return None;
+        }
+        let front = self.power[0] / total;
+        let (n, u2) = if u[2] < front {
+            (self.normal, u[2] / front)
+        } else {
+            (-self.normal, (u[2] - front) / (1.0 - front))
+        };
+        let p = self.point(u[0], u[1]);
+        let dir = Onb::build_from_w(n).local(cosine_direction(u2.min(1.0), u[3]));
+        let cosine = Vec3::dot(n, dir);
+        if cosine <= 0.0 {
+            return None;
+        }
+        //look back at the point from the emitting side to evaluate the emission
+        let probe = Ray::new(p + dir, -dir, 0.0);
+        let rec = self.trace(probe)?;
+        Some(EmissionSample {
+            ray: Ray::new(p, dir, 0.0),
+            le: rec.mat_ptr.emitted(probe, &rec),
+            normal: n,
+            pdf_pos: 1.0 / self.area,
+            pdf_dir: self.side_pmf(dir) * cosine / PI,
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, dir: Vec3) -> (f64, f64) {
+        let dir = dir.unit();
+        (
+            1.0 / self.area,
+            self.side_pmf(dir) * Vec3::dot(self.normal, dir).abs() / PI,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::uniform_sphere;
+    use crate::material::DiffuseLight;
+    use crate::vec3::Color;
+    use rand::Rng;
+
+    fn light() -> RectLight {
+        RectLight::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 1.0),
+            Arc::new(DiffuseLight::new_by_color(Color::ones())),
+            0,
+        )
+    }
+
+    #[test]
+    fn test_sample_li_matches_pdf_li() {
+        let light = light();
+        let mut rng = rand::thread_rng();
+        let p = Point3::new(0.5, 1.0, 0.3);
+        let n = 100_000;
+        let mut solid_angle = 0.0;
+        for _ in 0..n {
+            let ls = light.sample_li(p, rng.gen(), rng.gen()).unwrap();
+            assert!((ls.pdf - light.pdf_li(p, ls.wi)).abs() < 1e-9 * ls.pdf);
+            solid_angle += 1.0 / ls.pdf;
+        }
+        //the same solid angle from directions picked uniformly over the sphere
+        let hits = (0..n)
+            .filter(|_| light.pdf_li(p, uniform_sphere(rng.gen(), rng.gen())) > 0.0)
+            .count();
+        let expected = 4.0 * PI * hits as f64 / n as f64;
+        assert!((solid_angle / n as f64 - expected).abs() < 0.03 * expected);
+    }
+
+    #[test]
+    fn test_emission_carries_the_power_of_both_faces() {
+        let light = light();
+        assert!((light.power() - 2.0 * PI * 2.0).abs() < 1e-9);
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let u = [rng.gen(), rng.gen(), rng.gen(), rng.gen()];
+            let es = match light.sample_le(u) {
+                Some(es) => es,
+                None => continue,
+            };
+            let dir = es.ray.direction();
+            let (pdf_pos, pdf_dir) = light.pdf_le(es.ray.origin(), es.normal, dir);
+            assert!((pdf_pos - es.pdf_pos).abs() < 1e-12);
+            assert!((pdf_dir - es.pdf_dir).abs() < 1e-12);
+            let flux = luminance(es.le) * Vec3::dot(es.normal, dir) / (es.pdf_pos * es.pdf_dir);
+            assert!((flux - light.power()).abs() < 1e-6 * light.power());
+        }
+    }
+}
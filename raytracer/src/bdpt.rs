@@ -0,0 +1,684 @@
+use crate::camera::Camera;
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::Integrator;
+use crate::light::LightList;
+use crate::rtweekend::INFINITY;
+use crate::sampling::power_heuristic;
+use crate::vec3::{Color, Point3};
+use crate::{HittableList, Ray, Vec3};
+use rand::Rng;
+use std::sync::Arc;
+
+#[derive(Clone)]
+enum VertexKind {
+    Camera,
+    //index in the light list
+    Light(usize),
+    Surface(Box<HitRecord>),
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    p: Point3,
+    //facing the arriving ray, zero for the lens and point lights
+    n: Vec3,
+    r_in: Ray,
+    beta: Color,
+    delta: bool,
+    //area densities of generating the vertex from the camera side and from the light side
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, p: Point3, n: Vec3, beta: Color, pdf_fwd: f64) -> Self {
+        Self {
+            kind,
+            p,
+            n,
+            r_in: Ray::default_new(),
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn connectible(&self) -> bool {
+        matches!(self.kind, VertexKind::Surface(_)) && !self.delta
+    }
+}
+
+//solid angle density at from turned into area density at to
+fn convert_density(pdf: f64, from: &Vertex, to: &Vertex) -> f64 {
+    let d = to.p - from.p;
+    let dist2 = d.squared_length();
+    if dist2 <= 0.0 {
+        return 0.0;
+    }
+    let mut pdf = pdf / dist2;
+    if to.n.squared_length() > 0.0 {
+        pdf *= Vec3::dot(to.n, d).abs() / dist2.sqrt();
+    }
+    pdf
+}
+
+//Bdpt
+//bidirectional path tracer, every camera subpath vertex is joined to every light subpath vertex
+//lights at infinity can't start subpaths and are handled like in ray_color
+#[derive(Clone)]
+pub struct Bdpt {
+    camera: Camera,
+    world: Arc<HittableList>,
+    lights: Arc<LightList>,
+    max_depth: usize,
+    //the last row and column of pixels reach past the viewport
+    film_extent: (f64, f64),
+    film_scale: f64,
+}
+
+impl Bdpt {
+    pub fn new(
+        camera: Camera,
+        world: Arc<HittableList>,
+        lights: Arc<LightList>,
+        max_depth: usize,
+        (image_width, image_height): (f64, f64),
+    ) -> Self {
+        let film_extent = (
+            image_width / (image_width - 1.0),
+            image_height / (image_height - 1.0),
+        );
+        Self {
+            camera,
+            world,
+            lights,
+            max_depth,
+            film_extent,
+            film_scale: film_extent.0 * film_extent.1,
+        }
+    }
+
+    //returns what lights at infinity send along the subpath
+    fn camera_subpath(&self, ray: Ray, path: &mut Vec<Vertex>) -> Color {
+        path.push(Vertex::new(
+            VertexKind::Camera,
+            ray.origin(),
+            Vec3::zero(),
+            Color::ones(),
+            1.0,
+        ));
+        let pdf_dir = self.camera.pdf_we(ray.direction(), self.film_scale);
+        self.random_walk(ray, Color::ones(), pdf_dir, true, path)
+    }
+
+    fn light_subpath(&self, path: &mut Vec<Vertex>) {
+        let mut rng = rand::thread_rng();
+        let (index, pmf) = match self.lights.pick_emitter(rng.gen::<f64>()) {
+            Some(picked) => picked,
+            None => return,
+        };
+        let u = [rng.gen(), rng.gen(), rng.gen(), rng.gen()];
+        let es = match self.lights.lights[index].sample_le(u) {
+            Some(es) if es.pdf_pos > 0.0 && es.pdf_dir > 0.0 && !es.le.near_zero() => es,
+            _ => return,
+        };
+        let pdf_origin = pmf * es.pdf_pos;
+        path.push(Vertex::new(
+            VertexKind::Light(index),
+            es.ray.origin(),
+            es.normal,
+            es.le / pdf_origin,
+            pdf_origin,
+        ));
+        let cosine = if es.normal.squared_length() > 0.0 {
+            Vec3::dot(es.normal, es.ray.direction().unit()).abs()
+        } else {
+            1.0
+        };
+        let beta = es.le * (cosine / (pdf_origin * es.pdf_dir));
+        self.random_walk(es.ray, beta, es.pdf_dir, false, path);
+    }
+
+    fn random_walk(
+        &self,
+        mut ray: Ray,
+        mut beta: Color,
+        mut pdf_dir: f64,
+        camera_side: bool,
+        path: &mut Vec<Vertex>,
+    ) -> Color {
+        let mut infinite = Color::zero();
+        //density the current ray was scattered with, 0 when it left the lens or a delta bounce
+        let mut bsdf_pdf = 0.0;
+        while path.len() <= self.max_depth {
+            let mut rec = HitRecord::new();
+            if !self.world.hit(ray, 0.001, INFINITY, &mut rec) {
+                if camera_side {
+                    infinite += beta * self.escaped(ray, bsdf_pdf);
+                }
+                break;
+            }
+
+            let prev = path.len() - 1;
+            let mut vertex = Vertex::new(
+                VertexKind::Surface(Box::new(rec.clone())),
+                rec.p,
+                rec.normal,
+                beta,
+                0.0,
+            );
+            vertex.r_in = ray;
+            vertex.pdf_fwd = convert_density(pdf_dir, &path[prev], &vertex);
+            path.push(vertex);
+
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !rec
+                .mat_ptr
+                .scatter(ray, &rec, &mut attenuation, &mut scattered)
+            {
+                break;
+            }
+            let pdf_fwd = rec.mat_ptr.scattering_pdf(ray, &rec, scattered);
+            let delta = pdf_fwd <= 0.0;
+            let pdf_rev = if delta {
+                0.0
+            } else {
+                let back = Ray::new(rec.p, -scattered.direction().unit(), ray.time());
+                let toward_prev = Ray::new(rec.p, -ray.direction().unit(), ray.time());
+                rec.mat_ptr.scattering_pdf(back, &rec, toward_prev)
+            };
+            if camera_side && !delta {
+                infinite += beta * self.sample_infinite(ray, &rec);
+            }
+
+            let cur = path.len() - 1;
+            path[cur].delta = delta;
+            path[prev].pdf_rev = convert_density(pdf_rev, &path[cur], &path[prev]);
+
+            beta = beta * attenuation;
+            if beta.near_zero() {
+                break;
+            }
+            ray = scattered;
+            pdf_dir = pdf_fwd;
+            bsdf_pdf = pdf_fwd;
+        }
+        infinite
+    }
+
+    fn escaped(&self, r: Ray, bsdf_pdf: f64) -> Color {
+        let le = self.lights.le(&r);
+        if bsdf_pdf <= 0.0 {
+            return le;
+        }
+        le * power_heuristic(
+            bsdf_pdf,
+            self.lights.pdf_infinite(r.origin(), r.direction().unit()),
+        )
+    }
+
+    fn sample_infinite(&self, r: Ray, rec: &HitRecord) -> Color {
+        let mut rng = rand::thread_rng();
+        let (light, pick_pdf) = match self.lights.pick_infinite(rng.gen::<f64>()) {
+            Some(picked) => picked,
+            None => return Color::zero(),
+        };
+        let ls = match light.sample_li(rec.p, rng.gen::<f64>(), rng.gen::<f64>()) {
+            Some(ls) if ls.pdf > 0.0 => ls,
+            _ => return Color::zero(),
+        };
+        let shadow = Ray::new(rec.p, ls.wi, r.time());
+        let f = rec.mat_ptr.eval_bsdf(r, rec, shadow);
+        if f.near_zero() {
+            return Color::zero();
+        }
+        let tr = self.transmittance(rec.p, ls.wi, ls.dist);
+        if tr <= 0.0 {
+            return Color::zero();
+        }
+        let light_pdf = ls.pdf * pick_pdf;
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, rec.mat_ptr.scattering_pdf(r, rec, shadow))
+        };
+        f * ls.li * (tr * weight / light_pdf)
+    }
+
+    fn transmittance(&self, p: Point3, dir: Vec3, dist: f64) -> f64 {
+        self.world
+            .transmittance(Ray::new(p, dir, 0.0), 0.001, dist - 0.001)
+    }
+
+    //bsdf times the cosine at a surface vertex, toward dir
+    fn f(v: &Vertex, dir: Vec3) -> Color {
+        match &v.kind {
+            VertexKind::Surface(rec) => {
+                rec.mat_ptr
+                    .eval_bsdf(v.r_in, rec, Ray::new(v.p, dir, v.r_in.time()))
+            }
+            _ => Color::zero(),
+        }
+    }
+
+    fn connect(
+        &self,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        splats: &mut Vec<(f64, f64, Color)>,
+    ) -> Color {
+        let mut rng = rand::thread_rng();
+        let mut sampled = None;
+        let l;
+        if s == 0 {
+            //the camera subpath ran into an emitter
+            let pt = &camera_path[t - 1];
+            let rec = match &pt.kind {
+                VertexKind::Surface(rec) => rec,
+                _ => return Color::zero(),
+            };
+            l = pt.beta * rec.mat_ptr.emitted(pt.r_in, rec);
+            if l.near_zero() {
+                return Color::zero();
+            }
+            //emitters missing from the light list can't be found any other way
+            match rec.light {
+                Some(index) if self.lights.emitter_pmf(index) > 0.0 => {}
+                _ => return l,
+            }
+        } else if t == 1 {
+            //light tracing, the light subpath is joined to a point on the lens
+            let qs = &light_path[s - 1];
+            if !qs.connectible() {
+                return Color::zero();
+            }
+            let p_lens = self.camera.random_lens_point();
+            let d = p_lens - qs.p;
+            let dist = d.length();
+            let wi = d / dist;
+            let (fs, ft) = match self.camera.project(p_lens, -wi) {
+                Some(st) => st,
+                None => return Color::zero(),
+            };
+            if fs < 0.0 || ft < 0.0 || fs >= self.film_extent.0 || ft >= self.film_extent.1 {
+                return Color::zero();
+            }
+            let cos_lens = Vec3::dot(-wi, self.camera.forward());
+            let pdf = dist * dist / (cos_lens * self.camera.lens_area());
+            let we = self.camera.we(-wi, self.film_scale);
+            let contribution = qs.beta * Self::f(qs, wi) * (we / pdf);
+            if contribution.near_zero() {
+                return Color::zero();
+            }
+            let contribution = contribution * self.transmittance(qs.p, wi, dist);
+            let mut lens =
+                Vertex::new(VertexKind::Camera, p_lens, Vec3::zero(), Color::ones(), 0.0);
+            lens.beta = Color::ones() * (we / pdf);
+            let weight = self.mis_weight(light_path, camera_path, Some(&lens), s, t);
+            splats.push((fs, ft, contribution * weight));
+            return Color::zero();
+        } else if s == 1 {
+            //next event estimation toward a light picked by power
+            let pt = &camera_path[t - 1];
+            if !pt.connectible() {
+                return Color::zero();
+            }
+            let (index, pmf) = match self.lights.pick_emitter(rng.gen::<f64>()) {
+                Some(picked) => picked,
+                None => return Color::zero(),
+            };
+            let light = &self.lights.lights[index];
+            if light.is_delta() {
+                let ls = match light.sample_li(pt.p, rng.gen::<f64>(), rng.gen::<f64>()) {
+                    Some(ls) => ls,
+                    None => return Color::zero(),
+                };
+                let f = Self::f(pt, ls.wi);
+                if f.near_zero() {
+                    return Color::zero();
+                }
+                let tr = self.transmittance(pt.p, ls.wi, ls.dist);
+                l = pt.beta * f * ls.li * (tr / pmf);
+                sampled = Some(Vertex::new(
+                    VertexKind::Light(index),
+                    pt.p + ls.wi * ls.dist,
+                    Vec3::zero(),
+                    ls.li / pmf,
+                    pmf,
+                ));
+            } else {
+                //sample_li only picks points pt can face, e.g. the visible cap of a sphere
+                let ls = match light.sample_li(pt.p, rng.gen::<f64>(), rng.gen::<f64>()) {
+                    Some(ls) if ls.pdf > 0.0 => ls,
+                    _ => return Color::zero(),
+                };
+                let f = Self::f(pt, ls.wi);
+                if f.near_zero() {
+                    return Color::zero();
+                }
+                //the sampled point must be what pt actually sees in that direction
+                let ray = Ray::new(pt.p, ls.wi, pt.r_in.time());
+                let mut rec = HitRecord::new();
+                if !self.world.hit(ray, 0.001, INFINITY, &mut rec)
+                    || rec.light != Some(index)
+                    || (rec.t - ls.dist).abs() > 1e-4 * ls.dist.max(1.0)
+                {
+                    return Color::zero();
+                }
+                let le = rec.mat_ptr.emitted(ray, &rec);
+                l = pt.beta * f * le / (pmf * ls.pdf);
+                //the solid angle density in area measure, mis sees the vertex as a light subpath would start it
+                let dist2 = ls.dist * ls.dist;
+                let pdf_area = pmf * ls.pdf * Vec3::dot(rec.normal, ls.wi).abs() / dist2;
+                let pdf_origin = pmf * light.pdf_le(rec.p, rec.normal, -ls.wi).0;
+                sampled = Some(Vertex::new(
+                    VertexKind::Light(index),
+                    rec.p,
+                    rec.normal,
+                    le / pdf_area,
+                    pdf_origin,
+                ));
+            }
+        } else {
+            let qs = &light_path[s - 1];
+            let pt = &camera_path[t - 1];
+            if !qs.connectible() || !pt.connectible() {
+                return Color::zero();
+            }
+            let d = qs.p - pt.p;
+            let dist = d.length();
+            let wi = d / dist;
+            let unoccluded = qs.beta * Self::f(qs, -wi) * Self::f(pt, wi) * pt.beta / (dist * dist);
+            if unoccluded.near_zero() {
+                return Color::zero();
+            }
+            l = unoccluded * self.transmittance(pt.p, wi, dist);
+        }
+        if l.near_zero() {
+            return Color::zero();
+        }
+        l * self.mis_weight(light_path, camera_path, sampled.as_ref(), s, t)
+    }
+
+    //area density with which v, reached from prev, samples next
+    fn pdf(&self, v: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let dir = (next.p - v.p).unit();
+        let pdf_dir = match &v.kind {
+            VertexKind::Camera => self.camera.pdf_we(dir, self.film_scale),
+            VertexKind::Light(index) => self.lights.lights[*index].pdf_le(v.p, v.n, dir).1,
+            VertexKind::Surface(rec) => {
+                let prev = match prev {
+                    Some(prev) => prev,
+                    None => return 0.0,
+                };
+                let r_in = Ray::new(prev.p, (v.p - prev.p).unit(), v.r_in.time());
+                rec.mat_ptr
+                    .scattering_pdf(r_in, rec, Ray::new(v.p, dir, v.r_in.time()))
+            }
+        };
+        convert_density(pdf_dir, v, next)
+    }
+
+    //density of a light subpath starting at the emitter v and heading for next
+    fn pdf_light_origin(&self, v: &Vertex) -> f64 {
+        match &v.kind {
+            VertexKind::Surface(rec) => match rec.light {
+                Some(index) => {
+                    self.lights.emitter_pmf(index)
+                        * self.lights.lights[index].pdf_le(v.p, v.n, v.n).0
+                }
+                None => 0.0,
+            },
+            _ => 0.0,
+        }
+    }
+
+    fn pdf_light(&self, v: &Vertex, next: &Vertex) -> f64 {
+        match &v.kind {
+            VertexKind::Surface(rec) => match rec.light {
+                Some(index) => {
+                    let dir = next.p - v.p;
+                    let pdf_dir = self.lights.lights[index].pdf_le(v.p, v.n, dir).1;
+                    convert_density(pdf_dir, v, next)
+                }
+                None => 0.0,
+            },
+            _ => 0.0,
+        }
+    }
+
+    //power heuristic over every way of splitting the path into a camera and a light subpath
+    fn mis_weight(
+        &self,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        //the vertices at the connection, with a freshly sampled endpoint standing in where there is one
+        let qs = if s == 1 {
+            sampled
+        } else {
+            light_path[..s].last()
+        };
+        let pt = if t == 1 {
+            sampled
+        } else {
+            camera_path.get(t - 1)
+        };
+        let pt = match pt {
+            Some(pt) => pt,
+            None => return 0.0,
+        };
+        let qs_minus = if s > 1 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+        let pt_minus = if t > 1 {
+            Some(&camera_path[t - 2])
+        } else {
+            None
+        };
+
+        //(pdf_fwd, pdf_rev, delta) along both subpaths as this strategy sees them
+        let mut light: Vec<(f64, f64, bool)> = light_path[..s]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+        let mut camera: Vec<(f64, f64, bool)> = camera_path[..t]
+            .iter()
+            .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+            .collect();
+        if let Some(v) = sampled {
+            if s == 1 {
+                light[0] = (v.pdf_fwd, 0.0, false);
+            } else {
+                camera[0] = (v.pdf_fwd, 0.0, false);
+            }
+        }
+
+        camera[t - 1].2 = false;
+        camera[t - 1].1 = match qs {
+            Some(qs) => self.pdf(qs, qs_minus, pt),
+            None => self.pdf_light_origin(pt),
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].1 = match qs {
+                Some(qs) => self.pdf(pt, Some(qs), pt_minus),
+                None => self.pdf_light(pt, pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].2 = false;
+            light[s - 1].1 = self.pdf(pt, pt_minus, qs);
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].1 = self.pdf(qs, Some(pt), qs_minus);
+            }
+        }
+
+        let remap = |x: f64| if x != 0.0 { x } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ri = 1.0;
+        for i in (1..t).rev() {
+            ri *= remap(camera[i].1) / remap(camera[i].0);
+            if !camera[i].2 && !camera[i - 1].2 {
+                sum += ri * ri;
+            }
+        }
+        ri = 1.0;
+        for i in (0..s).rev() {
+            ri *= remap(light[i].1) / remap(light[i].0);
+            let delta_light_vertex = if i > 0 {
+                light[i - 1].2
+            } else {
+                match qs.map(|_| if s == 1 { sampled } else { light_path.first() }) {
+                    Some(Some(Vertex {
+                        kind: VertexKind::Light(index),
+                        ..
+                    })) => self.lights.lights[*index].is_delta(),
+                    _ => false,
+                }
+            };
+            if !light[i].2 && !delta_light_vertex {
+                sum += ri * ri;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for Bdpt {
+    //splats are summed per pixel and divided by the samples per pixel like everything else
+    fn li(&self, r: Ray, splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        let mut camera_path = Vec::with_capacity(self.max_depth + 1);
+        let mut light_path = Vec::with_capacity(self.max_depth + 1);
+        let mut l = self.camera_subpath(r, &mut camera_path);
+        self.light_subpath(&mut light_path);
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t < 2 || (s == 1 && t == 1) || s + t - 2 > self.max_depth {
+                    continue;
+                }
+                l += self.connect(&light_path, &camera_path, s, t, splats);
+            }
+        }
+        l
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    //a lambertian floor under a sphere light, seen from above
+    fn scene() -> (Bdpt, Point3) {
+        let mut lights = LightList::new_default();
+        let mut world = HittableList::new_default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let emit = Arc::new(DiffuseLight::new_by_color(Color::new(4.0, 4.0, 4.0)));
+        world.add(lights.add_sphere(Point3::new(0.0, 3.0, 0.0), 0.5, emit));
+        lights.build();
+        let eye = Point3::new(0.0, 2.0, 4.0);
+        let camera = Camera::new(
+            (eye, Point3::zero()),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            1.0,
+            (0.0, 1.0),
+        );
+        let bdpt = Bdpt::new(camera, Arc::new(world), Arc::new(lights), 5, (16.0, 16.0));
+        (bdpt, eye)
+    }
+
+    fn surface(bdpt: &Bdpt, from: Point3, toward: Point3) -> Vertex {
+        let r = Ray::new(from, (toward - from).unit(), 0.0);
+        let mut rec = HitRecord::new();
+        assert!(bdpt.world.hit(r, 0.001, INFINITY, &mut rec));
+        let mut v = Vertex::new(
+            VertexKind::Surface(Box::new(rec.clone())),
+            rec.p,
+            rec.normal,
+            Color::ones(),
+            0.0,
+        );
+        v.r_in = r;
+        v
+    }
+
+    #[test]
+    fn test_mis_weights_of_one_path_sum_to_one() {
+        let (bdpt, eye) = scene();
+        let index = 0;
+        let pmf = bdpt.lights.emitter_pmf(index);
+        let light = &bdpt.lights.lights[index];
+        let lens = Vertex::new(VertexKind::Camera, eye, Vec3::zero(), Color::ones(), 1.0);
+
+        for &floor in [
+            Point3::zero(),
+            Point3::new(0.4, 0.0, -0.3),
+            Point3::new(-0.6, 0.0, 0.5),
+        ]
+        .iter()
+        {
+            //eye, floor, light bottom as the camera would trace it
+            let mut x1 = surface(&bdpt, eye, floor);
+            let mut x2 = surface(&bdpt, x1.p, Point3::new(0.1, 3.0, 0.0));
+            x1.pdf_fwd = bdpt.pdf(&lens, None, &x1);
+            x2.pdf_fwd = bdpt.pdf(&x1, Some(&lens), &x2);
+            let mut eye_vertex = lens.clone();
+            eye_vertex.pdf_rev = bdpt.pdf(&x1, Some(&x2), &lens);
+            let camera_path = vec![eye_vertex, x1.clone(), x2.clone()];
+
+            //the same points as a light subpath leaving x2 toward the floor
+            let pdf_origin = pmf * light.pdf_le(x2.p, x2.n, x1.p - x2.p).0;
+            let origin = Vertex::new(
+                VertexKind::Light(index),
+                x2.p,
+                x2.n,
+                Color::ones(),
+                pdf_origin,
+            );
+            let mut y1 = surface(&bdpt, x2.p, x1.p);
+            y1.pdf_fwd = bdpt.pdf(&origin, None, &y1);
+            let light_path = vec![origin.clone(), y1];
+
+            let weights = [
+                bdpt.mis_weight(&[], &camera_path, None, 0, 3),
+                //s == 1 only happens with a light subpath, its origin gets replaced by the sampled vertex
+                bdpt.mis_weight(&light_path, &camera_path[..2], Some(&origin), 1, 2),
+                bdpt.mis_weight(&light_path, &camera_path[..1], Some(&lens), 2, 1),
+            ];
+            for &w in weights.iter() {
+                assert!(w > 0.0 && w < 1.0);
+            }
+            assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_direct_strategy_keeps_its_full_weight() {
+        let (bdpt, eye) = scene();
+        let lens = Vertex::new(VertexKind::Camera, eye, Vec3::zero(), Color::ones(), 1.0);
+        let hit = surface(&bdpt, eye, Point3::new(0.0, 3.0, 0.0));
+        assert_eq!(bdpt.mis_weight(&[], &[lens, hit], None, 0, 2), 1.0);
+    }
+}
@@ -121,6 +121,20 @@ impl Hittable for BvhNode {
         hit_left || hit_right
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self._box.hit(r, t_min, t_max) {
+            return 1.0;
+        }
+        let tr = self.left.transmittance(r, t_min, t_max);
+        //a leaf with one object holds it on both sides
+        if tr <= 0.0
+            || Arc::as_ptr(&self.left) as *const u8 == Arc::as_ptr(&self.right) as *const u8
+        {
+            return tr;
+        }
+        tr * self.right.transmittance(r, t_min, t_max)
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = self._box;
         true
@@ -1,4 +1,5 @@
-use crate::rtweekend::{degrees_to_radians, random_double};
+use crate::ray::RayDifferential;
+use crate::rtweekend::{degrees_to_radians, random_double, PI};
 use crate::vec3::Point3;
 use crate::Ray;
 use crate::Vec3;
@@ -11,8 +12,11 @@ pub struct Camera {
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    //w: Vec3,
+    w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+    //area of the viewport one unit in front of the lens
+    film_area: f64,
     time0: f64,
     time1: f64,
 }
@@ -46,8 +50,10 @@ impl Camera {
             lower_left_corner: llc,
             u: u1,
             v: v1,
-            //w: w1,
+            w: w1,
             lens_radius: aperture / 2.0,
+            focus_dist,
+            film_area: viewport_width * viewport_height,
             time0: _time0,
             time1: _time1,
         }
@@ -58,8 +64,75 @@ impl Camera {
         let offset = self.u * rd.x + self.v * rd.y;
         Ray::new(
             self.origin + offset,
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+            self.direction(s, t, offset),
             random_double(self.time0, self.time1),
         )
     }
+
+    //ds and dt are the size of one pixel in s and t, the offset rays share the lens sample
+    pub fn get_ray_differential(&self, s: f64, t: f64, ds: f64, dt: f64) -> Ray {
+        let r = self.get_ray(s, t);
+        let offset = r.origin() - self.origin;
+        r.with_differentials(RayDifferential {
+            rx_origin: r.origin(),
+            rx_direction: self.direction(s + ds, t, offset),
+            ry_origin: r.origin(),
+            ry_direction: self.direction(s, t + dt, offset),
+        })
+    }
+
+    fn direction(&self, s: f64, t: f64, offset: Vec3) -> Vec3 {
+        self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset
+    }
+
+    pub fn random_lens_point(&self) -> Point3 {
+        let rd = Vec3::random_in_unit_disk() * self.lens_radius;
+        self.origin + self.u * rd.x + self.v * rd.y
+    }
+
+    //area of the lens, 1 for a pinhole so that importance and densities stay finite
+    pub fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
+        }
+    }
+
+    //film coordinates the way get_ray takes them of a ray leaving the lens at p_lens along dir
+    pub fn project(&self, p_lens: Point3, dir: Vec3) -> Option<(f64, f64)> {
+        let cos_theta = -Vec3::dot(dir.unit(), self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus = p_lens + dir.unit() * (self.focus_dist / cos_theta);
+        let d = focus - self.lower_left_corner;
+        Some((
+            Vec3::dot(d, self.horizontal) / self.horizontal.squared_length(),
+            Vec3::dot(d, self.vertical) / self.vertical.squared_length(),
+        ))
+    }
+
+    //importance of a ray leaving the lens along dir, film_scale is the film area in viewport units
+    pub fn we(&self, dir: Vec3, film_scale: f64) -> f64 {
+        let cos_theta = -Vec3::dot(dir.unit(), self.w);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = cos_theta * cos_theta;
+        1.0 / (self.film_area * film_scale * self.lens_area() * cos2 * cos2)
+    }
+
+    //solid angle density of the direction get_ray generates
+    pub fn pdf_we(&self, dir: Vec3, film_scale: f64) -> f64 {
+        let cos_theta = -Vec3::dot(dir.unit(), self.w);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.film_area * film_scale * cos_theta * cos_theta * cos_theta)
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        -self.w
+    }
 }
@@ -58,9 +58,13 @@ impl Hittable for ConstantMedium {
 
         rec.t = rec1.t + hit_distance / ray_length;
         rec.p = r.at(rec.t);
+        rec.p_object = rec.p;
 
-        rec.normal = Vec3::new(1.0, 0.0, 0.0);
+        //no surface here, a zero normal keeps cosines out of the estimators
+        rec.normal = Vec3::zero();
+        rec.clear_surface_derivatives();
         rec.front_face = true;
+        rec.light = None;
         rec.mat_ptr = self.phase_function.clone();
         true
     }
@@ -0,0 +1,315 @@
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::microfacet::{
+    fr_dielectric, gtr1_d, reflect, refract, sample_gtr1, schlick_weight, Ggx,
+};
+use crate::onb::Onb;
+use crate::rtweekend::{clamp, PI};
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::Color;
+use crate::{Ray, Vec3};
+use rand::Rng;
+use std::sync::Arc;
+
+//clearcoat is always fully glossy, sheen is half tinted, as in the Disney defaults
+const CLEARCOAT_ALPHA: f64 = 0.001;
+const SHEEN_TINT: f64 = 0.5;
+
+//Disney principled BSDF, scalar parameters are read from the first channel of their texture
+pub struct Disney {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>,
+}
+
+//parameters evaluated at one hit point
+struct DisneyParams {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    eta: f64,
+    ggx: Ggx,
+    //lobe weights: diffuse, specular, clearcoat, transmission
+    weights: [f64; 4],
+}
+
+impl Disney {
+    pub fn new(base_color: Color) -> Self {
+        Self::new_by_pointer(Arc::new(SolidColor::new(base_color)))
+    }
+
+    pub fn new_by_pointer(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: Arc::new(SolidColor::new_by_value(0.0)),
+            roughness: Arc::new(SolidColor::new_by_value(0.5)),
+            specular: Arc::new(SolidColor::new_by_value(0.5)),
+            specular_tint: Arc::new(SolidColor::new_by_value(0.0)),
+            sheen: Arc::new(SolidColor::new_by_value(0.0)),
+            clearcoat: Arc::new(SolidColor::new_by_value(0.0)),
+            transmission: Arc::new(SolidColor::new_by_value(0.0)),
+            ior: Arc::new(SolidColor::new_by_value(1.5)),
+            anisotropic: Arc::new(SolidColor::new_by_value(0.0)),
+        }
+    }
+
+    fn params(&self, rec: &HitRecord) -> DisneyParams {
+        let scalar = |t: &Arc<dyn Texture>| t.value_at(rec).x;
+        let metallic = clamp(scalar(&self.metallic), 0.0, 1.0);
+        let transmission = clamp(scalar(&self.transmission), 0.0, 1.0);
+        let clearcoat = clamp(scalar(&self.clearcoat), 0.0, 1.0);
+        let ior = scalar(&self.ior).max(1.0001);
+        let roughness = clamp(scalar(&self.roughness), 0.0, 1.0);
+
+        let diffuse_w = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_w = (1.0 - metallic) * transmission;
+        DisneyParams {
+            base_color: self.base_color.value_at(rec),
+            metallic,
+            roughness,
+            specular: scalar(&self.specular).max(0.0),
+            specular_tint: clamp(scalar(&self.specular_tint), 0.0, 1.0),
+            sheen: scalar(&self.sheen).max(0.0),
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            ggx: Ggx::from_roughness(roughness, scalar(&self.anisotropic)),
+            weights: [
+                diffuse_w,
+                1.0 - transmission_w,
+                0.25 * clearcoat,
+                transmission_w,
+            ],
+        }
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+fn tint(base: Color) -> Color {
+    let lum = 0.3 * base.x + 0.6 * base.y + 0.1 * base.z;
+    if lum > 0.0 {
+        base / lum
+    } else {
+        Color::ones()
+    }
+}
+
+impl DisneyParams {
+    fn lobe_pdfs(&self) -> [f64; 4] {
+        let total: f64 = self.weights.iter().sum();
+        let mut tmp = self.weights;
+        for w in tmp.iter_mut() {
+            *w /= total;
+        }
+        tmp
+    }
+
+    //half vector of a refraction, oriented to the upper hemisphere
+    fn refraction_half(&self, wo: Vec3, wi: Vec3) -> Vec3 {
+        let h = (wo + wi * self.eta).unit();
+        if h.z < 0.0 {
+            -h
+        } else {
+            h
+        }
+    }
+
+    fn eval(&self, wo: Vec3, wi: Vec3) -> Color {
+        let [diffuse_w, specular_w, clearcoat_w, transmission_w] = self.weights;
+        let mut f = Color::zero();
+
+        if wi.z > 0.0 {
+            let h = (wo + wi).unit();
+            let cos_d = Vec3::dot(wi, h);
+            let ctint = tint(self.base_color);
+
+            if diffuse_w > 0.0 {
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let fl = schlick_weight(wi.z);
+                let fv = schlick_weight(wo.z);
+                let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+                let csheen = lerp(Color::ones(), ctint, SHEEN_TINT);
+                f += (self.base_color * (fd / PI) + csheen * self.sheen * schlick_weight(cos_d))
+                    * diffuse_w;
+            }
+
+            let microfacet = self.ggx.d(h) * self.ggx.g(wo, wi) / (4.0 * wo.z * wi.z);
+            if specular_w > 0.0 {
+                let cspec0 = lerp(
+                    lerp(Color::ones(), ctint, self.specular_tint) * self.specular * 0.08,
+                    self.base_color,
+                    self.metallic,
+                );
+                let fresnel = lerp(cspec0, Color::ones(), schlick_weight(cos_d));
+                f += fresnel * microfacet * specular_w;
+            }
+
+            if clearcoat_w > 0.0 {
+                let fr = 0.04 + 0.96 * schlick_weight(cos_d);
+                let gr = Ggx::new(0.25, 0.25).g(wo, wi);
+                f += Color::ones() * clearcoat_w * gtr1_d(h, CLEARCOAT_ALPHA) * fr * gr
+                    / (4.0 * wo.z * wi.z);
+            }
+
+            if transmission_w > 0.0 {
+                f += Color::ones()
+                    * (transmission_w * fr_dielectric(Vec3::dot(wo, h), self.eta) * microfacet);
+            }
+        } else if wi.z < 0.0 && transmission_w > 0.0 {
+            let h = self.refraction_half(wo, wi);
+            let o_h = Vec3::dot(wo, h);
+            let i_h = Vec3::dot(wi, h);
+            if o_h > 0.0 && i_h < 0.0 {
+                let fresnel = fr_dielectric(o_h, self.eta);
+                let denom = o_h + self.eta * i_h;
+                let value =
+                    (1.0 - fresnel) * self.ggx.d(h) * self.ggx.g(wo, wi) * (i_h * o_h).abs()
+                        / (wi.z.abs() * wo.z * denom * denom);
+                f += self.base_color * (transmission_w * value);
+            }
+        }
+        f
+    }
+
+    fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = self.lobe_pdfs();
+
+        if wi.z > 0.0 {
+            let h = (wo + wi).unit();
+            let o_h = Vec3::dot(wo, h);
+            if o_h <= 0.0 {
+                return 0.0;
+            }
+            let reflect_pdf = self.ggx.pdf_h(wo, h) / (4.0 * o_h);
+            p_diffuse * wi.z / PI
+                + p_specular * reflect_pdf
+                + p_clearcoat * gtr1_d(h, CLEARCOAT_ALPHA) * h.z / (4.0 * o_h)
+                + p_transmission * fr_dielectric(o_h, self.eta) * reflect_pdf
+        } else if wi.z < 0.0 && p_transmission > 0.0 {
+            let h = self.refraction_half(wo, wi);
+            let o_h = Vec3::dot(wo, h);
+            let i_h = Vec3::dot(wi, h);
+            if o_h <= 0.0 || i_h >= 0.0 {
+                return 0.0;
+            }
+            let denom = o_h + self.eta * i_h;
+            let dwh_dwi = self.eta * self.eta * i_h.abs() / (denom * denom);
+            p_transmission * (1.0 - fr_dielectric(o_h, self.eta)) * self.ggx.pdf_h(wo, h) * dwh_dwi
+        } else {
+            0.0
+        }
+    }
+
+    fn sample(&self, wo: Vec3) -> Option<Vec3> {
+        let [p_diffuse, p_specular, p_clearcoat, _] = self.lobe_pdfs();
+        let mut rng = rand::thread_rng();
+        let u = rng.gen::<f64>();
+        let (u1, u2) = (rng.gen::<f64>(), rng.gen::<f64>());
+
+        let wi = if u < p_diffuse {
+            Vec3::random_cosine_direction()
+        } else if u < p_diffuse + p_specular {
+            reflect(wo, self.ggx.sample_h(wo, u1, u2))
+        } else if u < p_diffuse + p_specular + p_clearcoat {
+            reflect(wo, sample_gtr1(CLEARCOAT_ALPHA, u1, u2))
+        } else {
+            let h = self.ggx.sample_h(wo, u1, u2);
+            let fresnel = fr_dielectric(Vec3::dot(wo, h), self.eta);
+            match refract(wo, h, self.eta) {
+                Some(wt) if rng.gen::<f64>() >= fresnel => wt,
+                _ => reflect(wo, h),
+            }
+        };
+        if wi.z == 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl Material for Disney {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let params = self.params(rec);
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let wi = match params.sample(wo) {
+            Some(wi) => wi,
+            None => return false,
+        };
+        let pdf = params.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+        *attenuation = params.eval(wo, wi) * (wi.z.abs() / pdf);
+        *scattered = Ray::new(rec.p, uvw.local(wi), r_in.time());
+        true
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let params = self.params(rec);
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = uvw.to_local(scattered.direction().unit());
+        if wo.z <= 0.0 {
+            return Color::zero();
+        }
+        params.eval(wo, wi) * wi.z.abs()
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let params = self.params(rec);
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = uvw.to_local(scattered.direction().unit());
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        params.pdf(wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::uniform_sphere;
+
+    #[test]
+    fn test_pdf_integrates_to_at_most_one() {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        let mut disney = Disney::new(Color::new(0.8, 0.4, 0.2));
+        disney.transmission = Arc::new(SolidColor::new_by_value(0.5));
+        let params = disney.params(&rec);
+        let wo = Vec3::new(0.4, 0.2, 0.8).unit();
+
+        let mut rng = rand::thread_rng();
+        let n = 400_000;
+        let sum: f64 = (0..n)
+            .map(|_| params.pdf(wo, uniform_sphere(rng.gen::<f64>(), rng.gen::<f64>())) * 4.0 * PI)
+            .sum();
+        //reflections sampled below the horizon are lost, the rest has to be accounted for
+        let total = sum / n as f64;
+        assert!(total > 0.9 && total < 1.02, "pdf integrates to {}", total);
+    }
+}
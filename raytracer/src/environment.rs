@@ -0,0 +1,185 @@
+use crate::light::{Light, LightSample};
+use crate::mipmap::{MipFilter, WrapMode};
+use crate::rtweekend::{degrees_to_radians, INFINITY, PI};
+use crate::sampling::Distribution2D;
+use crate::sphere::Sphere;
+use crate::texture::{ImageTexture, Texture};
+use crate::vec3::{Color, Point3};
+use crate::{Ray, Vec3};
+use image::ImageResult;
+
+//EnvironmentMap
+//equirectangular image at infinity, laid out like the uv of a sphere, importance sampled by luminance
+pub struct EnvironmentMap {
+    texture: ImageTexture,
+    sin_theta: f64,
+    cos_theta: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    //.exr, .hdr or any 8/16 bit format the image crate reads
+    pub fn new(filename: &str, rotation: f64, intensity: f64) -> ImageResult<Self> {
+        Ok(Self::new_by_texture(
+            ImageTexture::new(filename)?,
+            rotation,
+            intensity,
+        ))
+    }
+
+    //rotation is in degrees around the y axis
+    pub fn new_by_texture(mut texture: ImageTexture, rotation: f64, intensity: f64) -> Self {
+        texture.wrap = WrapMode::Repeat;
+        texture.mip_filter = MipFilter::None;
+
+        let (width, height) = (texture.width() as usize, texture.height() as usize);
+        let mut func = Vec::with_capacity(width * height);
+        for j in 0..height {
+            //rows are squeezed toward the poles
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let t = texture.texel(i, j);
+                func.push((0.2126 * t[0] + 0.7152 * t[1] + 0.0722 * t[2]) * sin_theta);
+            }
+        }
+
+        let radians = degrees_to_radians(rotation);
+        Self {
+            texture,
+            sin_theta: radians.sin(),
+            cos_theta: radians.cos(),
+            intensity,
+            distribution: Distribution2D::new(&func, width, height),
+        }
+    }
+
+    fn to_local(&self, d: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * d.x - self.sin_theta * d.z,
+            d.y,
+            self.sin_theta * d.x + self.cos_theta * d.z,
+        )
+    }
+
+    fn to_world(&self, d: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * d.x + self.sin_theta * d.z,
+            d.y,
+            -self.sin_theta * d.x + self.cos_theta * d.z,
+        )
+    }
+
+    fn uv(&self, d: Vec3) -> (f64, f64) {
+        let (mut u, mut v) = (0.0, 0.0);
+        Sphere::get_sphere_uv(self.to_local(d).unit(), &mut u, &mut v);
+        (u, v)
+    }
+
+    fn radiance(&self, u: f64, v: f64) -> Color {
+        self.texture.value(u, v, Point3::zero()) * self.intensity
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample_li(&self, _p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        //the distribution runs over image rows from the top, texture v from the bottom
+        let ((u, row), pdf_uv) = self.distribution.sample_continuous(u1, u2);
+        let v = 1.0 - row;
+        let theta = v * PI;
+        let phi = u * 2.0 * PI;
+        let sin_theta = theta.sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let local = Vec3::new(-sin_theta * phi.cos(), -theta.cos(), sin_theta * phi.sin());
+        Some(LightSample {
+            wi: self.to_world(local),
+            li: self.radiance(u, v),
+            pdf: pdf_uv / (2.0 * PI * PI * sin_theta),
+            dist: INFINITY,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, wi: Vec3) -> f64 {
+        let (u, v) = self.uv(wi);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, 1.0 - v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        let (u, v) = self.uv(r.direction());
+        self.radiance(u, v)
+    }
+}
+
+//UniformEnvironment
+//constant background radiance, the light form of the old background color
+pub struct UniformEnvironment {
+    color: Color,
+}
+
+impl UniformEnvironment {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Light for UniformEnvironment {
+    fn sample_li(&self, _p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        Some(LightSample {
+            wi: Vec3::new(r * phi.cos(), r * phi.sin(), z),
+            li: self.color,
+            pdf: 1.0 / (4.0 * PI),
+            dist: INFINITY,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn le(&self, _r: &Ray) -> Color {
+        self.color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_samples_match_their_density() {
+        //a dim sky with one bright texel
+        let (width, height) = (8, 4);
+        let mut data = vec![0.1f32; width * height * 4];
+        for c in data[(width + 5) * 4..(width + 5) * 4 + 3].iter_mut() {
+            *c = 20.0;
+        }
+        let env = EnvironmentMap::new_by_texture(
+            ImageTexture::from_data(width as u32, height as u32, data),
+            30.0,
+            1.0,
+        );
+        let mut bright = 0;
+        for k in 0..200 {
+            let u1 = (k as f64 + 0.5) / 200.0;
+            let u2 = ((k * 37) % 200) as f64 / 200.0 + 0.002;
+            let ls = env.sample_li(Point3::zero(), u1, u2).unwrap();
+            let pdf = env.pdf_li(Point3::zero(), ls.wi);
+            assert!((ls.pdf - pdf).abs() < 1e-6 * pdf.max(1.0));
+            assert!((ls.li - env.le(&Ray::new(Point3::zero(), ls.wi, 0.0))).length() < 1e-6);
+            if ls.li.x > 1.0 {
+                bright += 1;
+            }
+        }
+        //the bright texel holds most of the power and gets most of the samples
+        assert!(bright > 100);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::aabb::AABB;
+use crate::color::luminance;
+use crate::hittable::HitRecord;
+use crate::material::{Isotropic, Material};
+use crate::rtweekend::{random_double, INFINITY};
+use crate::texture::Texture;
+use crate::vec3::Point3;
+use crate::{Color, Hittable, Ray, Vec3};
+use std::sync::Arc;
+
+//VoxelGrid
+//densities at the voxel centers of a box, blended trilinearly and zero outside the box
+pub struct VoxelGrid {
+    bounds: AABB,
+    resolution: (usize, usize, usize),
+    //x runs fastest, then y, then z
+    values: Vec<f64>,
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    pub fn new(bounds: AABB, resolution: (usize, usize, usize), values: Vec<f64>) -> Self {
+        assert!(
+            resolution.0 > 0 && resolution.1 > 0 && resolution.2 > 0,
+            "voxel grid needs at least one voxel along every axis"
+        );
+        assert_eq!(
+            values.len(),
+            resolution.0 * resolution.1 * resolution.2,
+            "voxel count doesn't match the resolution"
+        );
+        let max_value = values.iter().cloned().fold(0.0, f64::max);
+        Self {
+            bounds,
+            resolution,
+            values,
+            max_value,
+        }
+    }
+
+    //samples density at every voxel center, e.g. from a noise texture for nebulae
+    pub fn new_by_fn<F: Fn(Point3) -> f64>(
+        bounds: AABB,
+        resolution: (usize, usize, usize),
+        density: F,
+    ) -> Self {
+        let (nx, ny, nz) = resolution;
+        let size = bounds.max() - bounds.min();
+        let mut values = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let p = bounds.min()
+                        + Vec3::new(
+                            size.x * (i as f64 + 0.5) / nx as f64,
+                            size.y * (j as f64 + 0.5) / ny as f64,
+                            size.z * (k as f64 + 0.5) / nz as f64,
+                        );
+                    values.push(density(p).max(0.0));
+                }
+            }
+        }
+        Self::new(bounds, resolution, values)
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    pub fn density(&self, p: Point3) -> f64 {
+        let (nx, ny, nz) = self.resolution;
+        let size = self.bounds.max() - self.bounds.min();
+        let local = p - self.bounds.min();
+        let (x, y, z) = (local.x / size.x, local.y / size.y, local.z / size.z);
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) || !(0.0..=1.0).contains(&z) {
+            return 0.0;
+        }
+
+        //position in voxel centers, the outer half voxels hold the border value
+        let cell = |t: f64, n: usize| {
+            let c = (t * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (c as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), c - i as f64)
+        };
+        let (i0, i1, tx) = cell(x, nx);
+        let (j0, j1, ty) = cell(y, ny);
+        let (k0, k1, tz) = cell(z, nz);
+        let at = |i: usize, j: usize, k: usize| self.values[(k * ny + j) * nx + i];
+        let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+        let plane = |k: usize| {
+            lerp(
+                lerp(at(i0, j0, k), at(i1, j0, k), tx),
+                lerp(at(i0, j1, k), at(i1, j1, k), tx),
+                ty,
+            )
+        };
+        lerp(plane(k0), plane(k1), tz)
+    }
+}
+
+impl Texture for VoxelGrid {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+        Color::ones() * self.density(p)
+    }
+}
+
+//HeterogeneousMedium
+//density varies inside a convex boundary, collisions are found by delta tracking against max_density
+//and shadow rays get their transmittance from ratio tracking, densities above max_density are cut off
+pub struct HeterogeneousMedium {
+    boundary: Arc<dyn Hittable>,
+    phase_function: Arc<dyn Material>,
+    density: Arc<dyn Texture>,
+    max_density: f64,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        b: Arc<dyn Hittable>,
+        density: Arc<dyn Texture>,
+        max_density: f64,
+        c: Color,
+    ) -> Self {
+        Self {
+            boundary: b,
+            phase_function: Arc::new(Isotropic::new_by_color(c)),
+            density,
+            max_density,
+        }
+    }
+
+    pub fn new_by_grid(b: Arc<dyn Hittable>, grid: VoxelGrid, c: Color) -> Self {
+        let max_density = grid.max_value();
+        Self::new(b, Arc::new(grid), max_density, c)
+    }
+
+    fn density(&self, p: Point3) -> f64 {
+        luminance(self.density.value(0.0, 0.0, p)).clamp(0.0, self.max_density)
+    }
+
+    //part of r inside the boundary and between t_min and t_max
+    fn interval(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut rec1 = HitRecord::new();
+        let mut rec2 = HitRecord::new();
+
+        if !self.boundary.hit(r, -INFINITY, INFINITY, &mut rec1) {
+            return None;
+        }
+        if !self.boundary.hit(r, rec1.t + 0.0001, INFINITY, &mut rec2) {
+            return None;
+        }
+
+        let t0 = rec1.t.max(t_min).max(0.0);
+        let t1 = rec2.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+        Some((t0, t1))
+    }
+
+    //distance in ray parameter to the next tentative collision against the majorant
+    fn step(&self, ray_length: f64) -> f64 {
+        -(1.0 - random_double(0.0, 1.0)).ln() / (self.max_density * ray_length)
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.max_density <= 0.0 {
+            return false;
+        }
+        let (mut t, t1) = match self.interval(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        //tentative collisions are real with probability density over max_density
+        let ray_length = r.direction().length();
+        loop {
+            t += self.step(ray_length);
+            if t >= t1 {
+                return false;
+            }
+            if random_double(0.0, 1.0) * self.max_density < self.density(r.at(t)) {
+                break;
+            }
+        }
+
+        rec.t = t;
+        rec.p = r.at(rec.t);
+        rec.p_object = rec.p;
+
+        //no surface here, a zero normal keeps cosines out of the estimators
+        rec.normal = Vec3::zero();
+        rec.clear_surface_derivatives();
+        rec.front_face = true;
+        rec.light = None;
+        rec.mat_ptr = self.phase_function.clone();
+        true
+    }
+
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.max_density <= 0.0 {
+            return 1.0;
+        }
+        let (mut t, t1) = match self.interval(r, t_min, t_max) {
+            Some(interval) => interval,
+            None => return 1.0,
+        };
+
+        let ray_length = r.direction().length();
+        let mut tr = 1.0;
+        loop {
+            t += self.step(ray_length);
+            if t >= t1 {
+                return tr;
+            }
+            tr *= 1.0 - self.density(r.at(t)) / self.max_density;
+            //russian roulette once little light is left
+            if tr < 0.1 {
+                if random_double(0.0, 1.0) < 0.5 {
+                    return 0.0;
+                }
+                tr *= 2.0;
+            }
+        }
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.boundary.bounding_box(time0, time1, output_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn test_ratio_tracking_matches_beer_lambert() {
+        let sigma = 0.5;
+        let medium = HeterogeneousMedium::new(
+            Arc::new(Sphere::new(
+                Point3::zero(),
+                1.0,
+                Arc::new(Lambertian::new(Color::zero())),
+            )),
+            Arc::new(SolidColor::new_by_value(sigma)),
+            2.0,
+            Color::ones(),
+        );
+        //two units of the ray run inside the sphere
+        let r = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let n = 100_000;
+        let sum: f64 = (0..n).map(|_| medium.transmittance(r, 0.0, 10.0)).sum();
+        let expected = (-sigma * 2.0).exp();
+        assert!((sum / n as f64 - expected).abs() < 0.01);
+
+        //a shadow ray stopping halfway through sees half the optical depth
+        let sum: f64 = (0..n).map(|_| medium.transmittance(r, 0.0, 5.0)).sum();
+        assert!((sum / n as f64 - (-sigma).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_voxel_grid_interpolates_between_centers() {
+        let grid = VoxelGrid::new(
+            AABB::new(Point3::zero(), Point3::new(2.0, 1.0, 1.0)),
+            (2, 1, 1),
+            vec![1.0, 3.0],
+        );
+        assert_eq!(grid.max_value(), 3.0);
+        assert!((grid.density(Point3::new(0.2, 0.5, 0.5)) - 1.0).abs() < 1e-12);
+        assert!((grid.density(Point3::new(1.0, 0.5, 0.5)) - 2.0).abs() < 1e-12);
+        assert!((grid.density(Point3::new(1.8, 0.5, 0.5)) - 3.0).abs() < 1e-12);
+        assert_eq!(grid.density(Point3::new(2.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one voxel")]
+    fn test_voxel_grid_rejects_zero_resolution() {
+        VoxelGrid::new(
+            AABB::new(Point3::zero(), Point3::ones()),
+            (4, 0, 4),
+            Vec::new(),
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::aabb::AABB;
 use crate::material::{Lambertian, Material};
+use crate::ray::RayDifferential;
 use crate::rtweekend::{degrees_to_radians, fmax, fmin, INFINITY};
 use crate::Ray;
 use crate::Vec3;
@@ -9,27 +10,57 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    //hit point in the frame of the primitive, before instance transforms
+    pub p_object: Point3,
     pub normal: Vec3,
     pub front_face: bool,
     pub t: f64,
     pub mat_ptr: Arc<dyn Material>,
     pub u: f64,
     pub v: f64,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    //derivatives of the outward normal
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+    //footprint of a pixel on the surface, zero when the ray carries no differentials
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+    //index in the light list when the surface is a registered emitter
+    pub light: Option<usize>,
 }
 
 impl HitRecord {
     pub fn new() -> HitRecord {
         HitRecord {
             p: Point3::zero(),
+            p_object: Point3::zero(),
             normal: Vec3::zero(),
             front_face: false,
             t: 0.0,
             u: 0.0,
             v: 0.0,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            dndu: Vec3::zero(),
+            dndv: Vec3::zero(),
+            dpdx: Vec3::zero(),
+            dpdy: Vec3::zero(),
+            dudx: 0.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.0,
+            light: None,
             mat_ptr: Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
         }
     }
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &mut Vec3) {
+        //every primitive hit comes through here, emitters tag the record again afterwards
+        self.light = None;
         self.front_face = Vec3::dot(r.direction(), *outward_normal) < 0.0;
         self.normal = if self.front_face {
             *outward_normal
@@ -37,6 +68,134 @@ impl HitRecord {
             -(*outward_normal)
         };
     }
+
+    //medium hits have no parametrization, nothing of the previous surface hit may carry over
+    pub fn clear_surface_derivatives(&mut self) {
+        self.u = 0.0;
+        self.v = 0.0;
+        self.dpdu = Vec3::zero();
+        self.dpdv = Vec3::zero();
+        self.dndu = Vec3::zero();
+        self.dndv = Vec3::zero();
+        self.dpdx = Vec3::zero();
+        self.dpdy = Vec3::zero();
+        self.dudx = 0.0;
+        self.dvdx = 0.0;
+        self.dudy = 0.0;
+        self.dvdy = 0.0;
+    }
+
+    //intersects the offset rays with the tangent plane and projects the offsets onto dpdu and dpdv
+    pub fn compute_differentials(&mut self, r: &Ray) {
+        self.dpdx = Vec3::zero();
+        self.dpdy = Vec3::zero();
+        self.dudx = 0.0;
+        self.dvdx = 0.0;
+        self.dudy = 0.0;
+        self.dvdy = 0.0;
+        let diff = match r.differentials() {
+            Some(diff) => diff,
+            None => return,
+        };
+
+        let n = self.normal;
+        let d = Vec3::dot(n, self.p);
+        let tx = (d - Vec3::dot(n, diff.rx_origin)) / Vec3::dot(n, diff.rx_direction);
+        let ty = (d - Vec3::dot(n, diff.ry_origin)) / Vec3::dot(n, diff.ry_direction);
+        if !tx.is_finite() || !ty.is_finite() {
+            return;
+        }
+        self.dpdx = diff.rx_origin + diff.rx_direction * tx - self.p;
+        self.dpdy = diff.ry_origin + diff.ry_direction * ty - self.p;
+
+        //least squares solution of dpdx = dpdu * dudx + dpdv * dvdx
+        let ata00 = Vec3::dot(self.dpdu, self.dpdu);
+        let ata01 = Vec3::dot(self.dpdu, self.dpdv);
+        let ata11 = Vec3::dot(self.dpdv, self.dpdv);
+        let inv_det = 1.0 / (ata00 * ata11 - ata01 * ata01);
+        if !inv_det.is_finite() {
+            return;
+        }
+        let solve = |dp: Vec3| {
+            let b0 = Vec3::dot(self.dpdu, dp);
+            let b1 = Vec3::dot(self.dpdv, dp);
+            (
+                (ata11 * b0 - ata01 * b1) * inv_det,
+                (ata00 * b1 - ata01 * b0) * inv_det,
+            )
+        };
+        let (dudx, dvdx) = solve(self.dpdx);
+        let (dudy, dvdy) = solve(self.dpdy);
+        self.dudx = dudx;
+        self.dvdx = dvdx;
+        self.dudy = dudy;
+        self.dvdy = dvdy;
+    }
+
+    //derivatives of the normal facing the ray along the screen axes
+    fn normal_differentials(&self) -> (Vec3, Vec3) {
+        let sign = if self.front_face { 1.0 } else { -1.0 };
+        (
+            (self.dndu * self.dudx + self.dndv * self.dvdx) * sign,
+            (self.dndu * self.dudy + self.dndv * self.dvdy) * sign,
+        )
+    }
+
+    //differentials of the mirror reflection of r_in into wi
+    pub fn reflected_differentials(&self, r_in: &Ray, wi: Vec3) -> Option<RayDifferential> {
+        let diff = r_in.differentials()?;
+        let n = self.normal;
+        let wo = -r_in.direction().unit();
+        let wi = wi.unit();
+        let (dndx, dndy) = self.normal_differentials();
+        let offset = |dp: Vec3, rd: Vec3, dndx: Vec3| {
+            let dwodx = -rd.unit() - wo;
+            let ddndx = Vec3::dot(dwodx, n) + Vec3::dot(wo, dndx);
+            (
+                self.p + dp,
+                wi - dwodx + (dndx * Vec3::dot(wo, n) + n * ddndx) * 2.0,
+            )
+        };
+        let (rx_origin, rx_direction) = offset(self.dpdx, diff.rx_direction, dndx);
+        let (ry_origin, ry_direction) = offset(self.dpdy, diff.ry_direction, dndy);
+        Some(RayDifferential {
+            rx_origin,
+            rx_direction,
+            ry_origin,
+            ry_direction,
+        })
+    }
+
+    //differentials of the refraction of r_in into wi, eta is the ratio of the incident to the transmitted ior
+    pub fn refracted_differentials(
+        &self,
+        r_in: &Ray,
+        wi: Vec3,
+        eta: f64,
+    ) -> Option<RayDifferential> {
+        let diff = r_in.differentials()?;
+        let n = self.normal;
+        let wo = -r_in.direction().unit();
+        let wi = wi.unit();
+        let (dndx, dndy) = self.normal_differentials();
+        let cos_o = Vec3::dot(wo, n);
+        let cos_i = Vec3::dot(wi, n).abs();
+        let mu = eta * cos_o - cos_i;
+        let offset = |dp: Vec3, rd: Vec3, dndx: Vec3| {
+            let dwodx = -rd.unit() - wo;
+            let ddndx = Vec3::dot(dwodx, n) + Vec3::dot(wo, dndx);
+            let dmudx = (eta - eta * eta * cos_o / cos_i) * ddndx;
+            (self.p + dp, wi - dwodx * eta + dndx * mu + n * dmudx)
+        };
+        let (rx_origin, rx_direction) = offset(self.dpdx, diff.rx_direction, dndx);
+        let (ry_origin, ry_direction) = offset(self.dpdy, diff.ry_direction, dndy);
+        Some(RayDifferential {
+            rx_origin,
+            rx_direction,
+            ry_origin,
+            ry_direction,
+        })
+    }
 }
 
 impl Default for HitRecord {
@@ -48,6 +207,16 @@ impl Default for HitRecord {
 pub trait Hittable {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool;
+
+    //fraction of light getting through along r between t_min and t_max, only media let part of it through
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut rec = HitRecord::new();
+        if self.hit(r, t_min, t_max, &mut rec) {
+            0.0
+        } else {
+            1.0
+        }
+    }
 }
 
 //Translate
@@ -78,6 +247,11 @@ impl Hittable for Translate {
         true
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        let moved_r = Ray::new(r.origin() - self.offset, r.direction(), r.time());
+        self.ptr.transmittance(moved_r, t_min, t_max)
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
         if !self.ptr.bounding_box(time0, time1, output_box) {
             return false;
@@ -142,10 +316,8 @@ impl RotateY {
 
         tmp
     }
-}
 
-impl Hittable for RotateY {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn rotate(&self, r: Ray) -> Ray {
         let mut origin = r.origin();
         let mut direction = r.direction();
 
@@ -155,7 +327,21 @@ impl Hittable for RotateY {
         direction.x = self.cos_theta * r.direction().x - self.sin_theta * r.direction().z;
         direction.z = self.sin_theta * r.direction().x + self.cos_theta * r.direction().z;
 
-        let rotated_r = Ray::new(origin, direction, r.time());
+        Ray::new(origin, direction, r.time())
+    }
+
+    fn rotate_back(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let rotated_r = self.rotate(r);
 
         if !self.ptr.hit(rotated_r, t_min, t_max, rec) {
             return false;
@@ -172,12 +358,89 @@ impl Hittable for RotateY {
 
         rec.p = p_;
         rec.set_face_normal(&rotated_r, &mut normal);
+        rec.dpdu = self.rotate_back(rec.dpdu);
+        rec.dpdv = self.rotate_back(rec.dpdv);
+        rec.dndu = self.rotate_back(rec.dndu);
+        rec.dndv = self.rotate_back(rec.dndv);
 
         true
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        self.ptr.transmittance(self.rotate(r), t_min, t_max)
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64, output_box: &mut AABB) -> bool {
         *output_box = self.bbox;
         self.hasbox
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrect::XZRect;
+    use crate::constant_medium::ConstantMedium;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_differentials_project_the_pixel_footprint_onto_uv() {
+        let floor = XZRect::new(
+            0.0,
+            2.0,
+            0.0,
+            4.0,
+            0.0,
+            Arc::new(Lambertian::new(Color::ones())),
+        );
+        let origin = Point3::new(1.0, 1.0, 1.0);
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let r = Ray::new(origin, down, 0.0).with_differentials(RayDifferential {
+            rx_origin: origin + Vec3::new(0.02, 0.0, 0.0),
+            rx_direction: down,
+            ry_origin: origin,
+            ry_direction: Vec3::new(0.0, -1.0, 0.04),
+        });
+        let mut rec = HitRecord::new();
+        assert!(floor.hit(r, 0.001, INFINITY, &mut rec));
+        rec.compute_differentials(&r);
+        assert!((rec.dpdx - Vec3::new(0.02, 0.0, 0.0)).length() < 1e-12);
+        assert!((rec.dpdy - Vec3::new(0.0, 0.0, 0.04)).length() < 1e-12);
+        assert!((rec.dudx - 0.01).abs() < 1e-12 && rec.dvdx.abs() < 1e-12);
+        assert!(rec.dudy.abs() < 1e-12 && (rec.dvdy - 0.01).abs() < 1e-12);
+
+        //halving the footprint halves the derivatives
+        let half = r.scale_differentials(0.5);
+        rec.compute_differentials(&half);
+        assert!((rec.dudx - 0.005).abs() < 1e-12);
+
+        //rays without differentials leave nothing behind
+        rec.compute_differentials(&Ray::new(origin, down, 0.0));
+        assert_eq!((rec.dudx, rec.dvdy), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_flat_hits_clear_the_normal_derivatives_of_the_last_hit() {
+        let gray: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let sphere = Sphere::new(Point3::new(0.0, 3.0, 0.0), 1.0, gray.clone());
+        let floor = XZRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, gray.clone());
+        let fog = ConstantMedium::new_by_color(
+            Arc::new(Sphere::new(Point3::new(0.0, 3.0, 0.0), 1.0, gray)),
+            1e9,
+            Color::ones(),
+        );
+        let down = Ray::new(Point3::new(0.2, 5.0, 0.1), Vec3::new(0.0, -1.0, 0.0), 0.0);
+
+        let mut rec = HitRecord::new();
+        assert!(sphere.hit(down, 0.001, INFINITY, &mut rec));
+        assert!(rec.dndu.length() > 0.0 && rec.dndv.length() > 0.0);
+        assert!(floor.hit(down, 0.001, INFINITY, &mut rec));
+        assert_eq!((rec.dndu, rec.dndv), (Vec3::zero(), Vec3::zero()));
+
+        assert!(sphere.hit(down, 0.001, INFINITY, &mut rec));
+        assert!(fog.hit(down, 0.001, INFINITY, &mut rec));
+        assert_eq!((rec.u, rec.v), (0.0, 0.0));
+        assert_eq!((rec.dpdu, rec.dpdv), (Vec3::zero(), Vec3::zero()));
+        assert_eq!((rec.dndu, rec.dndv), (Vec3::zero(), Vec3::zero()));
+    }
+}
@@ -52,6 +52,17 @@ impl crate::hittable::Hittable for HittableList {
         hit_anything
     }
 
+    fn transmittance(&self, r: Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut tr = 1.0;
+        for object in &self.objects {
+            tr *= object.transmittance(r, t_min, t_max);
+            if tr <= 0.0 {
+                return 0.0;
+            }
+        }
+        tr
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
         if self.objects.is_empty() {
             return false;
@@ -0,0 +1,251 @@
+use crate::rtweekend::PI;
+use crate::Vec3;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+//IesProfile
+//IESNA LM-63 candela distribution with type C photometry, vertical angles run from the nadir
+pub struct IesProfile {
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    //one row of vertical samples per horizontal angle
+    candela: Vec<Vec<f64>>,
+    flux: f64,
+    hemisphere_flux: f64,
+}
+
+impl IesProfile {
+    pub fn load(filename: &str) -> Result<Self> {
+        Self::parse(&fs::read_to_string(filename)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        let mut tilt = None;
+        for line in &mut lines {
+            let line = line.trim();
+            if let Some(rest) = line.strip_prefix("TILT=") {
+                tilt = Some(rest.trim().to_string());
+                break;
+            }
+        }
+        let tilt = tilt.ok_or_else(|| invalid("missing TILT line"))?;
+
+        let mut numbers = Vec::new();
+        for token in lines.flat_map(|l| l.split(|c: char| c.is_whitespace() || c == ',')) {
+            if token.is_empty() {
+                continue;
+            }
+            numbers.push(
+                token
+                    .parse::<f64>()
+                    .map_err(|_| invalid("malformed number"))?,
+            );
+        }
+        let mut next = numbers.into_iter();
+        let mut take = |n: usize| -> Result<Vec<f64>> {
+            let values: Vec<f64> = next.by_ref().take(n).collect();
+            if values.len() < n {
+                return Err(invalid("truncated photometric data"));
+            }
+            Ok(values)
+        };
+
+        //lamp to luminaire geometry, then angle and factor pairs, tilt doesn't change the shape we use
+        if tilt == "INCLUDE" {
+            let header = take(2)?;
+            take(2 * header[1] as usize)?;
+        }
+
+        let header = take(10)?;
+        let multiplier = header[2];
+        let n_vertical = header[3] as usize;
+        let n_horizontal = header[4] as usize;
+        if header[5] as i32 != 1 {
+            return Err(invalid("only type C photometry is supported"));
+        }
+        if n_vertical == 0 || n_horizontal == 0 {
+            return Err(invalid("empty candela table"));
+        }
+        //ballast factor, future use and input watts
+        take(3)?;
+
+        let vertical = take(n_vertical)?;
+        let horizontal = take(n_horizontal)?;
+        let mut candela = Vec::with_capacity(n_horizontal);
+        for _ in 0..n_horizontal {
+            candela.push(
+                take(n_vertical)?
+                    .into_iter()
+                    .map(|c| (c * multiplier).max(0.0))
+                    .collect(),
+            );
+        }
+
+        let mut profile = Self {
+            vertical,
+            horizontal,
+            candela,
+            flux: 0.0,
+            hemisphere_flux: 0.0,
+        };
+        let (flux, hemisphere_flux) = profile.integrate();
+        if flux <= 0.0 {
+            return Err(invalid("profile emits no light"));
+        }
+        profile.flux = flux;
+        profile.hemisphere_flux = hemisphere_flux;
+        Ok(profile)
+    }
+
+    //total luminous flux in lumens, and the part emitted below the horizontal plane
+    fn integrate(&self) -> (f64, f64) {
+        let (n_theta, n_phi) = (180, 72);
+        let d_theta = PI / n_theta as f64;
+        let d_phi = 2.0 * PI / n_phi as f64;
+        let (mut flux, mut hemisphere_flux) = (0.0, 0.0);
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            let mut ring = 0.0;
+            for j in 0..n_phi {
+                ring += self.candela((j as f64 + 0.5) * d_phi, theta);
+            }
+            let ring = ring * theta.sin() * d_theta * d_phi;
+            flux += ring;
+            if theta < PI / 2.0 {
+                hemisphere_flux += ring;
+            }
+        }
+        (flux, hemisphere_flux)
+    }
+
+    #[cfg(test)]
+    pub fn flux(&self) -> f64 {
+        self.flux
+    }
+
+    //candela at polar angle theta from the nadir and azimuth phi, both in radians
+    pub fn candela(&self, phi: f64, theta: f64) -> f64 {
+        let gamma = theta.to_degrees();
+        let mut h = phi.to_degrees().rem_euclid(360.0);
+        //the last horizontal angle tells which symmetry the table relies on
+        let last = *self.horizontal.last().unwrap();
+        if last <= 0.0 {
+            h = 0.0;
+        } else if last <= 90.0 {
+            h %= 180.0;
+            if h > 90.0 {
+                h = 180.0 - h;
+            }
+        } else if last <= 180.0 && h > 180.0 {
+            h = 360.0 - h;
+        }
+
+        let (v0, v1, tv) = match Self::bracket(&self.vertical, gamma) {
+            Some(b) => b,
+            None => return 0.0,
+        };
+        let (h0, h1, th) = Self::bracket(&self.horizontal, h).unwrap_or((0, 0, 0.0));
+        let row = |k: usize| self.candela[k][v0] * (1.0 - tv) + self.candela[k][v1] * tv;
+        row(h0) * (1.0 - th) + row(h1) * th
+    }
+
+    //neighbouring samples around x and the blend between them, None outside the table
+    fn bracket(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+        let n = angles.len();
+        if n == 1 || (x - angles[0]).abs() < 1e-9 {
+            return Some((0, 0, 0.0));
+        }
+        if x < angles[0] || x > angles[n - 1] {
+            return None;
+        }
+        let i = angles.iter().rposition(|&a| a <= x).unwrap().min(n - 2);
+        let span = angles[i + 1] - angles[i];
+        let t = if span > 0.0 {
+            (x - angles[i]) / span
+        } else {
+            0.0
+        };
+        Some((i, i + 1, t))
+    }
+
+    //local has the nadir along +z, x marks the 0 degree plane
+    fn angles(local: Vec3) -> (f64, f64) {
+        let local = local.unit();
+        let theta = local.z.clamp(-1.0, 1.0).acos();
+        (local.y.atan2(local.x), theta)
+    }
+
+    //multiplier on a point light's intensity, averages 1 over the sphere so the flux is kept
+    pub fn intensity_scale(&self, local: Vec3) -> f64 {
+        let (phi, theta) = Self::angles(local);
+        self.candela(phi, theta) * 4.0 * PI / self.flux
+    }
+
+    //multiplier on an emitter's radiance, keeps the power a lambertian surface sends into the hemisphere
+    pub fn radiance_scale(&self, local: Vec3) -> f64 {
+        let (phi, theta) = Self::angles(local);
+        let cos_theta = theta.cos();
+        if cos_theta <= 0.0 || self.hemisphere_flux <= 0.0 {
+            return 0.0;
+        }
+        self.candela(phi, theta) / cos_theta.max(1e-2) * PI / self.hemisphere_flux
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a single plane table, candela given per vertical angle in degrees
+    fn profile(vertical: &[f64], candela: impl Fn(f64) -> f64) -> String {
+        let angles: Vec<String> = vertical.iter().map(|v| v.to_string()).collect();
+        let values: Vec<String> = vertical.iter().map(|&v| candela(v).to_string()).collect();
+        format!(
+            "IESNA:LM-63-2002\n[TEST] test\nTILT=NONE\n1 -1 1 {} 1 1 2 0 0 0\n1 1 100\n{}\n0\n{}\n",
+            vertical.len(),
+            angles.join(" "),
+            values.join(" ")
+        )
+    }
+
+    #[test]
+    fn test_isotropic_profile_integrates_to_its_flux() {
+        let vertical: Vec<f64> = (0..=36).map(|i| i as f64 * 5.0).collect();
+        let ies = IesProfile::parse(&profile(&vertical, |_| 100.0)).unwrap();
+        assert!((ies.flux() / (400.0 * PI) - 1.0).abs() < 1e-3);
+        for &d in [Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 2.0, -3.0)].iter() {
+            assert!((ies.intensity_scale(d) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_lambertian_profile_keeps_emitter_radiance() {
+        let vertical: Vec<f64> = (0..=90).map(|i| i as f64).collect();
+        let ies = IesProfile::parse(&profile(&vertical, |v| 50.0 * v.to_radians().cos())).unwrap();
+        assert!((ies.flux() / (50.0 * PI) - 1.0).abs() < 1e-3);
+        for &theta in [0.0f64, 30.0, 60.0, 80.0].iter() {
+            let t = theta.to_radians();
+            let scale = ies.radiance_scale(Vec3::new(t.sin(), 0.0, t.cos()));
+            assert!((scale - 1.0).abs() < 1e-2);
+        }
+        //nothing leaves above the horizontal plane
+        assert_eq!(ies.candela(0.0, 2.0), 0.0);
+        assert_eq!(ies.radiance_scale(Vec3::new(0.0, 0.0, -1.0)), 0.0);
+    }
+
+    #[test]
+    fn test_malformed_profiles_are_rejected() {
+        assert!(IesProfile::parse("IESNA:LM-63-2002\n1 -1 1").is_err());
+        let vertical = [0.0, 90.0];
+        let type_b = profile(&vertical, |_| 1.0).replace("1 -1 1 2 1 1", "1 -1 1 2 1 2");
+        assert!(IesProfile::parse(&type_b).is_err());
+        assert!(IesProfile::parse(&profile(&vertical, |_| 0.0)).is_err());
+        let truncated = profile(&vertical, |_| 1.0);
+        assert!(IesProfile::parse(truncated.trim_end().trim_end_matches('1')).is_err());
+    }
+}
@@ -0,0 +1,645 @@
+use crate::bdpt::Bdpt;
+use crate::camera::Camera;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::LightList;
+use crate::onb::Onb;
+use crate::rtweekend::INFINITY;
+use crate::spectrum;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::vec3::Color;
+use crate::{emitted, escaped, ray_color, ray_color_spectral, sample_lights};
+use crate::{HittableList, Ray, Vec3};
+use rand::Rng;
+use std::ops::{Add, AddAssign, Div, Mul};
+use std::sync::Arc;
+
+//Integrator
+//turns a camera ray into radiance, integrators that trace from the lights splat onto film positions (s, t)
+pub trait Integrator: Send + Sync {
+    fn li(&self, r: Ray, splats: &mut Vec<(f64, f64, Color)>) -> Color;
+}
+
+//names the cli and the INTEGRATOR variable accept, sppm renders whole passes and is handled by main
+pub const INTEGRATOR_NAMES: [&str; 12] = [
+    "path",
+    "recursive",
+    "bdpt",
+    "sppm",
+    "ao",
+    "direct",
+    "normals",
+    "uv",
+    "depth",
+    "barycentrics",
+    "material",
+    "hit",
+];
+
+//what main passes on to whichever integrator gets picked
+pub struct IntegratorOptions {
+    pub max_depth: usize,
+    //image width and height in pixels
+    pub film: (f64, f64),
+    pub spectral: bool,
+    pub rr_depth: usize,
+    pub max_indirect: Option<f64>,
+}
+
+pub fn by_name(
+    name: &str,
+    camera: &Camera,
+    world: &Arc<HittableList>,
+    lights: &Arc<LightList>,
+    options: &IntegratorOptions,
+) -> Option<Arc<dyn Integrator>> {
+    let world = world.clone();
+    let lights = lights.clone();
+    let max_depth = options.max_depth;
+    let debug =
+        |view| Some(Arc::new(DebugIntegrator::new(world.clone(), view)) as Arc<dyn Integrator>);
+    match name {
+        "path" => Some(Arc::new(IterativePathIntegrator {
+            rr_depth: options.rr_depth,
+            max_indirect: options.max_indirect,
+            ..IterativePathIntegrator::new(world, lights, max_depth, options.spectral)
+        })),
+        "recursive" => Some(Arc::new(PathIntegrator::new(
+            world,
+            lights,
+            max_depth,
+            options.spectral,
+        ))),
+        "bdpt" => Some(Arc::new(Bdpt::new(
+            camera.clone(),
+            world,
+            lights,
+            max_depth,
+            options.film,
+        ))),
+        "ao" => Some(Arc::new(AmbientOcclusion::new(world, 5.0))),
+        "direct" => Some(Arc::new(DirectLighting::new(world, lights, max_depth))),
+        "normals" => debug(DebugView::Normals),
+        "uv" => debug(DebugView::Uv),
+        "depth" => debug(DebugView::Depth),
+        "barycentrics" => debug(DebugView::Barycentrics),
+        "material" => debug(DebugView::MaterialId),
+        "hit" => debug(DebugView::HitMiss),
+        _ => None,
+    }
+}
+
+//emission along the bsdf sample and a shadow ray, weighted against each other, for a path that stops at rec
+pub fn estimate_direct(
+    r: Ray,
+    rec: &HitRecord,
+    (attenuation, scattered): (Color, Ray),
+    bsdf_pdf: f64,
+    world: &Arc<HittableList>,
+    lights: &Arc<LightList>,
+) -> Color {
+    let mut light_rec = HitRecord::new();
+    let along_bsdf = if world.hit(scattered, 0.001, INFINITY, &mut light_rec) {
+        emitted(scattered, &light_rec, lights, bsdf_pdf)
+    } else {
+        escaped(scattered, lights, bsdf_pdf)
+    };
+    sample_lights(r, rec, lights, world) + attenuation * along_bsdf
+}
+
+//PathIntegrator
+//the recursive ray_color, or ray_color_spectral with one hero wavelength sample per ray
+//reference only: no russian roulette or clamping, renders should use the iterative "path" integrator
+pub struct PathIntegrator {
+    world: Arc<HittableList>,
+    lights: Arc<LightList>,
+    max_depth: usize,
+    spectral: bool,
+}
+
+impl PathIntegrator {
+    pub fn new(
+        world: Arc<HittableList>,
+        lights: Arc<LightList>,
+        max_depth: usize,
+        spectral: bool,
+    ) -> Self {
+        Self {
+            world,
+            lights,
+            max_depth,
+            spectral,
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(&self, r: Ray, _splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        if self.spectral {
+            let wl = SampledWavelengths::sample_uniform(rand::thread_rng().gen::<f64>());
+            ray_color_spectral(
+                r.with_wavelengths(wl),
+                &self.lights,
+                &self.world,
+                self.max_depth as i32,
+                0.0,
+            )
+            .to_rgb(&wl)
+        } else {
+            ray_color(r, &self.lights, &self.world, self.max_depth as i32, 0.0)
+        }
+    }
+}
+
+//IterativePathIntegrator
+//ray_color as a loop carrying the path throughput, past rr_depth bounces dim paths end early and the survivors are boosted
+//max_indirect caps the largest channel of what a sample gathers beyond direct lighting, trading bias for fewer fireflies
+pub struct IterativePathIntegrator {
+    world: Arc<HittableList>,
+    lights: Arc<LightList>,
+    max_depth: usize,
+    spectral: bool,
+    pub rr_depth: usize,
+    pub max_indirect: Option<f64>,
+}
+
+impl IterativePathIntegrator {
+    pub fn new(
+        world: Arc<HittableList>,
+        lights: Arc<LightList>,
+        max_depth: usize,
+        spectral: bool,
+    ) -> Self {
+        Self {
+            world,
+            lights,
+            max_depth,
+            spectral,
+            rr_depth: 3,
+            max_indirect: None,
+        }
+    }
+
+    //probability the path goes on after depth bounces with throughput whose largest channel is brightest
+    fn survival(&self, depth: usize, brightest: f64) -> f64 {
+        if depth < self.rr_depth {
+            1.0
+        } else {
+            brightest.min(1.0)
+        }
+    }
+
+    fn clamp_indirect(&self, brightest: f64) -> f64 {
+        match self.max_indirect {
+            Some(limit) if brightest > limit => limit / brightest,
+            _ => 1.0,
+        }
+    }
+
+    //direct is the light at the first hit and what its bsdf sample runs into, the rest is indirect
+    fn walk<T: Throughput>(&self, mut r: Ray) -> T {
+        let mut rng = rand::thread_rng();
+        let mut direct = T::zero();
+        let mut indirect = T::zero();
+        let mut beta = T::one();
+        let mut bsdf_pdf = 0.0;
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+            let hit = self.world.hit(r, 0.001, INFINITY, &mut rec);
+            let found = if hit {
+                rec.compute_differentials(&r);
+                emitted(r, &rec, &self.lights, bsdf_pdf)
+            } else {
+                escaped(r, &self.lights, bsdf_pdf)
+            };
+            let found = beta * T::radiance(found, &r);
+            if depth < 2 {
+                direct += found;
+            } else {
+                indirect += found;
+            }
+            if !hit {
+                break;
+            }
+
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !rec
+                .mat_ptr
+                .scatter(r, &rec, &mut attenuation, &mut scattered)
+            {
+                break;
+            }
+            let sampled = beta * T::radiance(sample_lights(r, &rec, &self.lights, &self.world), &r);
+            if depth == 0 {
+                direct += sampled;
+            } else {
+                indirect += sampled;
+            }
+            bsdf_pdf = rec.mat_ptr.scattering_pdf(r, &rec, scattered);
+            beta = beta * T::bounce(attenuation, &r, &mut scattered);
+
+            let survive = self.survival(depth + 1, beta.brightest());
+            if survive <= 0.0 || rng.gen::<f64>() >= survive {
+                break;
+            }
+            beta = beta / survive;
+            r = scattered;
+        }
+        direct + indirect * self.clamp_indirect(indirect.brightest())
+    }
+}
+
+//Throughput
+//what a path carries, rgb or the spectrum of the ray's sampled wavelengths, so both modes share one walk
+trait Throughput:
+    Copy
+    + Add<Output = Self>
+    + AddAssign
+    + Mul<Output = Self>
+    + Mul<f64, Output = Self>
+    + Div<f64, Output = Self>
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+    //rgb radiance arriving along r
+    fn radiance(c: Color, r: &Ray) -> Self;
+    //attenuation of scattering r into scattered
+    fn bounce(attenuation: Color, r: &Ray, scattered: &mut Ray) -> Self;
+    fn brightest(&self) -> f64;
+}
+
+impl Throughput for Color {
+    fn zero() -> Self {
+        Color::zero()
+    }
+
+    fn one() -> Self {
+        Color::ones()
+    }
+
+    fn radiance(c: Color, _r: &Ray) -> Self {
+        c
+    }
+
+    fn bounce(attenuation: Color, _r: &Ray, _scattered: &mut Ray) -> Self {
+        attenuation
+    }
+
+    fn brightest(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+}
+
+impl Throughput for SampledSpectrum {
+    fn zero() -> Self {
+        SampledSpectrum::zero()
+    }
+
+    fn one() -> Self {
+        SampledSpectrum::new(1.0)
+    }
+
+    fn radiance(c: Color, r: &Ray) -> Self {
+        SampledSpectrum::from_rgb(c, &wavelengths(r))
+    }
+
+    fn bounce(attenuation: Color, r: &Ray, scattered: &mut Ray) -> Self {
+        spectrum::bounce_throughput(attenuation, &wavelengths(r), scattered)
+    }
+
+    fn brightest(&self) -> f64 {
+        self.max_value()
+    }
+}
+
+fn wavelengths(r: &Ray) -> SampledWavelengths {
+    r.wavelengths().expect("spectral ray without wavelengths")
+}
+
+impl Integrator for IterativePathIntegrator {
+    fn li(&self, r: Ray, _splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        if self.spectral {
+            let wl = SampledWavelengths::sample_uniform(rand::thread_rng().gen::<f64>());
+            self.walk::<SampledSpectrum>(r.with_wavelengths(wl))
+                .to_rgb(&wl)
+        } else {
+            self.walk::<Color>(r)
+        }
+    }
+}
+
+//AmbientOcclusion
+//share of cosine weighted rays leaving the first hit that travel max_distance unblocked
+pub struct AmbientOcclusion {
+    world: Arc<HittableList>,
+    max_distance: f64,
+    pub samples: usize,
+}
+
+impl AmbientOcclusion {
+    pub fn new(world: Arc<HittableList>, max_distance: f64) -> Self {
+        Self {
+            world,
+            max_distance,
+            samples: 4,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn li(&self, r: Ray, _splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        let mut rec = HitRecord::new();
+        if !self.world.hit(r, 0.001, INFINITY, &mut rec) {
+            return Color::zero();
+        }
+        //media have no surface to occlude
+        if rec.normal.squared_length() <= 0.0 {
+            return Color::ones();
+        }
+        let uvw = Onb::build_from_w(rec.normal);
+        let mut open = 0;
+        for _ in 0..self.samples {
+            let dir = uvw.local(Vec3::random_cosine_direction());
+            let mut occluder = HitRecord::new();
+            if !self.world.hit(
+                Ray::new(rec.p, dir, r.time()),
+                0.001,
+                self.max_distance,
+                &mut occluder,
+            ) {
+                open += 1;
+            }
+        }
+        Color::ones() * (open as f64 / self.samples as f64)
+    }
+}
+
+//DirectLighting
+//emission plus light arriving straight from the lights at the first non specular hit
+pub struct DirectLighting {
+    world: Arc<HittableList>,
+    lights: Arc<LightList>,
+    max_depth: usize,
+}
+
+impl DirectLighting {
+    pub fn new(world: Arc<HittableList>, lights: Arc<LightList>, max_depth: usize) -> Self {
+        Self {
+            world,
+            lights,
+            max_depth,
+        }
+    }
+}
+
+impl Integrator for DirectLighting {
+    fn li(&self, mut r: Ray, _splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        let mut l = Color::zero();
+        let mut beta = Color::ones();
+        //specular bounces are followed, they can't be lit by shadow rays
+        for _ in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+            if !self.world.hit(r, 0.001, INFINITY, &mut rec) {
+                return l + beta * escaped(r, &self.lights, 0.0);
+            }
+            rec.compute_differentials(&r);
+            l += beta * emitted(r, &rec, &self.lights, 0.0);
+
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !rec
+                .mat_ptr
+                .scatter(r, &rec, &mut attenuation, &mut scattered)
+            {
+                return l;
+            }
+            let bsdf_pdf = rec.mat_ptr.scattering_pdf(r, &rec, scattered);
+            if bsdf_pdf > 0.0 {
+                return l + beta
+                    * estimate_direct(
+                        r,
+                        &rec,
+                        (attenuation, scattered),
+                        bsdf_pdf,
+                        &self.world,
+                        &self.lights,
+                    );
+            }
+            beta = beta * attenuation;
+            r = scattered;
+        }
+        l
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DebugView {
+    Normals,
+    Uv,
+    Depth,
+    //analytic shapes have no triangle to be inside of, they show (u, v, 1 - u - v) of their own parameterization
+    Barycentrics,
+    MaterialId,
+    HitMiss,
+}
+
+//DebugIntegrator
+//false colors of the first hit, misses stay black
+pub struct DebugIntegrator {
+    world: Arc<HittableList>,
+    view: DebugView,
+    //distance shown as black in the depth view
+    pub far: f64,
+}
+
+impl DebugIntegrator {
+    pub fn new(world: Arc<HittableList>, view: DebugView) -> Self {
+        Self {
+            world,
+            view,
+            far: 100.0,
+        }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(&self, r: Ray, _splats: &mut Vec<(f64, f64, Color)>) -> Color {
+        let mut rec = HitRecord::new();
+        if !self.world.hit(r, 0.001, INFINITY, &mut rec) {
+            return Color::zero();
+        }
+        match self.view {
+            DebugView::Normals if rec.normal.squared_length() <= 0.0 => Color::ones() * 0.5,
+            DebugView::Normals => (rec.normal.unit() + Color::ones()) * 0.5,
+            DebugView::Uv => Color::new(rec.u, rec.v, 0.0),
+            DebugView::Depth => {
+                let distance = rec.t * r.direction().length();
+                Color::ones() * (1.0 - distance / self.far).max(0.0)
+            }
+            DebugView::Barycentrics => Color::new(rec.u, rec.v, (1.0 - rec.u - rec.v).max(0.0)),
+            DebugView::MaterialId => {
+                //scrambled address of the shared material
+                let mut h = Arc::as_ptr(&rec.mat_ptr) as *const u8 as usize as u64;
+                h ^= h >> 33;
+                h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+                h ^= h >> 33;
+                Color::new(
+                    (h & 0xff) as f64 / 255.0,
+                    ((h >> 8) & 0xff) as f64 / 255.0,
+                    ((h >> 16) & 0xff) as f64 / 255.0,
+                )
+            }
+            DebugView::HitMiss => Color::ones(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrect::XZRect;
+    use crate::light::Light;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sky::{sun_direction, PhysicalSky};
+    use crate::sphere::Sphere;
+    use crate::vec3::Point3;
+
+    //a gray unit sphere on the origin under a sphere light
+    fn scene() -> (Arc<HittableList>, Arc<LightList>) {
+        let mut lights = LightList::new_default();
+        let mut world = HittableList::new_default();
+        world.add(Arc::new(Sphere::new(
+            Point3::zero(),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let emit = Arc::new(DiffuseLight::new_by_color(Color::new(4.0, 4.0, 4.0)));
+        world.add(lights.add_sphere(Point3::new(0.0, 4.0, 0.0), 0.5, emit));
+        lights.build();
+        (Arc::new(world), Arc::new(lights))
+    }
+
+    #[test]
+    fn test_every_listed_name_builds_an_integrator() {
+        let (world, lights) = scene();
+        let camera = Camera::new(
+            (Point3::new(0.0, 1.0, 5.0), Point3::zero()),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            1.0,
+            (0.0, 1.0),
+        );
+        let options = IntegratorOptions {
+            max_depth: 5,
+            film: (8.0, 8.0),
+            spectral: false,
+            rr_depth: 3,
+            max_indirect: None,
+        };
+        for name in INTEGRATOR_NAMES.iter() {
+            let integrator = by_name(name, &camera, &world, &lights, &options);
+            assert_eq!(integrator.is_some(), *name != "sppm", "{}", name);
+        }
+        assert!(by_name("whitted", &camera, &world, &lights, &options).is_none());
+    }
+
+    #[test]
+    fn test_debug_views_show_the_first_hit() {
+        let (world, _) = scene();
+        let down = Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let past = Ray::new(Point3::new(3.0, 2.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let li = |view, r| DebugIntegrator::new(world.clone(), view).li(r, &mut Vec::new());
+
+        assert_eq!(li(DebugView::Normals, down), Color::new(0.5, 1.0, 0.5));
+        assert!((li(DebugView::Depth, down).x - 0.99).abs() < 1e-9);
+        assert_eq!(li(DebugView::HitMiss, down), Color::ones());
+        assert_eq!(li(DebugView::HitMiss, past), Color::zero());
+        assert_eq!(li(DebugView::MaterialId, past), Color::zero());
+
+        let mut rec = HitRecord::new();
+        assert!(world.hit(down, 0.001, INFINITY, &mut rec));
+        let barycentric = Color::new(rec.u, rec.v, (1.0 - rec.u - rec.v).max(0.0));
+        assert_eq!(li(DebugView::Barycentrics, down), barycentric);
+        assert_eq!(li(DebugView::Barycentrics, past), Color::zero());
+    }
+
+    #[test]
+    fn test_roulette_and_clamp_factors() {
+        let (world, lights) = scene();
+        let mut path = IterativePathIntegrator::new(world, lights, 10, false);
+        assert_eq!(path.survival(2, 0.1), 1.0);
+        assert_eq!(path.survival(3, 0.1), 0.1);
+        assert_eq!(path.survival(5, 4.0), 1.0);
+        assert_eq!(path.clamp_indirect(100.0), 1.0);
+        path.max_indirect = Some(2.0);
+        assert_eq!(path.clamp_indirect(8.0), 0.25);
+        assert_eq!(path.clamp_indirect(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_physical_sky_is_the_background_and_lights_the_ground() {
+        let daylight =
+            || PhysicalSky::new(sun_direction(40.0, 30.0), 3.0, Color::ones() * 0.3, 0.1);
+        let mut lights = LightList::new_default();
+        lights.add(Arc::new(daylight().sun(0.27, 5.0)));
+        lights.add(Arc::new(daylight()));
+        lights.build();
+        let mut world = HittableList::new_default();
+        world.add(Arc::new(XZRect::new(
+            -50.0,
+            50.0,
+            -50.0,
+            50.0,
+            0.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let path = IterativePathIntegrator::new(Arc::new(world), Arc::new(lights), 5, false);
+
+        let up = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.2, 1.0, -0.3), 0.0);
+        assert_eq!(path.walk::<Color>(up), daylight().le(&up));
+
+        let down = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let n = 2000;
+        let mut sum = Color::zero();
+        for _ in 0..n {
+            let li = path.walk::<Color>(down);
+            assert!(li.x.is_finite() && li.y.is_finite() && li.z.is_finite());
+            sum += li;
+        }
+        //the sunlit floor comes out brighter than the sky's own ground below the horizon
+        let ground = daylight().le(&down);
+        assert!(sum.y / n as f64 > 2.0 * ground.y);
+    }
+
+    #[test]
+    fn test_russian_roulette_keeps_the_mean() {
+        //inside a closed light gray room most of what arrives has bounced a few times
+        let mut lights = LightList::new_default();
+        let mut world = HittableList::new_default();
+        world.add(Arc::new(Sphere::new(
+            Point3::zero(),
+            5.0,
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+        let emit = Arc::new(DiffuseLight::new_by_color(Color::ones()));
+        world.add(lights.add_sphere(Point3::new(0.0, 2.0, 0.0), 1.0, emit));
+        lights.build();
+        let mut path = IterativePathIntegrator::new(Arc::new(world), Arc::new(lights), 12, false);
+
+        let r = Ray::new(Point3::new(0.0, -2.0, 0.0), Vec3::new(1.0, 0.0, 0.3), 0.0);
+        let n = 20_000;
+        let mean = |path: &IterativePathIntegrator| {
+            (0..n).map(|_| path.walk::<Color>(r).y).sum::<f64>() / n as f64
+        };
+        path.rr_depth = 12;
+        let full = mean(&path);
+        path.rr_depth = 0;
+        let roulette = mean(&path);
+        path.max_depth = 1;
+        let direct = mean(&path);
+        assert!(full > 2.0 * direct);
+        assert!((roulette / full - 1.0).abs() < 0.03);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::microfacet::{fr_dielectric, reflect, refract, Ggx};
+use crate::onb::Onb;
+use crate::vec3::Color;
+use crate::{Ray, Vec3};
+use rand::Rng;
+use std::sync::Arc;
+
+const MAX_INTERNAL_BOUNCES: i32 = 16;
+
+//dielectric coating on top of any material, light is random walked between the coating and the base
+pub struct Layered {
+    base: Arc<dyn Material>,
+    ior: f64,
+    ggx: Option<Ggx>,
+    thickness: f64,
+    sigma_a: Color,
+}
+
+impl Layered {
+    pub fn new(base: Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self::new_tinted(base, ior, roughness, 0.0, Color::zero())
+    }
+
+    pub fn new_tinted(
+        base: Arc<dyn Material>,
+        ior: f64,
+        roughness: f64,
+        thickness: f64,
+        sigma_a: Color,
+    ) -> Self {
+        Self {
+            base,
+            ior,
+            ggx: if roughness > 0.0 {
+                Some(Ggx::from_roughness(roughness, 0.0))
+            } else {
+                None
+            },
+            thickness,
+            sigma_a,
+        }
+    }
+
+    fn absorption(&self, cosine: f64) -> Color {
+        let d = self.thickness / cosine.abs().max(1e-4);
+        Color::new(
+            (-self.sigma_a.x * d).exp(),
+            (-self.sigma_a.y * d).exp(),
+            (-self.sigma_a.z * d).exp(),
+        )
+    }
+
+    //one interface event in the local frame of the side wo is on, returns the new direction and its weight
+    fn interface(&self, wo: Vec3, eta: f64) -> (Vec3, f64) {
+        let mut rng = rand::thread_rng();
+        let h = match self.ggx {
+            Some(ggx) => ggx.sample_h(wo, rng.gen::<f64>(), rng.gen::<f64>()),
+            None => Vec3::new(0.0, 0.0, 1.0),
+        };
+        let fresnel = fr_dielectric(Vec3::dot(wo, h), eta);
+        let wi = match refract(wo, h, eta) {
+            Some(wt) if rng.gen::<f64>() >= fresnel => wt,
+            _ => reflect(wo, h),
+        };
+        let weight = match self.ggx {
+            Some(ggx) => ggx.g(wo, wi) / ggx.g1(wo),
+            None => 1.0,
+        };
+        (wi, weight)
+    }
+}
+
+impl Material for Layered {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        if wo.z <= 0.0 {
+            return false;
+        }
+        let new_ray = |w: Vec3| {
+            let r = Ray::new(rec.p, uvw.local(w), r_in.time());
+            match r_in.wavelengths() {
+                Some(wl) => r.with_wavelengths(wl),
+                None => r,
+            }
+        };
+
+        //entering the coating from outside
+        let (mut w, weight) = self.interface(wo, self.ior);
+        let mut throughput = Color::ones() * weight;
+        if w.z > 0.0 {
+            *attenuation = throughput;
+            *scattered = new_ray(w);
+            return true;
+        }
+
+        for _ in 0..MAX_INTERNAL_BOUNCES {
+            //down through the coating to the base
+            throughput = throughput * self.absorption(w.z);
+            let mut base_attenuation = Color::zero();
+            let mut base_scattered = Ray::default_new();
+            if !self
+                .base
+                .scatter(new_ray(w), rec, &mut base_attenuation, &mut base_scattered)
+            {
+                return false;
+            }
+            throughput = throughput * base_attenuation;
+            let ws = uvw.to_local(base_scattered.direction().unit());
+            if ws.z <= 0.0 {
+                //the base transmitted the light through the whole stack
+                *attenuation = throughput;
+                *scattered = base_scattered;
+                return true;
+            }
+
+            //up through the coating, then the interface seen from inside with the normal flipped
+            throughput = throughput * self.absorption(ws.z);
+            let (wi, weight) = self.interface(Vec3::new(-ws.x, -ws.y, ws.z), 1.0 / self.ior);
+            throughput *= weight;
+            w = Vec3::new(wi.x, wi.y, -wi.z);
+            if w.z > 0.0 {
+                *attenuation = throughput;
+                *scattered = new_ray(w);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Metal};
+
+    fn albedo(mat: &dyn Material, cos_theta: f64) -> Color {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let r_in = Ray::new(
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(sin_theta, 0.0, -cos_theta),
+            0.0,
+        );
+
+        let n = 20000;
+        let mut sum = Color::zero();
+        for _ in 0..n {
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if mat.scatter(r_in, &rec, &mut attenuation, &mut scattered) {
+                sum += attenuation;
+            }
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_energy_conservation() {
+        let white = Arc::new(Lambertian::new(Color::ones()));
+        let mirror = Arc::new(Metal::new(Color::ones(), 0.0));
+        let materials = [
+            Layered::new(white.clone(), 1.5, 0.0),
+            Layered::new(white.clone(), 1.5, 0.4),
+            Layered::new(mirror, 1.5, 0.2),
+            Layered::new_tinted(white, 1.5, 0.1, 0.5, Color::new(0.5, 1.0, 2.0)),
+        ];
+        for mat in materials.iter() {
+            for cos_theta in [1.0, 0.7, 0.3, 0.05].iter() {
+                let a = albedo(mat, *cos_theta);
+                assert!(a.x <= 1.02 && a.y <= 1.02 && a.z <= 1.02, "{:?}", a);
+                assert!(a.x >= 0.0 && a.y >= 0.0 && a.z >= 0.0, "{:?}", a);
+            }
+        }
+    }
+
+    #[test]
+    fn test_absorption_darkens() {
+        let white = Arc::new(Lambertian::new(Color::ones()));
+        let clear = Layered::new(white.clone(), 1.5, 0.0);
+        let tinted = Layered::new_tinted(white, 1.5, 0.0, 1.0, Color::new(1.0, 0.0, 0.0));
+        let a = albedo(&clear, 0.8);
+        let b = albedo(&tinted, 0.8);
+        assert!(b.x < a.x * 0.5);
+        assert!((b.y - a.y).abs() < 0.05);
+    }
+}
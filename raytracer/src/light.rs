@@ -0,0 +1,588 @@
+use crate::aabb::AABB;
+use crate::arrect::RectLight;
+use crate::color::luminance;
+use crate::ies::IesProfile;
+use crate::light_tree::LightTree;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::rtweekend::{clamp, degrees_to_radians, INFINITY, PI};
+use crate::sampling::Distribution1D;
+use crate::sphere::SphereLight;
+use crate::texture::Texture;
+use crate::vec3::{Color, Point3};
+use crate::{Ray, Vec3};
+use std::sync::Arc;
+
+pub struct LightSample {
+    //unit direction from the shading point toward the light
+    pub wi: Vec3,
+    //radiance arriving along wi
+    pub li: Color,
+    //solid angle density of wi, for delta lights the sample is taken with probability 1
+    pub pdf: f64,
+    //distance to the light along wi, infinite for lights at infinity
+    pub dist: f64,
+}
+
+//a ray leaving a light, for tracing paths from the light side
+pub struct EmissionSample {
+    pub ray: Ray,
+    pub le: Color,
+    //zero for lights without a surface
+    pub normal: Vec3,
+    //area density of the origin, 1 for point lights, and solid angle density of the direction
+    pub pdf_pos: f64,
+    pub pdf_dir: f64,
+}
+
+pub trait Light {
+    fn sample_li(&self, p: Point3, u1: f64, u2: f64) -> Option<LightSample>;
+
+    //solid angle density with which sample_li picks wi from p
+    fn pdf_li(&self, _p: Point3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    //point and directional lights can't be hit by rays and skip multiple importance sampling
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    //radiance carried by a ray that escapes the scene, nonzero only for lights at infinity
+    fn le(&self, _r: &Ray) -> Color {
+        Color::zero()
+    }
+
+    //None for lights at infinity
+    fn bounds(&self) -> Option<AABB> {
+        None
+    }
+
+    //rough emitted power, only compared between lights to decide which to sample
+    fn power(&self) -> f64 {
+        0.0
+    }
+
+    //None for lights that can't start a path, like those at infinity
+    fn sample_le(&self, _u: [f64; 4]) -> Option<EmissionSample> {
+        None
+    }
+
+    //pdf_pos and pdf_dir of sample_le emitting from p with normal n toward dir
+    fn pdf_le(&self, _p: Point3, _n: Vec3, _dir: Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+//LightList
+//lights at infinity are picked uniformly next to the tree, which holds everything with bounds
+#[derive(Clone)]
+pub struct LightList {
+    pub lights: Vec<Arc<dyn Light>>,
+    infinite: Vec<usize>,
+    tree: Option<Arc<LightTree>>,
+    //lights that can start a path, picked by power alone
+    emitters: Vec<usize>,
+    emitter_distribution: Option<Arc<Distribution1D>>,
+}
+unsafe impl Sync for LightList {}
+unsafe impl Send for LightList {}
+
+impl LightList {
+    pub fn new_default() -> Self {
+        Self {
+            lights: Vec::new(),
+            infinite: Vec::new(),
+            tree: None,
+            emitters: Vec::new(),
+            emitter_distribution: None,
+        }
+    }
+
+    //returns the index hit records use to refer to the light, call build after the last add
+    pub fn add(&mut self, light: Arc<dyn Light>) -> usize {
+        if light.bounds().is_none() {
+            self.infinite.push(self.lights.len());
+        }
+        self.lights.push(light);
+        self.lights.len() - 1
+    }
+
+    pub fn add_sphere(
+        &mut self,
+        center: Point3,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Arc<SphereLight> {
+        let light = Arc::new(SphereLight::new(
+            center,
+            radius,
+            material,
+            self.lights.len(),
+        ));
+        self.add(light.clone());
+        light
+    }
+
+    //min and max are opposite corners of a rectangle flat along one axis
+    pub fn add_rect(
+        &mut self,
+        min: Point3,
+        max: Point3,
+        material: Arc<dyn Material>,
+    ) -> Arc<RectLight> {
+        let light = Arc::new(RectLight::new(min, max, material, self.lights.len()));
+        self.add(light.clone());
+        light
+    }
+
+    //bounded lights added after the last build are never sampled, only hit
+    pub fn build(&mut self) {
+        let bounded = self
+            .lights
+            .iter()
+            .enumerate()
+            .filter_map(|(i, l)| l.bounds().map(|b| (i, b, l.power())))
+            .collect();
+        self.tree = LightTree::new(bounded, self.lights.len()).map(Arc::new);
+
+        self.emitters = (0..self.lights.len())
+            .filter(|&i| {
+                let light = &self.lights[i];
+                light.bounds().is_some() && light.power() > 0.0
+            })
+            .collect();
+        self.emitter_distribution = if self.emitters.is_empty() {
+            None
+        } else {
+            let power = self
+                .emitters
+                .iter()
+                .map(|&i| self.lights[i].power())
+                .collect();
+            Some(Arc::new(Distribution1D::new(power)))
+        };
+    }
+
+    fn slots(&self) -> usize {
+        self.infinite.len() + if self.tree.is_some() { 1 } else { 0 }
+    }
+
+    //picks a light by its estimated contribution at p, returns it with the probability it was picked
+    pub fn pick(&self, p: Point3, u: f64) -> Option<(&Arc<dyn Light>, f64)> {
+        let slots = self.slots();
+        if slots == 0 {
+            return None;
+        }
+        let scaled = u * slots as f64;
+        let slot = (scaled as usize).min(slots - 1);
+        if slot < self.infinite.len() {
+            return Some((&self.lights[self.infinite[slot]], 1.0 / slots as f64));
+        }
+        let u = (scaled - slot as f64).min(1.0 - 1e-12);
+        let (index, pmf) = self.tree.as_ref()?.sample(p, u)?;
+        Some((&self.lights[index], pmf / slots as f64))
+    }
+
+    //probability that pick returns the light at index from p
+    pub fn pmf(&self, p: Point3, index: usize) -> f64 {
+        let slots = self.slots();
+        if slots == 0 {
+            return 0.0;
+        }
+        if self.infinite.contains(&index) {
+            return 1.0 / slots as f64;
+        }
+        match &self.tree {
+            Some(tree) => tree.pmf(p, index) / slots as f64,
+            None => 0.0,
+        }
+    }
+
+    //density of reaching wi from p through pick and sample_li of the light at index
+    pub fn pdf_light(&self, index: usize, p: Point3, wi: Vec3) -> f64 {
+        self.pmf(p, index) * self.lights[index].pdf_li(p, wi)
+    }
+
+    //the same for a ray that escaped, so only lights at infinity can have sent it
+    pub fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        let slots = self.slots();
+        if slots == 0 {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .infinite
+            .iter()
+            .map(|&i| self.lights[i].pdf_li(p, wi))
+            .sum();
+        sum / slots as f64
+    }
+
+    //picks a light to start a path from in proportion to its power
+    pub fn pick_emitter(&self, u: f64) -> Option<(usize, f64)> {
+        let distribution = self.emitter_distribution.as_ref()?;
+        let (slot, pmf) = distribution.sample_discrete(u);
+        Some((self.emitters[slot], pmf))
+    }
+
+    pub fn emitter_pmf(&self, index: usize) -> f64 {
+        match (
+            &self.emitter_distribution,
+            self.emitters.binary_search(&index),
+        ) {
+            (Some(distribution), Ok(slot)) => distribution.pdf(slot) / distribution.count() as f64,
+            _ => 0.0,
+        }
+    }
+
+    //lights at infinity alone, picked uniformly
+    pub fn pick_infinite(&self, u: f64) -> Option<(&Arc<dyn Light>, f64)> {
+        let n = self.infinite.len();
+        if n == 0 {
+            return None;
+        }
+        let slot = ((u * n as f64) as usize).min(n - 1);
+        Some((&self.lights[self.infinite[slot]], 1.0 / n as f64))
+    }
+
+    pub fn pdf_infinite(&self, p: Point3, wi: Vec3) -> f64 {
+        let n = self.infinite.len();
+        if n == 0 {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .infinite
+            .iter()
+            .map(|&i| self.lights[i].pdf_li(p, wi))
+            .sum();
+        sum / n as f64
+    }
+
+    pub fn le(&self, r: &Ray) -> Color {
+        let mut sum = Color::zero();
+        for &i in self.infinite.iter() {
+            sum += self.lights[i].le(r);
+        }
+        sum
+    }
+}
+
+//PointLight
+//intensity is radiant intensity, arriving radiance falls off with the squared distance
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    profile: Option<(Arc<IesProfile>, Onb)>,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            profile: None,
+        }
+    }
+
+    //the profile's nadir points along direction, intensity is what an isotropic light of equal flux would have
+    pub fn new_with_profile(
+        position: Point3,
+        intensity: Color,
+        direction: Vec3,
+        profile: Arc<IesProfile>,
+    ) -> Self {
+        Self {
+            position,
+            intensity,
+            profile: Some((profile, Onb::build_from_w(direction))),
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        let d = self.position - p;
+        let dist2 = d.squared_length();
+        if dist2 <= 0.0 {
+            return None;
+        }
+        let dist = dist2.sqrt();
+        let wi = d / dist;
+        let mut li = self.intensity / dist2;
+        if let Some((profile, frame)) = &self.profile {
+            li *= profile.intensity_scale(frame.to_local(-wi));
+        }
+        Some(LightSample {
+            wi,
+            li,
+            pdf: 1.0,
+            dist,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<AABB> {
+        Some(AABB::new(self.position, self.position))
+    }
+
+    fn power(&self) -> f64 {
+        4.0 * PI * luminance(self.intensity)
+    }
+
+    fn sample_le(&self, u: [f64; 4]) -> Option<EmissionSample> {
+        let dir = uniform_sphere(u[2], u[3]);
+        let mut le = self.intensity;
+        if let Some((profile, frame)) = &self.profile {
+            le *= profile.intensity_scale(frame.to_local(dir));
+        }
+        Some(EmissionSample {
+            ray: Ray::new(self.position, dir, 0.0),
+            le,
+            normal: Vec3::zero(),
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (4.0 * PI),
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, _dir: Vec3) -> (f64, f64) {
+        (1.0, 1.0 / (4.0 * PI))
+    }
+}
+
+//SpotLight
+//point light restricted to a cone, angles in degrees are measured from the axis
+pub struct SpotLight {
+    position: Point3,
+    frame: Onb,
+    intensity: Color,
+    cos_total: f64,
+    cos_falloff: f64,
+    tan_total: f64,
+    //projected across the cone, u and v span its opening
+    pub gobo: Option<Arc<dyn Texture>>,
+    //candela distribution with its nadir on the cone axis, shaped further by the cone
+    pub profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    pub fn new(
+        from: Point3,
+        to: Point3,
+        intensity: Color,
+        cone_angle: f64,
+        falloff_angle: f64,
+    ) -> Self {
+        let total = degrees_to_radians(cone_angle.min(89.0));
+        let falloff = degrees_to_radians(falloff_angle.min(cone_angle));
+        Self {
+            position: from,
+            frame: Onb::build_from_w(to - from),
+            intensity,
+            cos_total: total.cos(),
+            cos_falloff: falloff.cos(),
+            tan_total: total.tan(),
+            gobo: None,
+            profile: None,
+        }
+    }
+
+    //smooth falloff between the two cones, d in the light's frame
+    fn falloff(&self, d: Vec3) -> f64 {
+        let cos_theta = d.z;
+        if cos_theta < self.cos_total {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff {
+            return 1.0;
+        }
+        let t = clamp(
+            (cos_theta - self.cos_total) / (self.cos_falloff - self.cos_total),
+            0.0,
+            1.0,
+        );
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    //intensity toward local, p is where the gobo gets looked up
+    fn radiant_intensity(&self, local: Vec3, p: Point3) -> Color {
+        let mut intensity = self.intensity * self.falloff(local);
+        if let Some(profile) = &self.profile {
+            intensity *= profile.intensity_scale(local);
+        }
+        if let Some(gobo) = &self.gobo {
+            let u = 0.5 + 0.5 * local.x / (local.z * self.tan_total);
+            let v = 0.5 + 0.5 * local.y / (local.z * self.tan_total);
+            intensity = intensity * gobo.value(u, v, p);
+        }
+        intensity
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        let d = self.position - p;
+        let dist2 = d.squared_length();
+        if dist2 <= 0.0 {
+            return None;
+        }
+        let dist = dist2.sqrt();
+        let wi = d / dist;
+        let local = self.frame.to_local(-wi);
+        if self.falloff(local) <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: self.radiant_intensity(local, p) / dist2,
+            pdf: 1.0,
+            dist,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn bounds(&self) -> Option<AABB> {
+        Some(AABB::new(self.position, self.position))
+    }
+
+    fn power(&self) -> f64 {
+        2.0 * PI * luminance(self.intensity) * (1.0 - 0.5 * (self.cos_total + self.cos_falloff))
+    }
+
+    fn sample_le(&self, u: [f64; 4]) -> Option<EmissionSample> {
+        //uniform over the cone
+        let cos_theta = 1.0 - u[2] * (1.0 - self.cos_total);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u[3];
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let dir = self.frame.local(local);
+        Some(EmissionSample {
+            ray: Ray::new(self.position, dir, 0.0),
+            le: self.radiant_intensity(local, self.position + dir),
+            normal: Vec3::zero(),
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (2.0 * PI * (1.0 - self.cos_total)),
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, _n: Vec3, dir: Vec3) -> (f64, f64) {
+        if Vec3::dot(dir.unit(), self.frame.w()) < self.cos_total {
+            return (1.0, 0.0);
+        }
+        (1.0, 1.0 / (2.0 * PI * (1.0 - self.cos_total)))
+    }
+}
+
+//DirectionalLight
+//parallel light from infinitely far away, radiance is the irradiance it delivers at normal incidence
+pub struct DirectionalLight {
+    direction: Vec3,
+    radiance: Color,
+}
+
+impl DirectionalLight {
+    //direction is the way the light travels
+    pub fn new(direction: Vec3, radiance: Color) -> Self {
+        Self {
+            direction: direction.unit(),
+            radiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            li: self.radiance,
+            pdf: 1.0,
+            dist: INFINITY,
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+pub fn uniform_sphere(u1: f64, u2: f64) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+//cosine weighted direction around +z
+pub fn cosine_direction(u1: f64, u2: f64) -> Vec3 {
+    let phi = 2.0 * PI * u1;
+    let r = u2.sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u2).max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_falls_off_with_squared_distance() {
+        let light = PointLight::new(Point3::new(0.0, 2.0, 0.0), Color::new(4.0, 4.0, 4.0));
+        let near = light.sample_li(Point3::zero(), 0.5, 0.5).unwrap();
+        let far = light
+            .sample_li(Point3::new(0.0, -2.0, 0.0), 0.5, 0.5)
+            .unwrap();
+        assert_eq!(near.wi, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!((near.dist, far.dist), (2.0, 4.0));
+        assert_eq!(near.li, Color::new(1.0, 1.0, 1.0));
+        assert_eq!(far.li * 4.0, near.li);
+        assert!(light
+            .sample_li(Point3::new(0.0, 2.0, 0.0), 0.5, 0.5)
+            .is_none());
+    }
+
+    #[test]
+    fn test_spot_light_fades_between_its_cones() {
+        let light = SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::zero(),
+            Color::new(1.0, 1.0, 1.0),
+            30.0,
+            20.0,
+        );
+        let at = |x: f64| {
+            light
+                .sample_li(Point3::new(x, 0.0, 0.0), 0.5, 0.5)
+                .map_or(0.0, |ls| ls.li.y * ls.dist * ls.dist)
+        };
+        assert!((at(0.0) - 1.0).abs() < 1e-12);
+        assert!((at(degrees_to_radians(15.0).tan()) - 1.0).abs() < 1e-12);
+        let edge = at(degrees_to_radians(25.0).tan());
+        assert!(edge > 0.0 && edge < 1.0);
+        assert_eq!(at(degrees_to_radians(35.0).tan()), 0.0);
+
+        //emitted rays stay in the cone and carry the density pdf_le reports
+        for k in 0..20 {
+            let u = [0.5, 0.5, (k as f64 + 0.5) / 20.0, 0.3];
+            let es = light.sample_le(u).unwrap();
+            let dir = es.ray.direction();
+            assert!(dir.y <= -degrees_to_radians(30.0).cos() + 1e-9);
+            assert!((light.pdf_le(es.ray.origin(), es.normal, dir).1 - es.pdf_dir).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_directional_light_arrives_against_its_direction() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Color::new(1.0, 2.0, 3.0));
+        let ls = light
+            .sample_li(Point3::new(5.0, 0.0, -3.0), 0.1, 0.9)
+            .unwrap();
+        assert_eq!(ls.wi, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(ls.li, Color::new(1.0, 2.0, 3.0));
+        assert_eq!(ls.dist, INFINITY);
+        assert!(light.is_delta());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::aabb::AABB;
+use crate::vec3::Point3;
+use std::cmp::Ordering;
+
+enum LightNodeKind {
+    Leaf(usize),
+    //the left child follows its parent, the right one sits at this index
+    Interior(usize),
+}
+
+struct LightNode {
+    bounds: AABB,
+    power: f64,
+    kind: LightNodeKind,
+}
+
+//LightTree
+//bvh over bounded lights, each level picks a child by its power over the squared distance to p
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    //branch taken at each level on the way to a light, bit i set means right at depth i
+    trails: Vec<Option<(u64, u32)>>,
+}
+
+impl LightTree {
+    //lights are (index in the light list, bounds, power), those without power never get picked,
+    //nor those whose power or bounds aren't finite since they would poison the split and the sums
+    pub fn new(mut lights: Vec<(usize, AABB, f64)>, light_count: usize) -> Option<Self> {
+        let finite = |p: Point3| p.x.is_finite() && p.y.is_finite() && p.z.is_finite();
+        lights.retain(|l| l.2 > 0.0 && l.2.is_finite() && finite(l.1.min()) && finite(l.1.max()));
+        if lights.is_empty() {
+            return None;
+        }
+        let mut tree = Self {
+            nodes: Vec::with_capacity(2 * lights.len()),
+            trails: (0..light_count).map(|_| None).collect(),
+        };
+        tree.build(&mut lights, 0, 0);
+        Some(tree)
+    }
+
+    fn build(&mut self, lights: &mut [(usize, AABB, f64)], trail: u64, depth: u32) -> usize {
+        let at = self.nodes.len();
+        if lights.len() == 1 {
+            let (index, bounds, power) = lights[0];
+            self.nodes.push(LightNode {
+                bounds,
+                power,
+                kind: LightNodeKind::Leaf(index),
+            });
+            self.trails[index] = Some((trail, depth));
+            return at;
+        }
+
+        //median split of the centroids along their widest axis keeps the depth logarithmic
+        let centroid = |b: &AABB| (b.min() + b.max()) * 0.5;
+        let mut lo = centroid(&lights[0].1);
+        let mut hi = lo;
+        for l in lights.iter() {
+            let c = centroid(&l.1);
+            lo = Point3::new(lo.x.min(c.x), lo.y.min(c.y), lo.z.min(c.z));
+            hi = Point3::new(hi.x.max(c.x), hi.y.max(c.y), hi.z.max(c.z));
+        }
+        let extent = hi - lo;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        let key = |b: &AABB| {
+            let c = centroid(b);
+            [c.x, c.y, c.z][axis]
+        };
+        let mid = lights.len() / 2;
+        //bounds are checked finite in new
+        lights.select_nth_unstable_by(mid, |a, b| {
+            key(&a.1).partial_cmp(&key(&b.1)).unwrap_or(Ordering::Equal)
+        });
+
+        self.nodes.push(LightNode {
+            bounds: AABB::default_new(),
+            power: 0.0,
+            kind: LightNodeKind::Interior(0),
+        });
+        let (left, right) = lights.split_at_mut(mid);
+        let l = self.build(left, trail, depth + 1);
+        let r = self.build(right, trail | (1 << depth), depth + 1);
+        self.nodes[at] = LightNode {
+            bounds: AABB::surrounding_box(self.nodes[l].bounds, self.nodes[r].bounds),
+            power: self.nodes[l].power + self.nodes[r].power,
+            kind: LightNodeKind::Interior(r),
+        };
+        at
+    }
+
+    //power over squared distance, clamped inside the bounds so nearby lights don't blow up
+    fn importance(node: &LightNode, p: Point3) -> f64 {
+        let center = (node.bounds.min() + node.bounds.max()) * 0.5;
+        let radius2 = (node.bounds.max() - node.bounds.min()).squared_length() * 0.25;
+        let dist2 = (center - p).squared_length();
+        node.power / dist2.max(radius2).max(1e-8)
+    }
+
+    //probability of going left at an interior node, None if neither side matters
+    fn left_probability(&self, at: usize, right: usize, p: Point3) -> Option<f64> {
+        let il = Self::importance(&self.nodes[at + 1], p);
+        let ir = Self::importance(&self.nodes[right], p);
+        if il + ir <= 0.0 {
+            return None;
+        }
+        Some(il / (il + ir))
+    }
+
+    //returns the light index and the probability it was picked with
+    pub fn sample(&self, p: Point3, mut u: f64) -> Option<(usize, f64)> {
+        let mut at = 0;
+        let mut pmf = 1.0;
+        loop {
+            match self.nodes[at].kind {
+                LightNodeKind::Leaf(index) => return Some((index, pmf)),
+                LightNodeKind::Interior(right) => {
+                    let pl = self.left_probability(at, right, p)?;
+                    if u < pl {
+                        u = (u / pl).min(1.0 - 1e-12);
+                        pmf *= pl;
+                        at += 1;
+                    } else {
+                        u = ((u - pl) / (1.0 - pl)).min(1.0 - 1e-12);
+                        pmf *= 1.0 - pl;
+                        at = right;
+                    }
+                }
+            }
+        }
+    }
+
+    //probability that sample picks the light at index from p
+    pub fn pmf(&self, p: Point3, index: usize) -> f64 {
+        let (trail, depth) = match self.trails.get(index) {
+            Some(Some(t)) => *t,
+            _ => return 0.0,
+        };
+        let mut at = 0;
+        let mut pmf = 1.0;
+        for level in 0..depth {
+            if let LightNodeKind::Interior(right) = self.nodes[at].kind {
+                let pl = match self.left_probability(at, right, p) {
+                    Some(pl) => pl,
+                    None => return 0.0,
+                };
+                if trail & (1 << level) == 0 {
+                    pmf *= pl;
+                    at += 1;
+                } else {
+                    pmf *= 1.0 - pl;
+                    at = right;
+                }
+            }
+        }
+        pmf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lights() -> Vec<(usize, AABB, f64)> {
+        (0..7)
+            .map(|i| {
+                let c = Point3::new(i as f64 * 1.5, (i % 3) as f64, -(i as f64) * 0.5);
+                let half = Point3::new(0.25, 0.25, 0.25);
+                (i, AABB::new(c - half, c + half), (i % 4) as f64)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pmf_sums_to_one_over_lights_with_power() {
+        //index 7 belongs to a light the tree never saw
+        let tree = LightTree::new(lights(), 8).unwrap();
+        for &p in [
+            Point3::zero(),
+            Point3::new(4.0, 1.0, -2.0),
+            Point3::new(-9.0, 5.0, 3.0),
+        ]
+        .iter()
+        {
+            let sum: f64 = (0..8).map(|i| tree.pmf(p, i)).sum();
+            assert!((sum - 1.0).abs() < 1e-12);
+            assert_eq!(tree.pmf(p, 0), 0.0);
+            assert_eq!(tree.pmf(p, 4), 0.0);
+            assert_eq!(tree.pmf(p, 7), 0.0);
+        }
+        assert!(LightTree::new(vec![(0, lights()[0].1, 0.0)], 1).is_none());
+    }
+
+    #[test]
+    fn test_lights_with_non_finite_power_or_bounds_are_dropped() {
+        let mut broken = lights();
+        broken[1].2 = f64::NAN;
+        broken[2].2 = f64::INFINITY;
+        broken[5].1 = AABB::new(Point3::new(f64::NAN, 0.0, 0.0), Point3::ones());
+        let tree = LightTree::new(broken, 7).unwrap();
+        let p = Point3::new(1.0, 2.0, 3.0);
+        for &i in [1, 2, 5].iter() {
+            assert_eq!(tree.pmf(p, i), 0.0);
+        }
+        let sum: f64 = (0..7).map(|i| tree.pmf(p, i)).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sampled_lights_are_picked_with_their_pmf() {
+        let tree = LightTree::new(lights(), 7).unwrap();
+        let p = Point3::new(2.0, 0.5, -1.0);
+        let n = 100_000;
+        let mut counts = [0usize; 7];
+        for k in 0..n {
+            let (index, pmf) = tree.sample(p, (k as f64 + 0.5) / n as f64).unwrap();
+            assert!((pmf - tree.pmf(p, index)).abs() < 1e-12);
+            counts[index] += 1;
+        }
+        for (i, &count) in counts.iter().enumerate() {
+            assert!((count as f64 / n as f64 - tree.pmf(p, i)).abs() < 1e-3);
+        }
+    }
+}
@@ -2,20 +2,43 @@ extern crate rand;
 
 mod _box;
 mod aabb;
+mod alpha_mask;
 mod arrect;
+mod bdpt;
 mod bvh;
 mod camera;
 mod color;
 mod constant_medium;
+mod disney;
+mod environment;
+mod heterogeneous_medium;
 mod hittable;
 mod hittable_list;
+mod ies;
+mod integrator;
+mod layered;
+mod light;
+mod light_tree;
 mod material;
+mod microfacet;
+mod mipmap;
 mod moving_sphere;
+mod node;
+mod normal_map;
+mod onb;
 mod perlin;
+mod photon_map;
+mod procedural;
 mod ray;
 mod rtweekend;
+mod sampling;
+mod scene;
+mod sky;
+mod spectrum;
 mod sphere;
+mod sppm;
 mod texture;
+mod thin_film;
 mod vec3;
 
 use image::{ImageBuffer, RgbImage};
@@ -24,14 +47,18 @@ use indicatif::ProgressBar;
 use crate::_box::_Box;
 use crate::arrect::{XYRect, XZRect, YZRect};
 use crate::bvh::BvhNode;
-use crate::camera::Camera;
 use crate::constant_medium::ConstantMedium;
 use crate::hittable::{HitRecord, RotateY, Translate};
+use crate::integrator::{Integrator, IntegratorOptions, INTEGRATOR_NAMES};
+use crate::light::LightList;
 use crate::material::{Dielectric, Lambertian};
 use crate::material::{DiffuseLight, Metal};
 use crate::moving_sphere::MovingSphere;
 use crate::rtweekend::random_double;
 use crate::rtweekend::INFINITY;
+use crate::sampling::power_heuristic;
+use crate::spectrum::SampledSpectrum;
+use crate::sppm::Sppm;
 use crate::texture::ImageTexture;
 use crate::texture::NoiseTexture;
 pub use crate::vec3::Color;
@@ -46,7 +73,63 @@ use std::sync::Arc;
 use threadpool::ThreadPool;
 pub use vec3::Vec3;
 
-fn ray_color(r: Ray, background: Color, world: &Arc<HittableList>, depth: i32) -> Color {
+//next event estimation toward one light from the list, weighted against bsdf sampling
+fn sample_lights(r: Ray, rec: &HitRecord, lights: &LightList, world: &Arc<HittableList>) -> Color {
+    let mut rng = rand::thread_rng();
+    let (light, pick_pdf) = match lights.pick(rec.p, rng.gen::<f64>()) {
+        Some(picked) => picked,
+        None => return Color::zero(),
+    };
+    let ls = match light.sample_li(rec.p, rng.gen::<f64>(), rng.gen::<f64>()) {
+        Some(ls) if ls.pdf > 0.0 => ls,
+        _ => return Color::zero(),
+    };
+    let shadow = Ray::new(rec.p, ls.wi, r.time());
+    let f = rec.mat_ptr.eval_bsdf(r, rec, shadow);
+    if f.near_zero() {
+        return Color::zero();
+    }
+    //media along the shadow ray dim the light instead of blocking it
+    let tr = world.transmittance(shadow, 0.001, ls.dist - 0.001);
+    if tr <= 0.0 {
+        return Color::zero();
+    }
+    let light_pdf = ls.pdf * pick_pdf;
+    let weight = if light.is_delta() {
+        1.0
+    } else {
+        power_heuristic(light_pdf, rec.mat_ptr.scattering_pdf(r, rec, shadow))
+    };
+    f * ls.li * (tr * weight / light_pdf)
+}
+
+//emission at a hit, weighted against next event estimation when the surface is a sampled light
+fn emitted(r: Ray, rec: &HitRecord, lights: &LightList, bsdf_pdf: f64) -> Color {
+    let le = rec.mat_ptr.emitted(r, rec);
+    match rec.light {
+        Some(index) if bsdf_pdf > 0.0 => {
+            le * power_heuristic(bsdf_pdf, lights.pdf_light(index, r.origin(), r.direction()))
+        }
+        _ => le,
+    }
+}
+
+//bsdf_pdf is the density r was scattered with, 0 for camera rays and delta scattering
+fn escaped(r: Ray, lights: &LightList, bsdf_pdf: f64) -> Color {
+    let le = lights.le(&r);
+    if bsdf_pdf <= 0.0 {
+        return le;
+    }
+    le * power_heuristic(bsdf_pdf, lights.pdf_li(r.origin(), r.direction().unit()))
+}
+
+fn ray_color(
+    r: Ray,
+    lights: &Arc<LightList>,
+    world: &Arc<HittableList>,
+    depth: i32,
+    bsdf_pdf: f64,
+) -> Color {
     let mut rec = HitRecord::new();
 
     if depth <= 0 {
@@ -54,12 +137,47 @@ fn ray_color(r: Ray, background: Color, world: &Arc<HittableList>, depth: i32) -
     }
 
     if !world.hit(r, 0.001, INFINITY, &mut rec) {
-        return background;
+        return escaped(r, lights, bsdf_pdf);
+    }
+    rec.compute_differentials(&r);
+
+    let mut scattered = Ray::default_new();
+    let mut attenuation = Color::zero();
+    let emitted = emitted(r, &rec, lights, bsdf_pdf);
+    let tmp_rec = rec.clone();
+    if !rec
+        .mat_ptr
+        .scatter(r, &tmp_rec, &mut attenuation, &mut scattered)
+    {
+        return emitted;
+    }
+    let direct = sample_lights(r, &tmp_rec, lights, world);
+    let next_pdf = rec.mat_ptr.scattering_pdf(r, &tmp_rec, scattered);
+    emitted + direct + ray_color(scattered, lights, world, depth - 1, next_pdf) * attenuation
+}
+
+fn ray_color_spectral(
+    r: Ray,
+    lights: &Arc<LightList>,
+    world: &Arc<HittableList>,
+    depth: i32,
+    bsdf_pdf: f64,
+) -> SampledSpectrum {
+    let wl = r.wavelengths().expect("spectral ray without wavelengths");
+    let mut rec = HitRecord::new();
+
+    if depth <= 0 {
+        return SampledSpectrum::zero();
+    }
+
+    if !world.hit(r, 0.001, INFINITY, &mut rec) {
+        return SampledSpectrum::from_rgb(escaped(r, lights, bsdf_pdf), &wl);
     }
+    rec.compute_differentials(&r);
 
     let mut scattered = Ray::default_new();
     let mut attenuation = Color::zero();
-    let emitted = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+    let emitted = SampledSpectrum::from_rgb(emitted(r, &rec, lights, bsdf_pdf), &wl);
     let tmp_rec = rec.clone();
     if !rec
         .mat_ptr
@@ -67,7 +185,29 @@ fn ray_color(r: Ray, background: Color, world: &Arc<HittableList>, depth: i32) -
     {
         return emitted;
     }
-    emitted + ray_color(scattered, background, world, depth - 1) * attenuation
+    let direct = SampledSpectrum::from_rgb(sample_lights(r, &tmp_rec, lights, world), &wl);
+    let next_pdf = rec.mat_ptr.scattering_pdf(r, &tmp_rec, scattered);
+
+    let attenuation = spectrum::bounce_throughput(attenuation, &wl, &mut scattered);
+    emitted
+        + direct
+        + ray_color_spectral(scattered, lights, world, depth - 1, next_pdf) * attenuation
+}
+
+//value of --flag <value> or --flag=<value> on the command line
+fn cli_option(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        } else if let Some(value) = arg
+            .strip_prefix(flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
 }
 
 fn main() {
@@ -75,8 +215,12 @@ fn main() {
     let aspect_ratio = 1.0;
     let image_width: f64 = 800.0;
     let image_height: f64 = image_width / aspect_ratio;
-    let samples_per_pixel = 5000.0;
+    let samples_per_pixel: f64 = 5000.0;
     let max_depth = 50;
+    //photon mapping runs passes over the whole film instead of samples per pixel
+    let sppm_iterations = 512;
+    let photons_per_iteration = 200_000;
+    let initial_radius = 0.25;
     //world
     /*
     let world = random_scene();
@@ -100,24 +244,23 @@ fn main() {
     );
     */
 
-    let world = my_scene();
-    let background = Vec3::new(0.0, 0.0, 0.0);
-
-    //camera
-    let lookfrom = Point3::new(30.0, 0.0, 50.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.0;
-    let cam = Camera::new(
-        (lookfrom, lookat),
-        vup,
-        55.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        (0.0, 1.0),
-    );
+    //without lights at infinity the background is black, add a UniformEnvironment, an EnvironmentMap or a PhysicalSky and its sun for sky light
+    //point, spot and directional lights only show up through the shadow rays cast at every bounce
+    //--scene, then the SCENE variable, my_scene by default
+    let scene_name = cli_option("--scene")
+        .or_else(|| std::env::var("SCENE").ok())
+        .unwrap_or_else(|| "default".to_string());
+    let scene::Scene {
+        world,
+        lights,
+        camera: cam,
+    } = scene::by_name(&scene_name, aspect_ratio).unwrap_or_else(|| {
+        panic!(
+            "unknown scene {}, expected one of {:?}",
+            scene_name,
+            scene::SCENE_NAMES
+        )
+    });
 
     //render
     let is_ci = match std::env::var("CI") {
@@ -127,30 +270,78 @@ fn main() {
 
     let (n_jobs, n_workers): (usize, usize) = if is_ci { (32, 4) } else { (16, 2) };
 
+    let is_spectral = match std::env::var("SPECTRAL") {
+        Ok(x) => x == "true",
+        Err(_) => false,
+    };
+
+    //SPECTRAL only applies to path and recursive, the other integrators render in rgb
+    //--integrator, then the INTEGRATOR variable, the iterative path tracer by default
+    let integrator_name = cli_option("--integrator")
+        .or_else(|| std::env::var("INTEGRATOR").ok())
+        .unwrap_or_else(|| "path".to_string());
+    let is_sppm = integrator_name == "sppm";
+
     println!(
-        "CI: {}, using {} jobs and {} workers",
-        is_ci, n_jobs, n_workers
+        "CI: {}, using {} jobs and {} workers, scene {}, integrator {}",
+        is_ci, n_jobs, n_workers, scene_name, integrator_name
     );
 
     let (tx, rx) = channel();
 
     let pool = ThreadPool::new(n_workers);
 
-    let bar = ProgressBar::new(n_jobs as u64);
+    let bar = ProgressBar::new(if is_sppm { sppm_iterations } else { n_jobs } as u64);
 
     let world = Arc::new(world);
+    let lights = Arc::new(lights);
+    let integrator: Option<Arc<dyn Integrator>> = if is_sppm {
+        None
+    } else {
+        Some(
+            integrator::by_name(
+                &integrator_name,
+                &cam,
+                &world,
+                &lights,
+                &IntegratorOptions {
+                    max_depth: max_depth as usize,
+                    film: (image_width, image_height),
+                    spectral: is_spectral,
+                    //bounces before russian roulette, and the cap on indirect light per sample
+                    rr_depth: cli_option("--rr-depth").map_or(3, |d| {
+                        d.parse().expect("--rr-depth takes a number of bounces")
+                    }),
+                    max_indirect: cli_option("--clamp")
+                        .map(|c| c.parse().expect("--clamp takes a radiance")),
+                },
+            )
+            .unwrap_or_else(|| {
+                panic!(
+                    "unknown integrator {}, expected one of {:?}",
+                    integrator_name, INTEGRATOR_NAMES
+                )
+            }),
+        )
+    };
 
-    for i in 0..n_jobs {
+    //the row jobs only run for integrators that take samples per pixel
+    let row_jobs = if is_sppm { 0 } else { n_jobs };
+
+    for i in 0..row_jobs {
         let tx = tx.clone();
-        let world_ptr = world.clone();
         let cam_ptr = cam.clone();
+        let integrator_ptr = integrator.clone().expect("sppm has no row jobs");
 
         pool.execute(move || {
             let mut rng = rand::thread_rng();
             let row_begin = image_height as usize * i / n_jobs;
             let row_end = image_height as usize * (i + 1) / n_jobs;
             let render_height = row_end - row_begin;
-            let mut img: RgbImage = ImageBuffer::new(image_width as u32, render_height as u32);
+            let mut img = vec![Color::zero(); image_width as usize * render_height];
+            //light tracing lands anywhere on the film
+            let mut film = Vec::new();
+            let mut splats = Vec::new();
 
             for x in 0..image_width as i32 {
                 for (img_y, y) in (row_begin..row_end).enumerate() {
@@ -161,35 +352,35 @@ fn main() {
                     while s_ < samples_per_pixel {
                         let u_ = (x as f64 + rng.gen::<f64>()) / (image_width - 1.0);
                         let v_ = (y as f64 + rng.gen::<f64>()) / (image_height - 1.0);
-                        let r_ = cam_ptr.get_ray(u_, v_);
-                        pixel_color += ray_color(r_, background, &world_ptr, max_depth);
+                        let r_ = cam_ptr
+                            .get_ray_differential(
+                                u_,
+                                v_,
+                                1.0 / (image_width - 1.0),
+                                1.0 / (image_height - 1.0),
+                            )
+                            .scale_differentials(1.0 / samples_per_pixel.sqrt());
+                        pixel_color += integrator_ptr.li(r_, &mut splats);
                         s_ += 1.0;
                     }
 
-                    //color_write
-                    let pixel = img.get_pixel_mut(x as u32, img_y as u32);
-
-                    let mut r_ = pixel_color.x;
-                    let mut g_ = pixel_color.y;
-                    let mut b_ = pixel_color.z;
-                    let scale = 1.0 / samples_per_pixel;
-                    r_ = (scale * r_).sqrt();
-                    g_ = (scale * g_).sqrt();
-                    b_ = (scale * b_).sqrt();
-                    r_ = rtweekend::clamp(r_, 0.0, 0.999);
-                    g_ = rtweekend::clamp(g_, 0.0, 0.999);
-                    b_ = rtweekend::clamp(b_, 0.0, 0.999);
-                    let r_ = r_ * 255.999;
-                    let g_ = g_ * 255.999;
-                    let b_ = b_ * 255.999;
-                    let r_ = r_ as i64;
-                    let g_ = g_ as i64;
-                    let b_ = b_ as i64;
-
-                    *pixel = image::Rgb([r_ as u8, g_ as u8, b_ as u8]);
+                    img[img_y * image_width as usize + x as usize] = pixel_color;
+
+                    if !splats.is_empty() {
+                        if film.is_empty() {
+                            film = vec![Color::zero(); (image_width * image_height) as usize];
+                        }
+                        for (s, t, c) in splats.drain(..) {
+                            let fx = (s * (image_width - 1.0)) as usize;
+                            let fy = (t * (image_height - 1.0)) as usize;
+                            if fx < image_width as usize && fy < image_height as usize {
+                                film[fy * image_width as usize + fx] += c;
+                            }
+                        }
+                    }
                 }
             }
-            tx.send((row_begin..row_end, img))
+            tx.send((row_begin..row_end, img, film))
                 .expect("failed to send result");
         });
     }
@@ -239,19 +430,64 @@ fn main() {
     bar.finish();
      */
 
-    let mut img: RgbImage = ImageBuffer::new(image_width as u32, image_height as u32);
+    let mut sums = vec![Color::zero(); (image_width * image_height) as usize];
+    let mut scale = 1.0 / samples_per_pixel;
+
+    if is_sppm {
+        let sppm = Sppm::new(
+            cam,
+            world,
+            lights,
+            max_depth as usize,
+            photons_per_iteration,
+            initial_radius,
+        );
+        sums = sppm.render(
+            &pool,
+            n_jobs,
+            (image_width as usize, image_height as usize),
+            sppm_iterations,
+            &bar,
+        );
+        scale = 1.0;
+    }
 
-    for (rows, data) in rx.iter().take(n_jobs) {
+    for (rows, data, film) in rx.iter().take(row_jobs) {
         for (idx, row) in rows.enumerate() {
-            for col in 0..image_width as i32 {
-                let row = row as u32;
-                let idx = idx as u32;
-                *img.get_pixel_mut(col as u32, row) = *data.get_pixel(col as u32, idx);
+            for col in 0..image_width as usize {
+                sums[row * image_width as usize + col] += data[idx * image_width as usize + col];
             }
         }
+        for (sum, splat) in sums.iter_mut().zip(film.iter()) {
+            *sum += *splat;
+        }
         bar.inc(1);
     }
 
+    let mut img: RgbImage = ImageBuffer::new(image_width as u32, image_height as u32);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        //color_write
+        let pixel_color = sums[y as usize * image_width as usize + x as usize];
+
+        let mut r_ = pixel_color.x;
+        let mut g_ = pixel_color.y;
+        let mut b_ = pixel_color.z;
+        r_ = (scale * r_).sqrt();
+        g_ = (scale * g_).sqrt();
+        b_ = (scale * b_).sqrt();
+        r_ = rtweekend::clamp(r_, 0.0, 0.999);
+        g_ = rtweekend::clamp(g_, 0.0, 0.999);
+        b_ = rtweekend::clamp(b_, 0.0, 0.999);
+        let r_ = r_ * 255.999;
+        let g_ = g_ * 255.999;
+        let b_ = b_ * 255.999;
+        let r_ = r_ as i64;
+        let g_ = g_ as i64;
+        let b_ = b_ as i64;
+
+        *pixel = image::Rgb([r_ as u8, g_ as u8, b_ as u8]);
+    }
+
     img.save("output/test.jpg").unwrap();
     bar.finish();
 }
@@ -368,7 +604,8 @@ fn two_perlin_spheres() -> BvhNode {
 }
 */
 fn earth() -> HittableList {
-    let earth_texture = Arc::new(ImageTexture::new("earthmap.jpg"));
+    let earth_texture =
+        Arc::new(ImageTexture::new("earthmap.jpg").expect("failed to load earthmap.jpg"));
     let earth_surface = Arc::new(Lambertian::new_by_pointer(earth_texture.clone()));
     let mut objects = HittableList::new_default();
     objects.add(Arc::new(Sphere::new(
@@ -406,7 +643,7 @@ fn simple_light() -> BvhNode {
     BvhNode::new_(&mut objects, 0.0, 0.0)
 }
 
-fn cornell_box() -> BvhNode {
+fn cornell_box(lights: &mut LightList) -> BvhNode {
     let mut objects = HittableList::new_default();
     let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
     let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
@@ -429,14 +666,11 @@ fn cornell_box() -> BvhNode {
         0.0,
         red.clone(),
     )));
-    objects.add(Arc::new(XZRect::new(
-        213.0,
-        343.0,
-        227.0,
-        332.0,
-        554.0,
+    objects.add(lights.add_rect(
+        Point3::new(213.0, 554.0, 227.0),
+        Point3::new(343.0, 554.0, 332.0),
         light.clone(),
-    )));
+    ));
     objects.add(Arc::new(XZRect::new(
         0.0,
         555.0,
@@ -650,9 +884,9 @@ fn final_scene() -> HittableList {
         Color::new(1.0, 1.0, 1.0),
     )));
 
-    let emat = Arc::new(Lambertian::new_by_pointer(Arc::new(ImageTexture::new(
-        "earthmap.jpg",
-    ))));
+    let emat = Arc::new(Lambertian::new_by_pointer(Arc::new(
+        ImageTexture::new("earthmap.jpg").expect("failed to load earthmap.jpg"),
+    )));
     objects.add(Arc::new(Sphere::new(
         Point3::new(400.0, 200.0, 400.0),
         100.0,
@@ -687,13 +921,18 @@ fn final_scene() -> HittableList {
     objects
 }
 
-fn my_scene() -> HittableList {
+fn my_scene(lights: &mut LightList) -> HittableList {
     let mut objects = HittableList::new_default();
 
     //objects.add(Arc::new(Sphere::new(Point3::new(0.0,0.0,0.0),100.0,ground_material.clone())));
     //objects.add(Arc::new(Sphere::new(Point3::new(0.0,0.0,0.0),-90.0,ground_material)));
 
-    let star1 = Arc::new(DiffuseLight::new_by_color1());
+    let star1 = Arc::new(DiffuseLight::new(
+        Arc::new(
+            ImageTexture::new("raytracer/star1.jpg").expect("failed to load raytracer/star1.jpg"),
+        ),
+        1.5,
+    ));
     objects.add(Arc::new(_Box::new(
         Point3::new(-4.0, -7.0, -7.0),
         Point3::new(10.0, 7.0, 7.0),
@@ -706,42 +945,19 @@ fn my_scene() -> HittableList {
         Arc::new(Dielectric::new(3.0)),
     )));
 
-    let star3 = Arc::new(DiffuseLight::new_by_color3());
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(-14.0, 8.0, 10.0),
-        3.0,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(15.0, 7.0, -7.0),
-        2.0,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(18.0, 11.0, 18.0),
-        4.0,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(22.0, -13.0, 15.0),
+    let star3 = Arc::new(DiffuseLight::new(
+        Arc::new(
+            ImageTexture::new("raytracer/star3.jpeg").expect("failed to load raytracer/star3.jpeg"),
+        ),
         1.5,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(-10.0, -20.0, 17.0),
-        1.5,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(17.0, -17.0, 17.0),
-        0.5,
-        star3.clone(),
-    )));
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(-10.0, -9.0, -13.0),
-        2.0,
-        star3.clone(),
-    )));
+    ));
+    objects.add(lights.add_sphere(Point3::new(-14.0, 8.0, 10.0), 3.0, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(15.0, 7.0, -7.0), 2.0, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(18.0, 11.0, 18.0), 4.0, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(22.0, -13.0, 15.0), 1.5, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(-10.0, -20.0, 17.0), 1.5, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(17.0, -17.0, 17.0), 0.5, star3.clone()));
+    objects.add(lights.add_sphere(Point3::new(-10.0, -9.0, -13.0), 2.0, star3.clone()));
     /*
         let mut fogs = HittableList::new_default();
         let ns = 100;
@@ -796,13 +1012,19 @@ fn my_scene() -> HittableList {
         Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)),
     )));
 
-    let star5 = Arc::new(DiffuseLight::new_by_color5());
-    let star6 = Arc::new(DiffuseLight::new_by_color6());
-    objects.add(Arc::new(Sphere::new(
-        Point3::new(16.0, -10.0, 15.0),
-        5.0,
-        star5.clone(),
-    )));
+    let star5 = Arc::new(DiffuseLight::new(
+        Arc::new(
+            ImageTexture::new("raytracer/star5.jpeg").expect("failed to load raytracer/star5.jpeg"),
+        ),
+        0.5,
+    ));
+    let star6 = Arc::new(DiffuseLight::new(
+        Arc::new(
+            ImageTexture::new("raytracer/star6.jpeg").expect("failed to load raytracer/star6.jpeg"),
+        ),
+        0.5,
+    ));
+    objects.add(lights.add_sphere(Point3::new(16.0, -10.0, 15.0), 5.0, star5.clone()));
 
     let mut fogs = HittableList::new_default();
     let ns = 10000;
@@ -1,6 +1,10 @@
 use crate::hittable::HitRecord;
+use crate::ies::IesProfile;
+use crate::onb::Onb;
+use crate::rtweekend::{clamp, degrees_to_radians, PI};
+use crate::spectrum::blackbody_rgb;
+use crate::texture::SolidColor;
 use crate::texture::Texture;
-use crate::texture::{ImageTexture, SolidColor};
 use crate::vec3::Color;
 use crate::Ray;
 use crate::Vec3;
@@ -16,9 +20,19 @@ pub trait Material {
         scattered: &mut Ray,
     ) -> bool;
 
-    fn emitted(&self, _u: f64, _v: f64, _p: &Vec3) -> Color {
+    fn emitted(&self, _r_in: Ray, _rec: &HitRecord) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+
+    //bsdf times |cos| toward scattered, zero for materials that only scatter into delta directions
+    fn eval_bsdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> Color {
+        Color::zero()
+    }
+
+    //solid angle density with which scatter() picks the scattered direction
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
+        0.0
+    }
 }
 
 //Lambertian
@@ -53,9 +67,242 @@ impl Material for Lambertian {
         }
 
         *scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+        *attenuation = self.albedo.value_at(rec);
+        true
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.albedo.value_at(rec) * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let cosine = Vec3::dot(rec.normal, scattered.direction().unit());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+}
+
+//OrenNayar
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    //sigma is the standard deviation of the facet angle, in degrees
+    pub fn new(c: Color, sigma: f64) -> Self {
+        Self::new_by_pointer(Arc::new(SolidColor::new(c)), sigma)
+    }
+
+    pub fn new_by_pointer(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma2 = degrees_to_radians(sigma).powi(2);
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    //the bsdf without the albedo and the 1/pi
+    fn shape(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let max_cos = if sin_o > 1e-4 && sin_i > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs())
+        };
+        self.a + self.b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = Vec3::random_cosine_direction();
+        *scattered = Ray::new(rec.p, uvw.local(wi), r_in.time());
+        *attenuation = self.albedo.value_at(rec) * self.shape(wo, wi);
+        true
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = uvw.to_local(scattered.direction().unit());
+        if wi.z <= 0.0 {
+            return Color::zero();
+        }
+        self.albedo.value_at(rec) * (self.shape(wo, wi) * wi.z / PI)
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let cosine = Vec3::dot(rec.normal, scattered.direction().unit());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+}
+
+//Hapke, Lommel-Seeliger law with the opposition surge, for regolith like the moon
+pub struct Hapke {
+    albedo: Arc<dyn Texture>,
+    b0: f64,
+    h: f64,
+}
+
+impl Hapke {
+    pub fn new(c: Color) -> Self {
+        Self::new_by_pointer(Arc::new(SolidColor::new(c)))
+    }
+
+    pub fn new_by_pointer(albedo: Arc<dyn Texture>) -> Self {
+        Self::new_with_opposition(albedo, 1.0, 0.06)
+    }
+
+    //b0 is the amplitude of the backscatter peak, h its angular width
+    pub fn new_with_opposition(albedo: Arc<dyn Texture>, b0: f64, h: f64) -> Self {
+        Self { albedo, b0, h }
+    }
+
+    //the bsdf times pi, without the albedo
+    fn shape(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let mu0 = wi.z.max(0.0);
+        let mu = wo.z.max(0.0);
+        if mu0 + mu <= 0.0 {
+            return 0.0;
+        }
+        let g = clamp(Vec3::dot(wo, wi), -1.0, 1.0).acos();
+        let surge = self.b0 / (1.0 + (g / 2.0).tan() / self.h);
+        (1.0 + surge) / (4.0 * (mu0 + mu))
+    }
+}
+
+impl Material for Hapke {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = Vec3::random_cosine_direction();
+        *scattered = Ray::new(rec.p, uvw.local(wi), r_in.time());
+        *attenuation = self.albedo.value_at(rec) * self.shape(wo, wi);
+        true
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.to_local(-r_in.direction().unit());
+        let wi = uvw.to_local(scattered.direction().unit());
+        if wi.z <= 0.0 {
+            return Color::zero();
+        }
+        self.albedo.value_at(rec) * (self.shape(wo, wi) * wi.z / PI)
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let cosine = Vec3::dot(rec.normal, scattered.direction().unit());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
+    }
+}
+
+//Translucent, lambertian reflection plus lambertian transmission for thin surfaces
+pub struct Translucent {
+    reflectance: Arc<dyn Texture>,
+    transmittance: Arc<dyn Texture>,
+}
+
+impl Translucent {
+    pub fn new(r: Color, t: Color) -> Self {
+        Self::new_by_pointer(Arc::new(SolidColor::new(r)), Arc::new(SolidColor::new(t)))
+    }
+
+    pub fn new_by_pointer(r: Arc<dyn Texture>, t: Arc<dyn Texture>) -> Self {
+        Self {
+            reflectance: r,
+            transmittance: t,
+        }
+    }
+
+    fn reflect_probability(r: Color, t: Color) -> f64 {
+        let lr = r.x + r.y + r.z;
+        let lt = t.x + t.y + t.z;
+        if lr + lt <= 0.0 {
+            return 0.5;
+        }
+        lr / (lr + lt)
+    }
+}
+
+impl Material for Translucent {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let r = self.reflectance.value_at(rec);
+        let t = self.transmittance.value_at(rec);
+        let pr = Translucent::reflect_probability(r, t);
+        let mut rng = rand::thread_rng();
+        let (w, color, prob) = if rng.gen::<f64>() < pr {
+            (rec.normal, r, pr)
+        } else {
+            (-rec.normal, t, 1.0 - pr)
+        };
+        if prob <= 0.0 {
+            return false;
+        }
+        let direction = Onb::build_from_w(w).local(Vec3::random_cosine_direction());
+        *scattered = Ray::new(rec.p, direction, r_in.time());
+        *attenuation = color / prob;
         true
     }
+
+    fn eval_bsdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let cosine = Vec3::dot(rec.normal, scattered.direction().unit());
+        let color = if cosine > 0.0 {
+            self.reflectance.value_at(rec)
+        } else {
+            self.transmittance.value_at(rec)
+        };
+        color * (cosine.abs() / PI)
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        let r = self.reflectance.value_at(rec);
+        let t = self.transmittance.value_at(rec);
+        let pr = Translucent::reflect_probability(r, t);
+        let cosine = Vec3::dot(rec.normal, scattered.direction().unit());
+        let prob = if cosine > 0.0 { pr } else { 1.0 - pr };
+        prob * cosine.abs() / PI
+    }
 }
 
 //metal
@@ -75,6 +322,10 @@ impl Metal {
             fuzz: f1,
         }
     }
+
+    pub fn albedo(&self) -> Color {
+        self.albedo
+    }
 }
 
 impl Material for Metal {
@@ -91,19 +342,81 @@ impl Material for Metal {
             reflected + Vec3::random_in_unit_sphere() * self.fuzz,
             r_in.time(),
         );
+        if let Some(diff) = rec.reflected_differentials(&r_in, reflected) {
+            *scattered = scattered.with_differentials(diff);
+        }
         *attenuation = self.albedo;
         Vec3::dot(scattered.direction(), rec.normal) > 0.0
     }
 }
 
 //dielectric
+#[derive(Clone, Copy)]
+pub enum Ior {
+    Constant(f64),
+    //n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    //n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i)), lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    //sodium D line, used when a ray carries no wavelength
+    pub const REFERENCE_LAMBDA: f64 = 589.3;
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l = lambda * 0.001;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / (l * l),
+            Ior::Sellmeier { b, c } => {
+                let mut n2 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * l * l / (l * l - c[i]);
+                }
+                n2.sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(*self, Ior::Constant(_))
+    }
+}
+
 pub struct Dielectric {
-    ir: f64,
+    ir: Ior,
 }
 
 impl Dielectric {
     pub fn new(i: f64) -> Dielectric {
-        Dielectric { ir: i }
+        Dielectric {
+            ir: Ior::Constant(i),
+        }
+    }
+
+    pub fn new_cauchy(a: f64, b: f64) -> Dielectric {
+        Dielectric {
+            ir: Ior::Cauchy { a, b },
+        }
+    }
+
+    pub fn new_sellmeier(b: [f64; 3], c: [f64; 3]) -> Dielectric {
+        Dielectric {
+            ir: Ior::Sellmeier { b, c },
+        }
+    }
+
+    //Schott N-BK7 crown glass
+    pub fn new_bk7() -> Dielectric {
+        Dielectric::new_sellmeier(
+            [1.03961212, 0.231792344, 1.01046945],
+            [0.00600069867, 0.0200179144, 103.560653],
+        )
+    }
+
+    pub fn ior(&self) -> Ior {
+        self.ir
     }
 
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -122,11 +435,13 @@ impl Material for Dielectric {
         scattered: &mut Ray,
     ) -> bool {
         *attenuation = Color::ones();
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        let (ir, wavelengths) = match r_in.wavelengths() {
+            Some(wl) if self.ir.is_dispersive() => {
+                (self.ir.at(wl.hero()), Some(wl.terminate_secondary()))
+            }
+            wl => (self.ir.at(Ior::REFERENCE_LAMBDA), wl),
         };
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
         let unit_direction = r_in.direction().unit();
 
         let mut cos_theta = Vec3::dot(-unit_direction, rec.normal);
@@ -138,53 +453,66 @@ impl Material for Dielectric {
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
         let direction;
+        let diff;
         let mut rng = rand::thread_rng();
         if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > rng.gen::<f64>()
         {
             direction = Vec3::reflect(unit_direction, rec.normal);
+            diff = rec.reflected_differentials(&r_in, direction);
         } else {
             direction = Vec3::refract(unit_direction, rec.normal, refraction_ratio);
+            diff = rec.refracted_differentials(&r_in, direction, refraction_ratio);
         }
         *scattered = Ray::new(rec.p, direction, r_in.time());
+        if let Some(diff) = diff {
+            *scattered = scattered.with_differentials(diff);
+        }
+        if let Some(wl) = wavelengths {
+            *scattered = scattered.with_wavelengths(wl);
+        }
         true
     }
 }
 
+//emit is scaled by intensity, a radiance scale that reads as nits for a texture of unit luminance
 pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
-    k: f64,
+    intensity: f64,
+    pub two_sided: bool,
+    //angular multiplier with its nadir along the surface normal and 0 degrees along dpdu
+    pub profile: Option<Arc<IesProfile>>,
 }
 
 impl DiffuseLight {
-    pub fn new_by_color(c: Color) -> Self {
+    pub fn new(emit: Arc<dyn Texture>, intensity: f64) -> Self {
         Self {
-            emit: Arc::new(SolidColor::new(c)),
-            k: 1.0,
+            emit,
+            intensity,
+            two_sided: true,
+            profile: None,
         }
     }
-    pub fn new_by_color1() -> Self {
-        Self {
-            emit: Arc::new(ImageTexture::new("raytracer/star1.jpg")),
-            k: 1.5,
-        }
+    pub fn new_by_color(c: Color) -> Self {
+        Self::new(Arc::new(SolidColor::new(c)), 1.0)
     }
-    pub fn new_by_color3() -> Self {
-        Self {
-            emit: Arc::new(ImageTexture::new("raytracer/star3.jpeg")),
-            k: 1.5,
-        }
+    pub fn new_by_pointer(emit: Arc<dyn Texture>) -> Self {
+        Self::new(emit, 1.0)
     }
-    pub fn new_by_color5() -> Self {
+    //total emitted power of an emitter of the given area, for a texture of unit luminance
+    pub fn new_with_power(emit: Arc<dyn Texture>, watts: f64, area: f64, two_sided: bool) -> Self {
+        let sides = if two_sided { 2.0 } else { 1.0 };
         Self {
-            emit: Arc::new(ImageTexture::new("raytracer/star5.jpeg")),
-            k: 0.5,
+            emit,
+            intensity: watts / (sides * PI * area),
+            two_sided,
+            profile: None,
         }
     }
-    pub fn new_by_color6() -> Self {
-        Self {
-            emit: Arc::new(ImageTexture::new("raytracer/star6.jpeg")),
-            k: 0.5,
-        }
+    pub fn new_blackbody(temperature: f64, intensity: f64) -> Self {
+        Self::new(
+            Arc::new(SolidColor::new(blackbody_rgb(temperature))),
+            intensity,
+        )
     }
 }
 
@@ -198,8 +526,16 @@ impl Material for DiffuseLight {
     ) -> bool {
         false
     }
-    fn emitted(&self, u_: f64, v_: f64, p_: &Vec3) -> Color {
-        self.emit.value(u_, v_, *p_) * self.k
+    fn emitted(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        if !self.two_sided && !rec.front_face {
+            return Color::zero();
+        }
+        let mut intensity = self.intensity;
+        if let Some(profile) = &self.profile {
+            let frame = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+            intensity *= profile.radiance_scale(frame.to_local(-r_in.direction()));
+        }
+        self.emit.value_at(rec) * intensity
     }
 }
 
@@ -224,8 +560,256 @@ impl Material for Isotropic {
         attenuation: &mut Vec3,
         scattered: &mut Ray,
     ) -> bool {
-        *scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time());
-        *attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+        *scattered = Ray::new(rec.p, Vec3::random_unit_vector(), r_in.time());
+        *attenuation = self.albedo.value_at(rec);
         true
     }
+
+    //phase functions carry no cosine
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.albedo.value_at(rec) * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering_pdf(&self, _r_in: Ray, _rec: &HitRecord, _scattered: Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+//scatters from one lobe of a mix, directions from a delta lobe are marked so the mix reports no density for them
+fn scatter_lobe(
+    lobe: &Arc<dyn Material>,
+    r_in: Ray,
+    rec: &HitRecord,
+    attenuation: &mut Color,
+    scattered: &mut Ray,
+) -> bool {
+    if !lobe.scatter(r_in, rec, attenuation, scattered) {
+        return false;
+    }
+    if lobe.scattering_pdf(r_in, rec, *scattered) <= 0.0 {
+        *scattered = scattered.with_delta();
+    }
+    true
+}
+
+//MixMaterial, picks b with the probability given by the first channel of the mask
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    mask: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, mask: Arc<dyn Texture>) -> Self {
+        Self { a, b, mask }
+    }
+
+    pub fn new_by_ratio(a: Arc<dyn Material>, b: Arc<dyn Material>, ratio: f64) -> Self {
+        Self::new(a, b, Arc::new(SolidColor::new_by_value(ratio)))
+    }
+
+    fn ratio(&self, rec: &HitRecord) -> f64 {
+        clamp(self.mask.value_at(rec).x, 0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f64>() < self.ratio(rec) {
+            scatter_lobe(&self.b, r_in, rec, attenuation, scattered)
+        } else {
+            scatter_lobe(&self.a, r_in, rec, attenuation, scattered)
+        }
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        let t = self.ratio(rec);
+        self.a.emitted(r_in, rec) * (1.0 - t) + self.b.emitted(r_in, rec) * t
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let t = self.ratio(rec);
+        self.a.eval_bsdf(r_in, rec, scattered) * (1.0 - t)
+            + self.b.eval_bsdf(r_in, rec, scattered) * t
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        if scattered.is_delta() {
+            return 0.0;
+        }
+        let t = self.ratio(rec);
+        self.a.scattering_pdf(r_in, rec, scattered) * (1.0 - t)
+            + self.b.scattering_pdf(r_in, rec, scattered) * t
+    }
+}
+
+//FresnelMixMaterial, picks the coat with the Schlick reflectance of the view angle
+pub struct FresnelMixMaterial {
+    base: Arc<dyn Material>,
+    coat: Arc<dyn Material>,
+    ior: f64,
+}
+
+impl FresnelMixMaterial {
+    pub fn new(base: Arc<dyn Material>, coat: Arc<dyn Material>, ior: f64) -> Self {
+        Self { base, coat, ior }
+    }
+
+    fn ratio(&self, cosine: f64) -> f64 {
+        Dielectric::reflectance(clamp(cosine, 0.0, 1.0), self.ior)
+    }
+
+    fn ratio_at(&self, r_in: Ray, rec: &HitRecord) -> f64 {
+        self.ratio(Vec3::dot(-r_in.direction().unit(), rec.normal))
+    }
+}
+
+impl Material for FresnelMixMaterial {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f64>() < self.ratio_at(r_in, rec) {
+            scatter_lobe(&self.coat, r_in, rec, attenuation, scattered)
+        } else {
+            scatter_lobe(&self.base, r_in, rec, attenuation, scattered)
+        }
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        let t = self.ratio_at(r_in, rec);
+        self.base.emitted(r_in, rec) * (1.0 - t) + self.coat.emitted(r_in, rec) * t
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        let t = self.ratio_at(r_in, rec);
+        self.base.eval_bsdf(r_in, rec, scattered) * (1.0 - t)
+            + self.coat.eval_bsdf(r_in, rec, scattered) * t
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        if scattered.is_delta() {
+            return 0.0;
+        }
+        let t = self.ratio_at(r_in, rec);
+        self.base.scattering_pdf(r_in, rec, scattered) * (1.0 - t)
+            + self.coat.scattering_pdf(r_in, rec, scattered) * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        rec
+    }
+
+    #[test]
+    fn test_mix_reports_no_density_for_delta_lobe() {
+        let mix = MixMaterial::new_by_ratio(
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0)),
+            0.5,
+        );
+        let rec = record();
+        let r_in = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        let mirror = Vec3::new(1.0, 1.0, 0.0).unit();
+        let (mut delta, mut diffuse) = (0, 0);
+        for _ in 0..1000 {
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !mix.scatter(r_in, &rec, &mut attenuation, &mut scattered) {
+                continue;
+            }
+            let pdf = mix.scattering_pdf(r_in, &rec, scattered);
+            if (scattered.direction().unit() - mirror).length() < 1e-9 {
+                assert_eq!(pdf, 0.0);
+                delta += 1;
+            } else {
+                assert!(pdf > 0.0);
+                diffuse += 1;
+            }
+        }
+        assert!(delta > 0 && diffuse > 0);
+
+        //directions that didn't come from scatter() only see the diffuse lobe
+        let light = Ray::new(rec.p, mirror, 0.0);
+        let lambertian = Lambertian::new(Color::zero()).scattering_pdf(r_in, &rec, light);
+        assert!((mix.scattering_pdf(r_in, &rec, light) - 0.5 * lambertian).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_isotropic_phase_function() {
+        let albedo = Color::new(0.2, 0.4, 0.8);
+        let medium = Isotropic::new_by_color(albedo);
+        let mut rec = record();
+        rec.normal = Vec3::zero();
+        let r_in = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        for _ in 0..100 {
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            assert!(medium.scatter(r_in, &rec, &mut attenuation, &mut scattered));
+            assert!((scattered.direction().length() - 1.0).abs() < 1e-9);
+            let pdf = medium.scattering_pdf(r_in, &rec, scattered);
+            assert!((pdf - 1.0 / (4.0 * PI)).abs() < 1e-12);
+            let f = medium.eval_bsdf(r_in, &rec, scattered);
+            assert!((f / pdf - attenuation).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_smooth_oren_nayar_is_lambertian() {
+        let albedo = Color::new(0.6, 0.5, 0.4);
+        let rough = OrenNayar::new(albedo, 0.0);
+        let rec = record();
+        let r_in = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        let scattered = Ray::new(Vec3::zero(), Vec3::new(0.3, 0.8, -0.5), 0.0);
+        let cosine = scattered.direction().unit().y;
+        let f = rough.eval_bsdf(r_in, &rec, scattered);
+        assert!((f - albedo * (cosine / PI)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_translucent_keeps_reflected_and_transmitted_energy() {
+        let translucent = Translucent::new(Color::new(0.6, 0.2, 0.1), Color::new(0.1, 0.3, 0.2));
+        let rec = record();
+        let r_in = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let n = 100_000;
+        let mut sum = Color::zero();
+        for _ in 0..n {
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            assert!(translucent.scatter(r_in, &rec, &mut attenuation, &mut scattered));
+            sum += attenuation;
+        }
+        assert!((sum / n as f64 - Color::new(0.7, 0.5, 0.3)).length() < 0.01);
+    }
+
+    #[test]
+    fn test_emitter_power_spreads_over_area_and_sides() {
+        let rec = record();
+        let r_in = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let white = Arc::new(SolidColor::new_by_value(1.0));
+        for two_sided in [false, true].iter() {
+            let light = DiffuseLight::new_with_power(white.clone(), 100.0, 4.0, *two_sided);
+            let sides = if *two_sided { 2.0 } else { 1.0 };
+            let radiance = light.emitted(r_in, &rec).y;
+            assert!((radiance * PI * 4.0 * sides - 100.0).abs() < 1e-9);
+        }
+    }
 }
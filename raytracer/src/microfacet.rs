@@ -0,0 +1,162 @@
+use crate::rtweekend::{clamp, PI};
+use crate::Vec3;
+
+//all directions below are in the local shading frame, the normal is +z
+
+pub fn schlick_weight(cosine: f64) -> f64 {
+    let m = clamp(1.0 - cosine, 0.0, 1.0);
+    m.powi(5)
+}
+
+//unpolarized Fresnel reflectance, eta is n_transmitted / n_incident
+pub fn fr_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+pub fn reflect(wo: Vec3, n: Vec3) -> Vec3 {
+    -wo + n * 2.0 * Vec3::dot(wo, n)
+}
+
+//refracts wo through the interface with normal n on the side of wo, None on total internal reflection
+pub fn refract(wo: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + n * (cos_i / eta - cos_t))
+}
+
+//GGX / Trowbridge-Reitz
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Ggx {
+    ax: f64,
+    ay: f64,
+}
+
+impl Ggx {
+    pub fn new(ax: f64, ay: f64) -> Self {
+        Self {
+            ax: ax.max(0.001),
+            ay: ay.max(0.001),
+        }
+    }
+
+    pub fn from_roughness(roughness: f64, anisotropic: f64) -> Self {
+        let aspect = (1.0 - 0.9 * clamp(anisotropic, 0.0, 1.0)).sqrt();
+        let r2 = roughness * roughness;
+        Self::new(r2 / aspect, r2 * aspect)
+    }
+
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let t = (h.x / self.ax).powi(2) + (h.y / self.ay).powi(2) + h.z * h.z;
+        1.0 / (PI * self.ax * self.ay * t * t)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        let tan2 = ((self.ax * w.x).powi(2) + (self.ay * w.y).powi(2)) / (w.z * w.z);
+        ((1.0 + tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    //visible normal sampling (Heitz 2018), wo must be in the upper hemisphere
+    pub fn sample_h(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let vh = Vec3::new(self.ax * wo.x, self.ay * wo.y, wo.z).unit();
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(vh, t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        Vec3::new(self.ax * nh.x, self.ay * nh.y, nh.z.max(1e-6)).unit()
+    }
+
+    pub fn pdf_h(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * Vec3::dot(wo, h).max(0.0) * self.d(h) / wo.z
+    }
+}
+
+//GTR1, used by clearcoat
+pub fn gtr1_d(h: Vec3, a: f64) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    if a >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = a * a;
+    let t = 1.0 + (a2 - 1.0) * h.z * h.z;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+pub fn sample_gtr1(a: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = a * a;
+    let cos_theta = if a >= 1.0 {
+        (1.0 - u1).sqrt()
+    } else {
+        ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt()
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::uniform_sphere;
+    use rand::Rng;
+
+    #[test]
+    fn test_ggx_microfacets_cover_unit_area() {
+        let mut rng = rand::thread_rng();
+        let ggx = Ggx::new(0.3, 0.6);
+        let n = 400_000;
+        //projected microfacet area integrates to one over the hemisphere
+        let sum: f64 = (0..n)
+            .map(|_| {
+                let h = uniform_sphere(rng.gen::<f64>(), rng.gen::<f64>());
+                ggx.d(h) * h.z.max(0.0) * 4.0 * PI
+            })
+            .sum();
+        assert!((sum / n as f64 - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_fresnel_at_normal_incidence() {
+        assert!((fr_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fr_dielectric(0.1, 1.0 / 1.5), 1.0);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::rtweekend::clamp;
+
+const MAX_ANISOTROPY: f64 = 8.0;
+
+//image textures default to clamped trilinear lookups, the other modes are set through their pub fields
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MipFilter {
+    None,
+    Trilinear,
+    Ewa,
+}
+
+pub type Texel = [f64; 4];
+
+fn add(a: Texel, b: Texel) -> Texel {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]
+}
+
+fn scale(a: Texel, k: f64) -> Texel {
+    [a[0] * k, a[1] * k, a[2] * k, a[3] * k]
+}
+
+fn lerp(a: Texel, b: Texel, t: f64) -> Texel {
+    add(scale(a, 1.0 - t), scale(b, t))
+}
+
+struct Level {
+    width: usize,
+    height: usize,
+    //RGBA, rows from the top
+    data: Vec<f32>,
+}
+
+impl Level {
+    fn texel(&self, i: usize, j: usize) -> Texel {
+        let offset = (j * self.width + i) * 4;
+        [
+            self.data[offset] as f64,
+            self.data[offset + 1] as f64,
+            self.data[offset + 2] as f64,
+            self.data[offset + 3] as f64,
+        ]
+    }
+
+    //box filtered half resolution level
+    fn downsample(&self) -> Level {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut data = Vec::with_capacity(width * height * 4);
+        for j in 0..height {
+            for i in 0..width {
+                let mut sum = [0.0; 4];
+                for (di, dj) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                    let x = (2 * i + di).min(self.width - 1);
+                    let y = (2 * j + dj).min(self.height - 1);
+                    sum = add(sum, self.texel(x, y));
+                }
+                for c in sum.iter() {
+                    data.push((c / 4.0) as f32);
+                }
+            }
+        }
+        Level {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+pub struct MipMap {
+    levels: Vec<Level>,
+}
+
+impl MipMap {
+    pub fn new(width: usize, height: usize, data: Vec<f32>) -> Self {
+        let mut levels = vec![Level {
+            width,
+            height,
+            data,
+        }];
+        loop {
+            let last = levels.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    #[cfg(test)]
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    fn wrap_index(i: i64, n: usize, wrap: WrapMode) -> usize {
+        let n = n as i64;
+        let k = match wrap {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+            WrapMode::Clamp => i.max(0).min(n - 1),
+        };
+        k as usize
+    }
+
+    pub fn texel(&self, level: usize, i: i64, j: i64, wrap: WrapMode) -> Texel {
+        let l = &self.levels[level.min(self.levels.len() - 1)];
+        l.texel(
+            MipMap::wrap_index(i, l.width, wrap),
+            MipMap::wrap_index(j, l.height, wrap),
+        )
+    }
+
+    fn level_size(&self, level: usize) -> (f64, f64) {
+        let l = &self.levels[level.min(self.levels.len() - 1)];
+        (l.width as f64, l.height as f64)
+    }
+
+    //s and t in 0..1, t counted from the top row
+    pub fn nearest(&self, level: usize, s: f64, t: f64, wrap: WrapMode) -> Texel {
+        let (w, h) = self.level_size(level);
+        self.texel(level, (s * w).floor() as i64, (t * h).floor() as i64, wrap)
+    }
+
+    pub fn bilinear(&self, level: usize, s: f64, t: f64, wrap: WrapMode) -> Texel {
+        let (w, h) = self.level_size(level);
+        let x = s * w - 0.5;
+        let y = t * h - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (i, j) = (x0 as i64, y0 as i64);
+        lerp(
+            lerp(
+                self.texel(level, i, j, wrap),
+                self.texel(level, i + 1, j, wrap),
+                dx,
+            ),
+            lerp(
+                self.texel(level, i, j + 1, wrap),
+                self.texel(level, i + 1, j + 1, wrap),
+                dx,
+            ),
+            dy,
+        )
+    }
+
+    fn catmull_rom_weights(t: f64) -> [f64; 4] {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        [
+            -0.5 * t3 + t2 - 0.5 * t,
+            1.5 * t3 - 2.5 * t2 + 1.0,
+            -1.5 * t3 + 2.0 * t2 + 0.5 * t,
+            0.5 * t3 - 0.5 * t2,
+        ]
+    }
+
+    pub fn bicubic(&self, level: usize, s: f64, t: f64, wrap: WrapMode) -> Texel {
+        let (w, h) = self.level_size(level);
+        let x = s * w - 0.5;
+        let y = t * h - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let wx = MipMap::catmull_rom_weights(x - x0);
+        let wy = MipMap::catmull_rom_weights(y - y0);
+        let mut sum = [0.0; 4];
+        for (b, weight_y) in wy.iter().enumerate() {
+            for (a, weight_x) in wx.iter().enumerate() {
+                let texel = self.texel(
+                    level,
+                    x0 as i64 - 1 + a as i64,
+                    y0 as i64 - 1 + b as i64,
+                    wrap,
+                );
+                sum = add(sum, scale(texel, weight_x * weight_y));
+            }
+        }
+        //the negative lobes can overshoot on sharp edges
+        for c in sum.iter_mut() {
+            *c = c.max(0.0);
+        }
+        sum
+    }
+
+    pub fn filtered(&self, level: usize, s: f64, t: f64, filter: Filter, wrap: WrapMode) -> Texel {
+        match filter {
+            Filter::Nearest => self.nearest(level, s, t, wrap),
+            Filter::Bilinear => self.bilinear(level, s, t, wrap),
+            Filter::Bicubic => self.bicubic(level, s, t, wrap),
+        }
+    }
+
+    //width is the filter width in texture space, 0 samples the finest level
+    pub fn trilinear(&self, s: f64, t: f64, width: f64, filter: Filter, wrap: WrapMode) -> Texel {
+        let n = self.levels.len();
+        let level = n as f64 - 1.0 + width.max(1e-8).log2();
+        if level <= 0.0 {
+            return self.filtered(0, s, t, filter, wrap);
+        }
+        if level >= (n - 1) as f64 {
+            return self.texel(n - 1, 0, 0, wrap);
+        }
+        let i = level.floor();
+        lerp(
+            self.filtered(i as usize, s, t, filter, wrap),
+            self.filtered(i as usize + 1, s, t, filter, wrap),
+            level - i,
+        )
+    }
+
+    //elliptically weighted average over the footprint spanned by the two texture space differentials
+    pub fn ewa(
+        &self,
+        s: f64,
+        t: f64,
+        mut dst0: (f64, f64),
+        mut dst1: (f64, f64),
+        wrap: WrapMode,
+    ) -> Texel {
+        let length = |d: (f64, f64)| (d.0 * d.0 + d.1 * d.1).sqrt();
+        if length(dst0) < length(dst1) {
+            std::mem::swap(&mut dst0, &mut dst1);
+        }
+        let major = length(dst0);
+        let mut minor = length(dst1);
+
+        if minor * MAX_ANISOTROPY < major && minor > 0.0 {
+            let k = major / (minor * MAX_ANISOTROPY);
+            dst1 = (dst1.0 * k, dst1.1 * k);
+            minor *= k;
+        }
+        if minor == 0.0 {
+            return self.bilinear(0, s, t, wrap);
+        }
+
+        let n = self.levels.len();
+        let lod = (n as f64 - 1.0 + minor.log2()).max(0.0);
+        let i = lod.floor();
+        if i as usize >= n - 1 {
+            return self.texel(n - 1, 0, 0, wrap);
+        }
+        lerp(
+            self.ewa_level(i as usize, s, t, dst0, dst1, wrap),
+            self.ewa_level(i as usize + 1, s, t, dst0, dst1, wrap),
+            lod - i,
+        )
+    }
+
+    fn ewa_level(
+        &self,
+        level: usize,
+        s: f64,
+        t: f64,
+        dst0: (f64, f64),
+        dst1: (f64, f64),
+        wrap: WrapMode,
+    ) -> Texel {
+        let (w, h) = self.level_size(level);
+        let s = s * w - 0.5;
+        let t = t * h - 0.5;
+        let dst0 = (dst0.0 * w, dst0.1 * h);
+        let dst1 = (dst1.0 * w, dst1.1 * h);
+
+        //implicit ellipse a s^2 + b s t + c t^2 = 1
+        let mut a = dst0.1 * dst0.1 + dst1.1 * dst1.1 + 1.0;
+        let mut b = -2.0 * (dst0.0 * dst0.1 + dst1.0 * dst1.1);
+        let mut c = dst0.0 * dst0.0 + dst1.0 * dst1.0 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+        let s0 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        let mut sum = [0.0; 4];
+        let mut weight_sum = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f64 - t;
+            for is in s0..=s1 {
+                let ss = is as f64 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-2.0 * r2).exp() - (-2.0_f64).exp();
+                    sum = add(sum, scale(self.texel(level, is, it, wrap), weight));
+                    weight_sum += weight;
+                }
+            }
+        }
+        if weight_sum <= 0.0 {
+            return self.bilinear(level, s / w, t / h, wrap);
+        }
+        let tmp = scale(sum, 1.0 / weight_sum);
+        [tmp[0], tmp[1], tmp[2], clamp(tmp[3], 0.0, 1.0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //a gray ramp, texel i holds i in every channel
+    fn ramp(width: usize) -> MipMap {
+        let data = (0..width).flat_map(|i| vec![i as f32; 4]).collect();
+        MipMap::new(width, 1, data)
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        assert_eq!(MipMap::wrap_index(-1, 4, WrapMode::Repeat), 3);
+        assert_eq!(MipMap::wrap_index(5, 4, WrapMode::Repeat), 1);
+        assert_eq!(MipMap::wrap_index(-1, 4, WrapMode::Mirror), 0);
+        assert_eq!(MipMap::wrap_index(5, 4, WrapMode::Mirror), 2);
+        assert_eq!(MipMap::wrap_index(-1, 4, WrapMode::Clamp), 0);
+        assert_eq!(MipMap::wrap_index(5, 4, WrapMode::Clamp), 3);
+    }
+
+    #[test]
+    fn test_filters_pass_through_texel_centers() {
+        let map = ramp(4);
+        let center = 1.5 / 4.0;
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::Bicubic].iter() {
+            let texel = map.filtered(0, center, 0.5, *filter, WrapMode::Clamp);
+            assert!((texel[0] - 1.0).abs() < 1e-12);
+        }
+        let between = map.bilinear(0, 0.5, 0.5, WrapMode::Clamp);
+        assert!((between[0] - 1.5).abs() < 1e-12);
+        let wrapped = map.bilinear(0, 0.0, 0.5, WrapMode::Repeat);
+        assert!((wrapped[0] - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_levels_average_down_to_one_texel() {
+        let map = ramp(4);
+        assert_eq!((map.width(), map.height(), map.levels()), (4, 1, 3));
+        assert!((map.texel(2, 0, 0, WrapMode::Clamp)[0] - 1.5).abs() < 1e-6);
+        //a footprint wider than the image sees the average
+        let wide = map.trilinear(0.3, 0.5, 4.0, Filter::Bilinear, WrapMode::Clamp);
+        assert!((wide[0] - 1.5).abs() < 1e-6);
+        let ewa = map.ewa(0.3, 0.5, (4.0, 0.0), (0.0, 4.0), WrapMode::Clamp);
+        assert!((ewa[0] - 1.5).abs() < 1e-6);
+    }
+}
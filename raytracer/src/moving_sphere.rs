@@ -1,5 +1,6 @@
 use crate::aabb::AABB;
 use crate::material::Material;
+use crate::sphere::Sphere;
 use crate::vec3::Point3;
 use crate::HitRecord;
 use crate::Vec3;
@@ -62,8 +63,16 @@ impl Hittable for MovingSphere {
 
         rec.t = root;
         rec.p = r.at(rec.t);
+        //follow the sphere so object space textures move with it
+        rec.p_object = rec.p - (self.center(r.time()) - self.center0);
         let mut outward_normal = (rec.p - self.center(r.time())) / self.radius;
         rec.set_face_normal(&r, &mut outward_normal);
+        Sphere::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
+        let (dpdu, dpdv) = Sphere::get_sphere_partials(outward_normal, self.radius);
+        rec.dpdu = dpdu;
+        rec.dpdv = dpdv;
+        rec.dndu = dpdu / self.radius;
+        rec.dndv = dpdv / self.radius;
         rec.mat_ptr = self.mat_ptr.clone();
         true
     }
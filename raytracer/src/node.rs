@@ -0,0 +1,433 @@
+use crate::hittable::HitRecord;
+use crate::procedural::ColorRamp;
+use crate::rtweekend::clamp;
+use crate::texture::{lookup_point, Lookup, SolidColor, Texture};
+use crate::{Color, Vec3};
+use std::sync::Arc;
+
+//MixTexture
+//factor is read from the first channel, 0 gives a and 1 gives b
+pub struct MixTexture {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+    factor: Arc<dyn Texture>,
+}
+
+impl MixTexture {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>, factor: Arc<dyn Texture>) -> Self {
+        Self { a, b, factor }
+    }
+
+    pub fn new_by_ratio(a: Arc<dyn Texture>, b: Arc<dyn Texture>, ratio: f64) -> Self {
+        Self::new(a, b, Arc::new(SolidColor::new_by_value(ratio)))
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let t = clamp(at.scalar(&self.factor), 0.0, 1.0);
+        at.eval(&self.a) * (1.0 - t) + at.eval(&self.b) * t
+    }
+}
+
+impl Texture for MixTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//MultiplyTexture
+pub struct MultiplyTexture {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+}
+
+impl MultiplyTexture {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Self { a, b }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        at.eval(&self.a) * at.eval(&self.b)
+    }
+}
+
+impl Texture for MultiplyTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//AddTexture
+pub struct AddTexture {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+}
+
+impl AddTexture {
+    pub fn new(a: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Self { a, b }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        at.eval(&self.a) + at.eval(&self.b)
+    }
+}
+
+impl Texture for AddTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//InvertTexture
+pub struct InvertTexture {
+    input: Arc<dyn Texture>,
+}
+
+impl InvertTexture {
+    pub fn new(input: Arc<dyn Texture>) -> Self {
+        Self { input }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        Color::ones() - at.eval(&self.input)
+    }
+}
+
+impl Texture for InvertTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//ClampTexture
+pub struct ClampTexture {
+    input: Arc<dyn Texture>,
+    min: f64,
+    max: f64,
+}
+
+impl ClampTexture {
+    pub fn new(input: Arc<dyn Texture>, min: f64, max: f64) -> Self {
+        Self { input, min, max }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let c = at.eval(&self.input);
+        Color::new(
+            clamp(c.x, self.min, self.max),
+            clamp(c.y, self.min, self.max),
+            clamp(c.z, self.min, self.max),
+        )
+    }
+}
+
+impl Texture for ClampTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//RemapTexture
+//linearly maps from_min..from_max to to_min..to_max on every channel, without clamping
+pub struct RemapTexture {
+    input: Arc<dyn Texture>,
+    from: (f64, f64),
+    to: (f64, f64),
+}
+
+impl RemapTexture {
+    pub fn new(input: Arc<dyn Texture>, from: (f64, f64), to: (f64, f64)) -> Self {
+        Self { input, from, to }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let c = at.eval(&self.input);
+        let k = (self.to.1 - self.to.0) / (self.from.1 - self.from.0);
+        let remap = |x: f64| self.to.0 + (x - self.from.0) * k;
+        Color::new(remap(c.x), remap(c.y), remap(c.z))
+    }
+}
+
+impl Texture for RemapTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//RampTexture
+//colors the first channel of the input through a color ramp
+pub struct RampTexture {
+    input: Arc<dyn Texture>,
+    ramp: ColorRamp,
+}
+
+impl RampTexture {
+    pub fn new(input: Arc<dyn Texture>, ramp: ColorRamp) -> Self {
+        Self { input, ramp }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        self.ramp.at(at.scalar(&self.input))
+    }
+}
+
+impl Texture for RampTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//HsvTexture
+//hue_shift is in turns, saturation and value are multipliers
+pub struct HsvTexture {
+    input: Arc<dyn Texture>,
+    hue_shift: f64,
+    saturation: f64,
+    value: f64,
+}
+
+pub fn rgb_to_hsv(c: Color) -> Vec3 {
+    let max = c.x.max(c.y).max(c.z);
+    let min = c.x.min(c.y).min(c.z);
+    let delta = max - min;
+    let h = if delta <= 0.0 {
+        0.0
+    } else if max == c.x {
+        ((c.y - c.z) / delta).rem_euclid(6.0) / 6.0
+    } else if max == c.y {
+        ((c.z - c.x) / delta + 2.0) / 6.0
+    } else {
+        ((c.x - c.y) / delta + 4.0) / 6.0
+    };
+    let s = if max > 0.0 { delta / max } else { 0.0 };
+    Vec3::new(h, s, max)
+}
+
+pub fn hsv_to_rgb(hsv: Vec3) -> Color {
+    let h = hsv.x.rem_euclid(1.0) * 6.0;
+    let c = hsv.z * hsv.y;
+    let x = c * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let m = hsv.z - c;
+    let (r, g, b) = match h as i32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    Color::new(r + m, g + m, b + m)
+}
+
+impl HsvTexture {
+    pub fn new(input: Arc<dyn Texture>, hue_shift: f64, saturation: f64, value: f64) -> Self {
+        Self {
+            input,
+            hue_shift,
+            saturation,
+            value,
+        }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let hsv = rgb_to_hsv(at.eval(&self.input));
+        hsv_to_rgb(Vec3::new(
+            hsv.x + self.hue_shift,
+            clamp(hsv.y * self.saturation, 0.0, 1.0),
+            hsv.z * self.value,
+        ))
+    }
+}
+
+impl Texture for HsvTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//ChannelTexture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    Alpha,
+    Luminance,
+}
+
+//one channel of the input as a gray color
+pub struct ChannelTexture {
+    input: Arc<dyn Texture>,
+    channel: Channel,
+}
+
+impl ChannelTexture {
+    pub fn new(input: Arc<dyn Texture>, channel: Channel) -> Self {
+        Self { input, channel }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let x = if self.channel == Channel::Alpha {
+            match at {
+                Lookup::Point { u, v, p } => self.input.alpha(u, v, p),
+                Lookup::Hit(rec) => self.input.alpha_at(rec),
+            }
+        } else {
+            let c = at.eval(&self.input);
+            match self.channel {
+                Channel::R => c.x,
+                Channel::G => c.y,
+                Channel::B => c.z,
+                _ => 0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z,
+            }
+        };
+        Color::new(x, x, x)
+    }
+}
+
+impl Texture for ChannelTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//CombineTexture
+//builds a color from the first channel of three scalar inputs
+pub struct CombineTexture {
+    r: Arc<dyn Texture>,
+    g: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+}
+
+impl CombineTexture {
+    pub fn new(r: Arc<dyn Texture>, g: Arc<dyn Texture>, b: Arc<dyn Texture>) -> Self {
+        Self { r, g, b }
+    }
+
+    //gray color from a scalar
+    pub fn new_gray(scalar: Arc<dyn Texture>) -> Self {
+        Self::new(scalar.clone(), scalar.clone(), scalar)
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        Color::new(at.scalar(&self.r), at.scalar(&self.g), at.scalar(&self.b))
+    }
+}
+
+impl Texture for CombineTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//CoordinateTexture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coordinate {
+    Object,
+    World,
+    //shading normal facing the ray, mapped from -1..1 to 0..1
+    Normal,
+    //u and v in the first two channels
+    Uv,
+}
+
+//without a hit record object space falls back to world space and the normal to +z
+pub struct CoordinateTexture {
+    coordinate: Coordinate,
+}
+
+impl CoordinateTexture {
+    pub fn new(coordinate: Coordinate) -> Self {
+        Self { coordinate }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (u, v, p, p_object, normal) = match at {
+            Lookup::Point { u, v, p } => (u, v, p, p, Vec3::new(0.0, 0.0, 1.0)),
+            Lookup::Hit(rec) => (rec.u, rec.v, rec.p, rec.p_object, rec.normal),
+        };
+        match self.coordinate {
+            Coordinate::Object => p_object,
+            Coordinate::World => p,
+            Coordinate::Normal => (normal + Vec3::ones()) * 0.5,
+            Coordinate::Uv => Color::new(u, v, 0.0),
+        }
+    }
+}
+
+impl Texture for CoordinateTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::SolidColor;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn test_hsv_round_trip() {
+        for c in &[
+            Color::new(0.9, 0.2, 0.1),
+            Color::new(0.1, 0.7, 0.3),
+            Color::new(0.2, 0.4, 0.8),
+            Color::new(0.5, 0.5, 0.5),
+        ] {
+            assert!((hsv_to_rgb(rgb_to_hsv(*c)) - *c).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_hue_shift_by_a_third_turns_red_green() {
+        let red = Arc::new(SolidColor::new(Color::new(1.0, 0.0, 0.0)));
+        let shifted = HsvTexture::new(red, 1.0 / 3.0, 1.0, 1.0);
+        let c = shifted.value(0.0, 0.0, Point3::zero());
+        assert!((c - Color::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    }
+}
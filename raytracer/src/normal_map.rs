@@ -0,0 +1,173 @@
+use crate::hittable::HitRecord;
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::texture::Texture;
+use crate::vec3::Color;
+use crate::{Ray, Vec3};
+use std::sync::Arc;
+
+const BUMP_DELTA: f64 = 0.0005;
+
+pub enum NormalPerturbation {
+    //tangent space normal map, rgb in 0..1 encodes xyz in -1..1
+    NormalMap(Arc<dyn Texture>),
+    //height field from the first channel of any texture, scaled along the normal
+    BumpMap {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+//wraps a material and replaces the normal it shades with
+pub struct NormalMapped {
+    material: Arc<dyn Material>,
+    perturbation: NormalPerturbation,
+}
+
+impl NormalMapped {
+    pub fn new_normal_map(material: Arc<dyn Material>, normal_map: Arc<dyn Texture>) -> Self {
+        Self {
+            material,
+            perturbation: NormalPerturbation::NormalMap(normal_map),
+        }
+    }
+
+    pub fn new_bump_map(material: Arc<dyn Material>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            material,
+            perturbation: NormalPerturbation::BumpMap { height, scale },
+        }
+    }
+
+    fn outward_normal(rec: &HitRecord) -> Vec3 {
+        if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        }
+    }
+
+    fn perturbed_normal(&self, rec: &HitRecord) -> Vec3 {
+        let n = NormalMapped::outward_normal(rec);
+        match &self.perturbation {
+            NormalPerturbation::NormalMap(map) => {
+                let c = map.value_at(rec);
+                let local = Vec3::new(2.0 * c.x - 1.0, 2.0 * c.y - 1.0, 2.0 * c.z - 1.0);
+                let uvw = Onb::build_from_w_and_tangent(n, rec.dpdu);
+                //keep the bitangent on the side of dpdv for mirrored uv layouts
+                let flip = if Vec3::dot(uvw.v(), rec.dpdv) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                uvw.local(Vec3::new(local.x, local.y * flip, local.z))
+            }
+            NormalPerturbation::BumpMap { height, scale } => {
+                let h = |u: f64, v: f64, p: Vec3| height.value(u, v, p).x * scale;
+                let displace = h(rec.u, rec.v, rec.p);
+                let u_displace = h(rec.u + BUMP_DELTA, rec.v, rec.p + rec.dpdu * BUMP_DELTA);
+                let v_displace = h(rec.u, rec.v + BUMP_DELTA, rec.p + rec.dpdv * BUMP_DELTA);
+                let dpdu = rec.dpdu + n * ((u_displace - displace) / BUMP_DELTA);
+                let dpdv = rec.dpdv + n * ((v_displace - displace) / BUMP_DELTA);
+                let bumped = Vec3::cross(dpdu, dpdv);
+                if bumped.near_zero() {
+                    return n;
+                }
+                //the sign of dpdu x dpdv depends on the primitive's parametrization
+                if Vec3::dot(Vec3::cross(rec.dpdu, rec.dpdv), n) < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                }
+            }
+        }
+    }
+
+    fn shading_record(&self, rec: &HitRecord) -> HitRecord {
+        let mut tmp = rec.clone();
+        if rec.dpdu.near_zero() && rec.dpdv.near_zero() {
+            return tmp;
+        }
+        let n = self.perturbed_normal(rec);
+        if n.near_zero() {
+            return tmp;
+        }
+        let n = n.unit();
+        tmp.normal = if rec.front_face { n } else { -n };
+        tmp
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        self.material
+            .scatter(r_in, &self.shading_record(rec), attenuation, scattered)
+    }
+
+    fn emitted(&self, r_in: Ray, rec: &HitRecord) -> Color {
+        self.material.emitted(r_in, rec)
+    }
+
+    fn eval_bsdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> Color {
+        self.material
+            .eval_bsdf(r_in, &self.shading_record(rec), scattered)
+    }
+
+    fn scattering_pdf(&self, r_in: Ray, rec: &HitRecord, scattered: Ray) -> f64 {
+        self.material
+            .scattering_pdf(r_in, &self.shading_record(rec), scattered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    fn record() -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        rec.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 0.0, -1.0);
+        rec
+    }
+
+    fn mapped(c: Color) -> NormalMapped {
+        NormalMapped::new_normal_map(
+            Arc::new(Lambertian::new(Color::ones())),
+            Arc::new(SolidColor::new(c)),
+        )
+    }
+
+    #[test]
+    fn test_normal_map_leans_toward_the_tangents() {
+        let rec = record();
+        let flat = mapped(Color::new(0.5, 0.5, 1.0)).shading_record(&rec);
+        assert!((flat.normal - rec.normal).length() < 1e-12);
+
+        let along_u = mapped(Color::new(1.0, 0.5, 0.5)).shading_record(&rec);
+        assert!((along_u.normal - rec.dpdu).length() < 1e-12);
+        let along_v = mapped(Color::new(0.5, 1.0, 0.5)).shading_record(&rec);
+        assert!((along_v.normal - rec.dpdv).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_flat_bump_map_keeps_the_normal() {
+        let bumped = NormalMapped::new_bump_map(
+            Arc::new(Lambertian::new(Color::ones())),
+            Arc::new(SolidColor::new_by_value(0.7)),
+            2.0,
+        );
+        let rec = record();
+        let shading = bumped.shading_record(&rec);
+        assert!((shading.normal - rec.normal).length() < 1e-12);
+    }
+}
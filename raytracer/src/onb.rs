@@ -0,0 +1,80 @@
+use crate::Vec3;
+
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    //right handed like every other frame here, u x v = w
+    pub fn build_from_w(n: Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::cross(w, a).unit();
+        let u = Vec3::cross(v, w);
+        Self { axis: [u, v, w] }
+    }
+
+    //u follows the tangent projected onto the plane of n, falls back to an arbitrary frame
+    pub fn build_from_w_and_tangent(n: Vec3, tangent: Vec3) -> Self {
+        let w = n.unit();
+        let t = tangent - w * Vec3::dot(tangent, w);
+        if t.squared_length() < 1e-12 {
+            return Self::build_from_w(w);
+        }
+        let u = t.unit();
+        let v = Vec3::cross(w, u);
+        Self { axis: [u, v, w] }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u() * a.x + self.v() * a.y + self.w() * a.z
+    }
+
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(a, self.u()),
+            Vec3::dot(a, self.v()),
+            Vec3::dot(a, self.w()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_right_handed(uvw: Onb) {
+        assert!((Vec3::cross(uvw.u(), uvw.v()) - uvw.w()).length() < 1e-9);
+        assert!((Vec3::cross(uvw.v(), uvw.w()) - uvw.u()).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_frames_are_right_handed() {
+        for n in &[
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-0.3, 0.8, 0.2),
+            Vec3::new(0.95, -0.1, 0.3),
+        ] {
+            assert_right_handed(Onb::build_from_w(*n));
+            assert_right_handed(Onb::build_from_w_and_tangent(*n, Vec3::new(0.2, 0.1, -1.0)));
+        }
+    }
+}
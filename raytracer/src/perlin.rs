@@ -1,56 +1,98 @@
-use crate::rtweekend::random_int;
+use crate::rtweekend::clamp;
 use crate::vec3::Point3;
 use crate::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::vec::Vec;
 
+const POINT_COUNT: usize = 256;
+
+//the 12 cube edge directions shared by improved perlin and 2D/3D simplex
+const GRAD3: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseBasis {
+    //gradient noise with random vectors and a cubic fade, as in the book
+    Classic,
+    //Perlin's 2002 noise with a quintic fade
+    Improved,
+    Simplex,
+}
+
 pub struct Perlin {
-    point_count: i32,
     ranvec: Vec<Vec3>,
     perm_x: Vec<i32>,
     perm_y: Vec<i32>,
     perm_z: Vec<i32>,
+    //permutation repeated twice, indexed without wrapping
+    perm: Vec<usize>,
 }
 
-impl Perlin {
-    pub fn perlin_generate_perm(&mut self, axis: i32) {
-        let tmp = match axis {
-            0 => &mut self.perm_x,
-            1 => &mut self.perm_y,
-            _ => &mut self.perm_z,
-        };
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        for i in 0..self.point_count {
-            tmp.push(i);
-        }
-        Perlin::permut(tmp, self.point_count);
+impl Perlin {
+    pub fn perlin_generate_perm<R: Rng>(rng: &mut R) -> Vec<i32> {
+        let mut p: Vec<i32> = (0..POINT_COUNT as i32).collect();
+        Perlin::permut(&mut p, rng);
+        p
     }
 
-    pub fn permut(p: &mut Vec<i32>, n: i32) {
-        for i in 1..n {
-            let target = random_int(0, n - i) as usize;
-            let j = i as usize;
-            p.swap(j, target);
+    pub fn permut<R: Rng>(p: &mut [i32], rng: &mut R) {
+        for i in (1..p.len()).rev() {
+            let target = rng.gen_range(0..=i);
+            p.swap(i, target);
         }
     }
 
+    //randomly seeded
     pub fn new() -> Self {
-        Self {
-            point_count: 256,
-            ranvec: Vec::new(),
-            perm_x: Vec::new(),
-            perm_y: Vec::new(),
-            perm_z: Vec::new(),
-        }
+        Self::with_seed(rand::thread_rng().gen())
     }
 
-    pub fn init(&mut self) {
-        for _i in 0..self.point_count {
-            self.ranvec.push(Vec3::random_range(-1.0, 1.0));
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let ranvec = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+            })
+            .collect();
+        let perm_x = Perlin::perlin_generate_perm(&mut rng);
+        let perm_y = Perlin::perlin_generate_perm(&mut rng);
+        let perm_z = Perlin::perlin_generate_perm(&mut rng);
+        let half = Perlin::perlin_generate_perm(&mut rng);
+        let perm = half
+            .iter()
+            .chain(half.iter())
+            .map(|i| *i as usize)
+            .collect();
+        Self {
+            ranvec,
+            perm_x,
+            perm_y,
+            perm_z,
+            perm,
         }
-
-        self.perlin_generate_perm(0);
-        self.perlin_generate_perm(1);
-        self.perlin_generate_perm(2);
     }
 
     pub fn noise(&self, p: Point3) -> f64 {
@@ -110,6 +152,234 @@ impl Perlin {
         accum
     }
 
+    fn cell(x: f64) -> usize {
+        (x.floor() as i64 & 255) as usize
+    }
+
+    fn fade(t: f64) -> f64 {
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+    }
+
+    fn lerp(t: f64, a: f64, b: f64) -> f64 {
+        a + t * (b - a)
+    }
+
+    fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+        let g = GRAD3[hash % 12];
+        g[0] * x + g[1] * y + g[2] * z
+    }
+
+    //improved perlin noise, roughly in -1..1
+    pub fn improved(&self, p: Point3) -> f64 {
+        let perm = &self.perm;
+        let (xi, yi, zi) = (Perlin::cell(p.x), Perlin::cell(p.y), Perlin::cell(p.z));
+        let (x, y, z) = (p.x - p.x.floor(), p.y - p.y.floor(), p.z - p.z.floor());
+        let (u, v, w) = (Perlin::fade(x), Perlin::fade(y), Perlin::fade(z));
+
+        let a = perm[xi] + yi;
+        let aa = perm[a] + zi;
+        let ab = perm[a + 1] + zi;
+        let b = perm[xi + 1] + yi;
+        let ba = perm[b] + zi;
+        let bb = perm[b + 1] + zi;
+
+        Perlin::lerp(
+            w,
+            Perlin::lerp(
+                v,
+                Perlin::lerp(
+                    u,
+                    Perlin::grad(perm[aa], x, y, z),
+                    Perlin::grad(perm[ba], x - 1.0, y, z),
+                ),
+                Perlin::lerp(
+                    u,
+                    Perlin::grad(perm[ab], x, y - 1.0, z),
+                    Perlin::grad(perm[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            Perlin::lerp(
+                v,
+                Perlin::lerp(
+                    u,
+                    Perlin::grad(perm[aa + 1], x, y, z - 1.0),
+                    Perlin::grad(perm[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                Perlin::lerp(
+                    u,
+                    Perlin::grad(perm[ab + 1], x, y - 1.0, z - 1.0),
+                    Perlin::grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    //2D simplex noise in -1..1, cheaper for flat layouts, the textures all sample 3D points for now
+    #[allow(dead_code)]
+    pub fn simplex2(&self, x: f64, y: f64) -> f64 {
+        let f2 = 0.5 * (3.0_f64.sqrt() - 1.0);
+        let g2 = (3.0 - 3.0_f64.sqrt()) / 6.0;
+        let perm = &self.perm;
+
+        let s = (x + y) * f2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * g2;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let corners = [
+            (x0, y0, 0, 0),
+            (x0 - i1 as f64 + g2, y0 - j1 as f64 + g2, i1, j1),
+            (x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2, 1, 1),
+        ];
+
+        let (ii, jj) = (Perlin::cell(i), Perlin::cell(j));
+        let mut n = 0.0;
+        for (cx, cy, di, dj) in corners.iter() {
+            let t = 0.5 - cx * cx - cy * cy;
+            if t > 0.0 {
+                let hash = perm[ii + di + perm[jj + dj]];
+                n += t.powi(4) * Perlin::grad(hash, *cx, *cy, 0.0);
+            }
+        }
+        70.0 * n
+    }
+
+    //3D simplex noise in -1..1
+    pub fn simplex3(&self, p: Point3) -> f64 {
+        let f3 = 1.0 / 3.0;
+        let g3 = 1.0 / 6.0;
+        let perm = &self.perm;
+
+        let s = (p.x + p.y + p.z) * f3;
+        let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+        let t = (i + j + k) * g3;
+        let d0 = Vec3::new(p.x - (i - t), p.y - (j - t), p.z - (k - t));
+
+        //which simplex of the skewed cube holds the point
+        let (o1, o2) = if d0.x >= d0.y {
+            if d0.y >= d0.z {
+                ([1, 0, 0], [1, 1, 0])
+            } else if d0.x >= d0.z {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if d0.y < d0.z {
+            ([0, 0, 1], [0, 1, 1])
+        } else if d0.x < d0.z {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+        let offsets = [[0, 0, 0], o1, o2, [1, 1, 1]];
+
+        let (ii, jj, kk) = (Perlin::cell(i), Perlin::cell(j), Perlin::cell(k));
+        let mut n = 0.0;
+        for (c, o) in offsets.iter().enumerate() {
+            let g = g3 * c as f64;
+            let d = Vec3::new(
+                d0.x - o[0] as f64 + g,
+                d0.y - o[1] as f64 + g,
+                d0.z - o[2] as f64 + g,
+            );
+            let t = 0.6 - d.squared_length();
+            if t > 0.0 {
+                let hash = perm[ii + o[0] + perm[jj + o[1] + perm[kk + o[2]]]];
+                n += t.powi(4) * Perlin::grad(hash, d.x, d.y, d.z);
+            }
+        }
+        32.0 * n
+    }
+
+    //4D simplex noise in -1..1, w is typically time, texture lookups carry no time to pass in yet
+    #[allow(dead_code)]
+    pub fn simplex4(&self, p: Point3, w: f64) -> f64 {
+        let f4 = (5.0_f64.sqrt() - 1.0) / 4.0;
+        let g4 = (5.0 - 5.0_f64.sqrt()) / 20.0;
+        let perm = &self.perm;
+
+        let x = [p.x, p.y, p.z, w];
+        let s = x.iter().sum::<f64>() * f4;
+        let cell = [
+            (x[0] + s).floor(),
+            (x[1] + s).floor(),
+            (x[2] + s).floor(),
+            (x[3] + s).floor(),
+        ];
+        let t = cell.iter().sum::<f64>() * g4;
+        let mut d0 = [0.0; 4];
+        for a in 0..4 {
+            d0[a] = x[a] - (cell[a] - t);
+        }
+
+        //rank of each coordinate decides the order the simplex corners are walked
+        let mut rank = [0; 4];
+        for a in 0..4 {
+            for b in a + 1..4 {
+                if d0[a] > d0[b] {
+                    rank[a] += 1;
+                } else {
+                    rank[b] += 1;
+                }
+            }
+        }
+
+        let idx = [
+            Perlin::cell(cell[0]),
+            Perlin::cell(cell[1]),
+            Perlin::cell(cell[2]),
+            Perlin::cell(cell[3]),
+        ];
+        let mut n = 0.0;
+        for c in 0..5 {
+            let mut o = [0; 4];
+            for a in 0..4 {
+                if c == 4 || rank[a] >= 4 - c {
+                    o[a] = 1;
+                }
+            }
+            let g = g4 * c as f64;
+            let mut d = [0.0; 4];
+            for a in 0..4 {
+                d[a] = d0[a] - o[a] as f64 + g;
+            }
+            let t = 0.6 - d.iter().map(|v| v * v).sum::<f64>();
+            if t > 0.0 {
+                let hash = perm[idx[0]
+                    + o[0]
+                    + perm[idx[1] + o[1] + perm[idx[2] + o[2] + perm[idx[3] + o[3]]]]];
+                n += t.powi(4) * Perlin::grad4(hash, d);
+            }
+        }
+        27.0 * n
+    }
+
+    //the 32 directions with one zero and three unit coordinates
+    #[allow(dead_code)]
+    fn grad4(hash: usize, d: [f64; 4]) -> f64 {
+        let h = hash & 31;
+        let zero = h >> 3;
+        let mut sum = 0.0;
+        let mut bit = 0;
+        for (a, x) in d.iter().enumerate() {
+            if a == zero {
+                continue;
+            }
+            sum += if h & (1 << bit) == 0 { *x } else { -*x };
+            bit += 1;
+        }
+        sum
+    }
+
+    pub fn sample(&self, basis: NoiseBasis, p: Point3) -> f64 {
+        match basis {
+            NoiseBasis::Classic => self.noise(p),
+            NoiseBasis::Improved => self.improved(p),
+            NoiseBasis::Simplex => self.simplex3(p),
+        }
+    }
+
     pub fn turb(&self, p: Point3, depth: i32) -> f64 {
         let mut accum = 0.0;
         let mut temp_p = p;
@@ -123,4 +393,123 @@ impl Perlin {
 
         accum.abs()
     }
+
+    //fractal sum normalized by the total amplitude, roughly in -1..1
+    pub fn fbm(
+        &self,
+        basis: NoiseBasis,
+        p: Point3,
+        octaves: i32,
+        lacunarity: f64,
+        gain: f64,
+    ) -> f64 {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+
+        for _i in 0..octaves {
+            accum += weight * self.sample(basis, temp_p);
+            norm += weight;
+            weight *= gain;
+            temp_p *= lacunarity;
+        }
+
+        if norm > 0.0 {
+            accum / norm
+        } else {
+            0.0
+        }
+    }
+
+    //Musgrave's ridged multifractal, sharp crests where the noise crosses zero, in 0..1
+    pub fn ridged(
+        &self,
+        basis: NoiseBasis,
+        p: Point3,
+        octaves: i32,
+        lacunarity: f64,
+        gain: f64,
+    ) -> f64 {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        let mut amplitude = 1.0;
+
+        for _i in 0..octaves {
+            let ridge = 1.0 - self.sample(basis, temp_p).abs();
+            let signal = ridge * ridge * weight;
+            //crests of the previous octave get more detail
+            weight = clamp(signal * 2.0, 0.0, 1.0);
+            accum += signal * amplitude;
+            norm += amplitude;
+            amplitude *= gain;
+            temp_p *= lacunarity;
+        }
+
+        if norm > 0.0 {
+            accum / norm
+        } else {
+            0.0
+        }
+    }
+
+    //domain warping, p displaced by three decorrelated fbm lookups
+    pub fn warp(&self, basis: NoiseBasis, p: Point3, strength: f64, octaves: i32) -> Point3 {
+        let offset = |o: Vec3| self.fbm(basis, p + o, octaves, 2.0, 0.5);
+        p + Vec3::new(
+            offset(Vec3::zero()),
+            offset(Vec3::new(5.2, 1.3, 2.8)),
+            offset(Vec3::new(1.7, 9.2, 4.1)),
+        ) * strength
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation_repeats() {
+        let perlin = Perlin::with_seed(7);
+        assert_eq!(perlin.perm.len(), 2 * POINT_COUNT);
+        assert_eq!(perlin.perm[..POINT_COUNT], perlin.perm[POINT_COUNT..]);
+    }
+
+    #[test]
+    fn test_noise_wraps_every_256_units() {
+        let perlin = Perlin::with_seed(11);
+        let offset = Vec3::new(256.0, 256.0, 256.0);
+        for i in 0..64 {
+            let t = i as f64 * 0.37;
+            let p = Point3::new(t, 1.3 * t + 0.2, 0.7 * t + 5.1);
+            for basis in [NoiseBasis::Improved, NoiseBasis::Simplex].iter() {
+                let a = perlin.sample(*basis, p);
+                let b = perlin.sample(*basis, p + offset);
+                assert!((a - b).abs() < 1e-9, "{:?} differs at {:?}", basis, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simplex_stays_in_range_and_vanishes_on_the_lattice() {
+        let perlin = Perlin::with_seed(3);
+        assert_eq!(perlin.simplex2(0.0, 0.0), 0.0);
+        assert_eq!(perlin.simplex3(Point3::zero()), 0.0);
+        assert_eq!(perlin.simplex4(Point3::zero(), 0.0), 0.0);
+        for i in 0..500 {
+            let t = i as f64 * 0.173;
+            let p = Point3::new(t, 2.1 * t - 3.0, 0.6 * t + 1.7);
+            for value in &[
+                perlin.simplex2(p.x, p.y),
+                perlin.simplex3(p),
+                perlin.simplex4(p, 0.4 * t),
+            ] {
+                assert!(value.abs() <= 1.0);
+            }
+            let ridged = perlin.ridged(NoiseBasis::Improved, p, 5, 2.0, 0.5);
+            assert!((0.0..=1.0).contains(&ridged));
+        }
+    }
 }
@@ -0,0 +1,146 @@
+use crate::vec3::{Color, Point3};
+use crate::Vec3;
+
+#[derive(Clone, Copy)]
+pub struct Photon {
+    pub p: Point3,
+    //toward where the photon came from
+    pub wi: Vec3,
+    pub beta: Color,
+}
+
+fn coordinate(p: Point3, axis: usize) -> f64 {
+    match axis {
+        0 => p.x,
+        1 => p.y,
+        _ => p.z,
+    }
+}
+
+//PhotonMap
+//kd-tree kept in one array, the median of every range is its node and splits the rest in two
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        Self::build(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    fn build(photons: &mut [Photon], axes: &mut [u8]) {
+        if photons.len() <= 1 {
+            return;
+        }
+        let mut lo = photons[0].p;
+        let mut hi = lo;
+        for photon in photons.iter() {
+            let p = photon.p;
+            lo = Point3::new(lo.x.min(p.x), lo.y.min(p.y), lo.z.min(p.z));
+            hi = Point3::new(hi.x.max(p.x), hi.y.max(p.y), hi.z.max(p.z));
+        }
+        let extent = hi - lo;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| {
+            coordinate(a.p, axis)
+                .partial_cmp(&coordinate(b.p, axis))
+                .unwrap()
+        });
+        axes[mid] = axis as u8;
+
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        Self::build(left, left_axes);
+        Self::build(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    //calls found for every photon closer to p than radius
+    pub fn lookup<F: FnMut(&Photon)>(&self, p: Point3, radius: f64, found: &mut F) {
+        self.lookup_range(0, self.photons.len(), p, radius, found);
+    }
+
+    fn lookup_range<F: FnMut(&Photon)>(
+        &self,
+        begin: usize,
+        end: usize,
+        p: Point3,
+        radius: f64,
+        found: &mut F,
+    ) {
+        if begin >= end {
+            return;
+        }
+        let mid = begin + (end - begin) / 2;
+        let photon = &self.photons[mid];
+        if (photon.p - p).squared_length() < radius * radius {
+            found(photon);
+        }
+        if end - begin == 1 {
+            return;
+        }
+        let axis = self.axes[mid] as usize;
+        let d = coordinate(p, axis) - coordinate(photon.p, axis);
+        if d <= radius {
+            self.lookup_range(begin, mid, p, radius, found);
+        }
+        if d >= -radius {
+            self.lookup_range(mid + 1, end, p, radius, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_lookup_finds_exactly_the_photons_in_range() {
+        let mut rng = rand::thread_rng();
+        let photons: Vec<Photon> = (0..2000)
+            .map(|i| Photon {
+                p: Point3::new(
+                    rng.gen::<f64>() * 4.0,
+                    rng.gen::<f64>(),
+                    rng.gen::<f64>() * 2.0,
+                ),
+                wi: Vec3::new(0.0, 1.0, 0.0),
+                beta: Color::new(i as f64, 0.0, 0.0),
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        for _ in 0..50 {
+            let p = Point3::new(
+                rng.gen::<f64>() * 4.0,
+                rng.gen::<f64>(),
+                rng.gen::<f64>() * 2.0,
+            );
+            let radius = rng.gen::<f64>() * 0.4;
+            let mut found = Vec::new();
+            map.lookup(p, radius, &mut |photon: &Photon| {
+                found.push(photon.beta.x as usize)
+            });
+            found.sort_unstable();
+            let expected: Vec<usize> = photons
+                .iter()
+                .enumerate()
+                .filter(|(_, photon)| (photon.p - p).squared_length() < radius * radius)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(found, expected);
+        }
+        let mut none = 0;
+        PhotonMap::new(Vec::new()).lookup(Point3::zero(), 1.0, &mut |_: &Photon| none += 1);
+        assert_eq!(none, 0);
+    }
+}
@@ -0,0 +1,632 @@
+use crate::hittable::HitRecord;
+use crate::perlin::{NoiseBasis, Perlin};
+use crate::rtweekend::clamp;
+use crate::texture::{lookup_point, Lookup, SolidColor, Texture};
+use crate::vec3::Point3;
+use crate::{Color, Vec3};
+use std::cmp::Ordering;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+
+fn mix(a: &Arc<dyn Texture>, b: &Arc<dyn Texture>, t: f64, at: Lookup) -> Color {
+    let t = clamp(t, 0.0, 1.0);
+    at.eval(a) * (1.0 - t) + at.eval(b) * t
+}
+
+//ColorRamp
+//piecewise linear colors over 0..1, constant past the first and last stop
+#[derive(Clone, Debug)]
+pub struct ColorRamp {
+    stops: Vec<(f64, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f64, Color)>) -> Result<Self> {
+        if stops.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "color ramp without stops",
+            ));
+        }
+        if stops.iter().any(|(t, _)| !t.is_finite()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "color ramp stop at a non finite position",
+            ));
+        }
+        //every position is finite past the check above, so each pair has an order
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        Ok(Self { stops })
+    }
+
+    pub fn new_by_colors(c1: Color, c2: Color) -> Self {
+        Self::new(vec![(0.0, c1), (1.0, c2)]).expect("two fixed stops")
+    }
+
+    pub fn at(&self, t: f64) -> Color {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let (t0, c0) = pair[0];
+            let (t1, c1) = pair[1];
+            if t <= t1 {
+                let s = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return c0 * (1.0 - s) + c1 * s;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+//FbmTexture
+pub struct FbmTexture {
+    pub noise: Perlin,
+    pub basis: NoiseBasis,
+    scale: f64,
+    octaves: i32,
+    lacunarity: f64,
+    gain: f64,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl FbmTexture {
+    pub fn new(
+        scale: f64,
+        octaves: i32,
+        lacunarity: f64,
+        gain: f64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(),
+            basis: NoiseBasis::Improved,
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+            low,
+            high,
+        }
+    }
+
+    pub fn new_by_color(scale: f64, octaves: i32, low: Color, high: Color) -> Self {
+        Self::new(
+            scale,
+            octaves,
+            2.0,
+            0.5,
+            Arc::new(SolidColor::new(low)),
+            Arc::new(SolidColor::new(high)),
+        )
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let n = self.noise.fbm(
+            self.basis,
+            p * self.scale,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+        );
+        mix(&self.low, &self.high, 0.5 * (1.0 + n), at)
+    }
+}
+
+impl Texture for FbmTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//RidgedTexture
+pub struct RidgedTexture {
+    pub noise: Perlin,
+    pub basis: NoiseBasis,
+    scale: f64,
+    octaves: i32,
+    lacunarity: f64,
+    gain: f64,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl RidgedTexture {
+    pub fn new(
+        scale: f64,
+        octaves: i32,
+        lacunarity: f64,
+        gain: f64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(),
+            basis: NoiseBasis::Improved,
+            scale,
+            octaves,
+            lacunarity,
+            gain,
+            low,
+            high,
+        }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let n = self.noise.ridged(
+            self.basis,
+            p * self.scale,
+            self.octaves,
+            self.lacunarity,
+            self.gain,
+        );
+        mix(&self.low, &self.high, n, at)
+    }
+}
+
+impl Texture for RidgedTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//WorleyTexture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorleyMode {
+    //distance to the nearest feature point
+    F1,
+    //distance to the second nearest
+    F2,
+    //cell borders
+    F2MinusF1,
+}
+
+pub struct WorleyTexture {
+    scale: f64,
+    mode: WorleyMode,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl WorleyTexture {
+    pub fn new(
+        scale: f64,
+        mode: WorleyMode,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            scale,
+            mode,
+            low,
+            high,
+        }
+    }
+
+    fn hash(i: i64, j: i64, k: i64, n: u64) -> u64 {
+        //splitmix64 finalizer over the combined cell coordinates
+        let mut h = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+            ^ (k as u64).wrapping_mul(0x1656_67b1_9e37_79f9)
+            ^ n.wrapping_mul(0x27d4_eb2f_1656_67c5);
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^ (h >> 31)
+    }
+
+    //one jittered feature point per unit cell
+    fn feature_point(i: i64, j: i64, k: i64) -> Point3 {
+        let r = |n: u64| (WorleyTexture::hash(i, j, k, n) >> 11) as f64 / (1u64 << 53) as f64;
+        Point3::new(i as f64 + r(0), j as f64 + r(1), k as f64 + r(2))
+    }
+
+    //distances to the nearest and second nearest feature points
+    pub fn distances(p: Point3) -> (f64, f64) {
+        let (ci, cj, ck) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let mut f1 = f64::INFINITY;
+        let mut f2 = f64::INFINITY;
+        for i in ci - 1..=ci + 1 {
+            for j in cj - 1..=cj + 1 {
+                for k in ck - 1..=ck + 1 {
+                    let d = (WorleyTexture::feature_point(i, j, k) - p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let (f1, f2) = WorleyTexture::distances(p * self.scale);
+        let t = match self.mode {
+            WorleyMode::F1 => f1,
+            WorleyMode::F2 => f2,
+            WorleyMode::F2MinusF1 => f2 - f1,
+        };
+        mix(&self.low, &self.high, t, at)
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//WoodTexture
+//concentric rings around the y axis, distorted by fbm
+pub struct WoodTexture {
+    pub noise: Perlin,
+    pub basis: NoiseBasis,
+    scale: f64,
+    rings: f64,
+    turbulence: f64,
+    light: Arc<dyn Texture>,
+    dark: Arc<dyn Texture>,
+}
+
+impl WoodTexture {
+    pub fn new(
+        scale: f64,
+        rings: f64,
+        turbulence: f64,
+        light: Arc<dyn Texture>,
+        dark: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(),
+            basis: NoiseBasis::Improved,
+            scale,
+            rings,
+            turbulence,
+            light,
+            dark,
+        }
+    }
+
+    pub fn new_by_color(scale: f64, light: Color, dark: Color) -> Self {
+        Self::new(
+            scale,
+            8.0,
+            0.6,
+            Arc::new(SolidColor::new(light)),
+            Arc::new(SolidColor::new(dark)),
+        )
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let q = p * self.scale;
+        let distortion = self.turbulence * self.noise.fbm(self.basis, q, 4, 2.0, 0.5);
+        let r = (q.x * q.x + q.z * q.z).sqrt() * self.rings + distortion;
+        //late wood is a narrow dark band at the end of each ring
+        let ring = r - r.floor();
+        mix(&self.light, &self.dark, ring.powi(3), at)
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//MarbleTexture
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    scale: f64,
+    turbulence: f64,
+    octaves: i32,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl MarbleTexture {
+    pub fn new(
+        scale: f64,
+        turbulence: f64,
+        octaves: i32,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            turbulence,
+            octaves,
+            low,
+            high,
+        }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let phase = self.scale * p.z + self.turbulence * self.noise.turb(p, self.octaves);
+        mix(&self.low, &self.high, 0.5 * (1.0 + phase.sin()), at)
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//UvCheckerTexture
+pub struct UvCheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    u_count: f64,
+    v_count: f64,
+}
+
+impl UvCheckerTexture {
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, u_count: f64, v_count: f64) -> Self {
+        Self {
+            even,
+            odd,
+            u_count,
+            v_count,
+        }
+    }
+
+    pub fn new_by_color(c1: Color, c2: Color, u_count: f64, v_count: f64) -> Self {
+        Self::new(
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+            u_count,
+            v_count,
+        )
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (u, v, _) = at.uvp();
+        let cell = (u * self.u_count).floor() as i64 + (v * self.v_count).floor() as i64;
+        if cell.rem_euclid(2) == 0 {
+            at.eval(&self.even)
+        } else {
+            at.eval(&self.odd)
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//StripesTexture
+//bands across direction, width is the fraction of each period taken by a
+pub struct StripesTexture {
+    a: Arc<dyn Texture>,
+    b: Arc<dyn Texture>,
+    direction: Vec3,
+    period: f64,
+    width: f64,
+}
+
+impl StripesTexture {
+    pub fn new(
+        a: Arc<dyn Texture>,
+        b: Arc<dyn Texture>,
+        direction: Vec3,
+        period: f64,
+        width: f64,
+    ) -> Self {
+        Self {
+            a,
+            b,
+            direction: direction.unit(),
+            period,
+            width,
+        }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let x = Vec3::dot(p, self.direction) / self.period;
+        if x - x.floor() < self.width {
+            at.eval(&self.a)
+        } else {
+            at.eval(&self.b)
+        }
+    }
+}
+
+impl Texture for StripesTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+//GradientTexture
+#[derive(Clone, Copy, Debug)]
+pub enum Gradient {
+    //0 at from, 1 at to
+    Linear { from: Point3, to: Point3 },
+    //0 at the center, 1 at radius
+    Spherical { center: Point3, radius: f64 },
+    U,
+    V,
+}
+
+//low at 0 and high at 1, the ends are held past them
+pub struct GradientTexture {
+    gradient: Gradient,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>,
+}
+
+impl GradientTexture {
+    pub fn new(gradient: Gradient, low: Arc<dyn Texture>, high: Arc<dyn Texture>) -> Self {
+        Self {
+            gradient,
+            low,
+            high,
+        }
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (u, v, p) = at.uvp();
+        let t = match self.gradient {
+            Gradient::Linear { from, to } => {
+                let axis = to - from;
+                Vec3::dot(p - from, axis) / axis.squared_length()
+            }
+            Gradient::Spherical { center, radius } => (p - center).length() / radius,
+            Gradient::U => u,
+            Gradient::V => v,
+        };
+        mix(&self.low, &self.high, t, at)
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::CheckerTexture;
+
+    //tells a filtered lookup at a hit apart from a plain point lookup
+    struct Footprint;
+
+    impl Texture for Footprint {
+        fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+            Color::zero()
+        }
+
+        fn value_at(&self, rec: &HitRecord) -> Color {
+            Color::ones() * rec.dudx
+        }
+    }
+
+    #[test]
+    fn test_inputs_see_the_hit() {
+        let input: Arc<dyn Texture> = Arc::new(Footprint);
+        let textures: Vec<Arc<dyn Texture>> = vec![
+            Arc::new(GradientTexture::new(
+                Gradient::U,
+                input.clone(),
+                input.clone(),
+            )),
+            Arc::new(MarbleTexture::new(
+                4.0,
+                2.0,
+                5,
+                input.clone(),
+                input.clone(),
+            )),
+            Arc::new(FbmTexture::new(
+                2.0,
+                4,
+                2.0,
+                0.5,
+                input.clone(),
+                input.clone(),
+            )),
+            Arc::new(StripesTexture::new(
+                input.clone(),
+                input.clone(),
+                Vec3::new(1.0, 0.0, 0.0),
+                1.0,
+                0.5,
+            )),
+            Arc::new(CheckerTexture::new(input.clone(), input, 10.0)),
+        ];
+        let mut rec = HitRecord::new();
+        rec.p = Point3::new(0.3, 0.7, 0.1);
+        rec.u = 0.4;
+        rec.dudx = 0.25;
+        for texture in textures.iter() {
+            assert_eq!(texture.value(rec.u, rec.v, rec.p), Color::zero());
+            assert_eq!(texture.value_at(&rec), Color::ones() * 0.25);
+        }
+    }
+
+    #[test]
+    fn test_color_ramp_rejects_non_finite_stops() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let blue = Color::new(0.0, 0.0, 1.0);
+        assert!(ColorRamp::new(Vec::new()).is_err());
+        assert!(ColorRamp::new(vec![(0.0, red), (f64::NAN, blue)]).is_err());
+        assert!(ColorRamp::new(vec![(f64::INFINITY, red)]).is_err());
+
+        let ramp = ColorRamp::new(vec![(1.0, blue), (0.0, red)]).unwrap();
+        assert_eq!(ramp.at(-1.0), red);
+        assert_eq!(ramp.at(0.5), (red + blue) * 0.5);
+        assert_eq!(ramp.at(2.0), blue);
+    }
+
+    #[test]
+    fn test_worley_distances_are_ordered() {
+        let feature = WorleyTexture::feature_point(3, -2, 5);
+        let (f1, _) = WorleyTexture::distances(feature);
+        assert!(f1 < 1e-12);
+        for k in 0..100 {
+            let p = Point3::new(k as f64 * 0.37, -(k as f64) * 0.21, k as f64 * 0.13);
+            let (f1, f2) = WorleyTexture::distances(p);
+            assert!(f1 <= f2);
+            //every cell holds a point, so the nearest one is never more than two cells away
+            assert!(f2 < 2.0 * 3.0f64.sqrt());
+        }
+    }
+
+    #[test]
+    fn test_uv_checker_alternates_cells() {
+        let checker = UvCheckerTexture::new_by_color(Color::zero(), Color::ones(), 4.0, 2.0);
+        let at = |u: f64, v: f64| checker.value(u, v, Point3::zero()).x;
+        assert_eq!(at(0.1, 0.1), 0.0);
+        assert_eq!(at(0.3, 0.1), 1.0);
+        assert_eq!(at(0.3, 0.6), 0.0);
+        assert_eq!(at(0.6, 0.6), 1.0);
+    }
+}
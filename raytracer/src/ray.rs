@@ -1,11 +1,25 @@
+use crate::spectrum::SampledWavelengths;
 use crate::vec3::Point3;
 use crate::Vec3;
 
+//offset rays one pixel to the right and one pixel up
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct RayDifferential {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3,
+}
+
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
     tm: f64,
+    wl: Option<SampledWavelengths>,
+    diff: Option<RayDifferential>,
+    //the material that produced this ray picked a delta lobe
+    delta: bool,
 }
 
 impl Ray {
@@ -14,6 +28,9 @@ impl Ray {
             orig: Point3::copy(origin),
             dir: Vec3::copy(direction),
             tm: time,
+            wl: None,
+            diff: None,
+            delta: false,
         }
     }
 
@@ -22,6 +39,9 @@ impl Ray {
             orig: Point3::zero(),
             dir: Vec3::zero(),
             tm: 0.0,
+            wl: None,
+            diff: None,
+            delta: false,
         }
     }
 
@@ -40,4 +60,50 @@ impl Ray {
     pub fn time(&self) -> f64 {
         self.tm
     }
+
+    pub fn wavelengths(&self) -> Option<SampledWavelengths> {
+        self.wl
+    }
+
+    pub fn with_wavelengths(&self, wl: SampledWavelengths) -> Ray {
+        Ray {
+            wl: Some(wl),
+            ..*self
+        }
+    }
+
+    pub fn differentials(&self) -> Option<RayDifferential> {
+        self.diff
+    }
+
+    pub fn with_differentials(&self, diff: RayDifferential) -> Ray {
+        Ray {
+            diff: Some(diff),
+            ..*self
+        }
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.delta
+    }
+
+    pub fn with_delta(&self) -> Ray {
+        Ray {
+            delta: true,
+            ..*self
+        }
+    }
+
+    //shrinks the footprint when a pixel is covered by many samples
+    pub fn scale_differentials(&self, s: f64) -> Ray {
+        match self.diff {
+            Some(d) => self.with_differentials(RayDifferential {
+                rx_origin: self.orig + (d.rx_origin - self.orig) * s,
+                rx_direction: self.dir + (d.rx_direction - self.dir) * s,
+                ry_origin: self.orig + (d.ry_origin - self.orig) * s,
+                ry_direction: self.dir + (d.ry_direction - self.dir) * s,
+            }),
+            None => *self,
+        }
+    }
 }
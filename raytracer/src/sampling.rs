@@ -0,0 +1,163 @@
+//piecewise constant density over 0..1 proportional to func
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_int: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].max(0.0) / n as f64;
+        }
+        let func_int = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if func_int > 0.0 {
+                *c / func_int
+            } else {
+                i as f64 / n as f64
+            };
+        }
+        Self {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    //the marginal of Distribution2D is built from these
+    pub fn integral(&self) -> f64 {
+        self.func_int
+    }
+
+    //returns x in 0..1, its density and the index of the segment it fell in
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        //last cdf entry not greater than u
+        let mut lo = 0;
+        let mut hi = n;
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            if self.cdf[mid] <= u {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let offset = lo;
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as f64 + du) / n as f64).min(1.0 - 1e-12);
+        (x, self.pdf(offset), offset)
+    }
+
+    //picks segment i with probability func[i] over the sum
+    pub fn sample_discrete(&self, u: f64) -> (usize, f64) {
+        let (_, pdf, offset) = self.sample_continuous(u);
+        (offset, pdf / self.func.len() as f64)
+    }
+
+    //density of segment i
+    pub fn pdf(&self, i: usize) -> f64 {
+        if self.func_int > 0.0 {
+            self.func[i].max(0.0) / self.func_int
+        } else {
+            1.0
+        }
+    }
+}
+
+//piecewise constant density over the unit square, func is given row by row
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<Distribution1D> = (0..nv)
+            .map(|v| Distribution1D::new(func[v * nu..(v + 1) * nu].to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|c| c.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    //returns (u, v) in the unit square, v indexing rows, and the density
+    pub fn sample_continuous(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let nv = self.marginal.count();
+        let row = ((v * nv as f64) as usize).min(nv - 1);
+        let nu = self.conditional[row].count();
+        let col = ((u * nu as f64) as usize).min(nu - 1);
+        if self.marginal.integral() <= 0.0 {
+            return 1.0;
+        }
+        self.conditional[row].func[col].max(0.0) / self.marginal.integral()
+    }
+}
+
+//Veach's power heuristic with beta 2, weight of a sample from f against one from g
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f = f_pdf * f_pdf;
+    let g = g_pdf * g_pdf;
+    if f + g <= 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_1d_follows_func() {
+        let d = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        assert_eq!(d.integral(), 2.0);
+        let (x, pdf, i) = d.sample_continuous(0.3);
+        assert_eq!(i, 1);
+        assert!((x - (1.0 + (0.3 - 0.125) / 0.375) / 4.0).abs() < 1e-12);
+        assert_eq!(pdf, 1.5);
+        assert_eq!(d.sample_discrete(0.95), (3, 0.5));
+        //empty segments are never picked
+        for k in 0..100 {
+            assert_ne!(d.sample_continuous(k as f64 / 100.0).2, 2);
+        }
+
+        let flat = Distribution1D::new(vec![0.0; 4]);
+        assert_eq!(flat.sample_continuous(0.6).1, 1.0);
+        assert_eq!(flat.sample_discrete(0.6).0, 2);
+    }
+
+    #[test]
+    fn test_distribution_2d_density_matches_samples() {
+        let func = [1.0, 2.0, 0.5, 4.0, 0.0, 1.0];
+        let d = Distribution2D::new(&func, 3, 2);
+        for k in 0..50 {
+            let u1 = (k as f64 + 0.5) / 50.0;
+            let u2 = ((k * 7) % 50) as f64 / 50.0 + 0.01;
+            let ((u, v), pdf) = d.sample_continuous(u1, u2);
+            assert!((pdf - d.pdf(u, v)).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,1242 @@
+use crate::_box::_Box;
+use crate::aabb::AABB;
+use crate::alpha_mask::AlphaMask;
+use crate::arrect::{XYRect, XZRect};
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::disney::Disney;
+use crate::environment::{EnvironmentMap, UniformEnvironment};
+use crate::heterogeneous_medium::{HeterogeneousMedium, VoxelGrid};
+use crate::ies::IesProfile;
+use crate::layered::Layered;
+use crate::light::{DirectionalLight, LightList, PointLight, SpotLight};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use crate::material::{FresnelMixMaterial, Hapke, MixMaterial, OrenNayar, Translucent};
+use crate::mipmap::{Filter, MipFilter, WrapMode};
+use crate::node::{AddTexture, Channel, ChannelTexture, ClampTexture, CombineTexture};
+use crate::node::{Coordinate, CoordinateTexture, HsvTexture, InvertTexture, MixTexture};
+use crate::node::{MultiplyTexture, RampTexture, RemapTexture};
+use crate::normal_map::NormalMapped;
+use crate::perlin::{NoiseBasis, Perlin};
+use crate::procedural::{
+    ColorRamp, FbmTexture, Gradient, GradientTexture, MarbleTexture, RidgedTexture,
+};
+use crate::procedural::{StripesTexture, UvCheckerTexture, WoodTexture, WorleyMode, WorleyTexture};
+use crate::sky::{sun_direction, PhysicalSky};
+use crate::sphere::Sphere;
+use crate::texture::{
+    CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture, UvTransform,
+};
+use crate::thin_film::ThinFilm;
+use crate::vec3::{Color, Point3, Vec3};
+use crate::{cornell_box, my_scene, Hittable, HittableList};
+use std::sync::Arc;
+
+//Scene
+//a world, the lights registered with it and the camera it is framed for
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: LightList,
+    pub camera: Camera,
+}
+
+//names the cli and the SCENE variable accept
+pub const SCENE_NAMES: [&str; 24] = [
+    "default",
+    "dispersion",
+    "disney",
+    "layered",
+    "diffuse",
+    "thin_film",
+    "mix",
+    "normal_map",
+    "alpha_mask",
+    "earth",
+    "filtering",
+    "procedural",
+    "nodes",
+    "noise",
+    "emitters",
+    "many_lights",
+    "environment",
+    "overcast",
+    "sky",
+    "delta_lights",
+    "ies",
+    "cornell",
+    "caustics",
+    "nebula",
+];
+
+fn pinhole(lookfrom: Point3, lookat: Point3, vfov: f64, aspect_ratio: f64) -> Camera {
+    Camera::new(
+        (lookfrom, lookat),
+        Vec3::new(0.0, 1.0, 0.0),
+        vfov,
+        aspect_ratio,
+        0.0,
+        10.0,
+        (0.0, 1.0),
+    )
+}
+
+//looks down at material_row from the front
+fn row_camera(aspect_ratio: f64) -> Camera {
+    pinhole(
+        Point3::new(0.0, 3.5, 14.0),
+        Point3::new(0.0, 1.0, 0.0),
+        45.0,
+        aspect_ratio,
+    )
+}
+
+pub fn by_name(name: &str, aspect_ratio: f64) -> Option<Scene> {
+    let mut lights = LightList::new_default();
+    let (world, camera) = match name {
+        "default" => (
+            my_scene(&mut lights),
+            pinhole(
+                Point3::new(30.0, 0.0, 50.0),
+                Point3::zero(),
+                55.0,
+                aspect_ratio,
+            ),
+        ),
+        "dispersion" => (
+            dispersion(&mut lights),
+            pinhole(
+                Point3::new(0.0, 4.0, 12.0),
+                Point3::new(0.0, 1.0, 0.0),
+                35.0,
+                aspect_ratio,
+            ),
+        ),
+        "disney" => (disney(&mut lights), row_camera(aspect_ratio)),
+        "layered" => (layered(&mut lights), row_camera(aspect_ratio)),
+        "diffuse" => (diffuse(&mut lights), row_camera(aspect_ratio)),
+        "thin_film" => (thin_film(&mut lights), row_camera(aspect_ratio)),
+        "mix" => (mix(&mut lights), row_camera(aspect_ratio)),
+        "normal_map" => (normal_map(&mut lights), row_camera(aspect_ratio)),
+        "alpha_mask" => (alpha_mask(&mut lights), row_camera(aspect_ratio)),
+        "earth" => (
+            earth(&mut lights),
+            pinhole(
+                Point3::new(0.0, 0.0, 12.0),
+                Point3::zero(),
+                20.0,
+                aspect_ratio,
+            ),
+        ),
+        "filtering" => (
+            filtering(&mut lights),
+            pinhole(
+                Point3::new(0.0, 1.0, 10.0),
+                Point3::new(0.0, 0.0, -20.0),
+                50.0,
+                aspect_ratio,
+            ),
+        ),
+        "procedural" => (
+            procedural(&mut lights),
+            pinhole(
+                Point3::new(0.0, 5.0, 20.0),
+                Point3::new(0.0, 1.0, 0.0),
+                50.0,
+                aspect_ratio,
+            ),
+        ),
+        "nodes" => (nodes(&mut lights), row_camera(aspect_ratio)),
+        "noise" => (noise(&mut lights), row_camera(aspect_ratio)),
+        "emitters" => (emitters(&mut lights), row_camera(aspect_ratio)),
+        "many_lights" => (
+            many_lights(&mut lights),
+            pinhole(
+                Point3::new(0.0, 6.0, 26.0),
+                Point3::new(0.0, 0.0, 0.0),
+                40.0,
+                aspect_ratio,
+            ),
+        ),
+        "environment" => (
+            environment(&mut lights),
+            pinhole(
+                Point3::new(0.0, 2.0, 10.0),
+                Point3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+            ),
+        ),
+        "overcast" => (overcast(&mut lights), row_camera(aspect_ratio)),
+        "sky" => (
+            sky(&mut lights),
+            pinhole(
+                Point3::new(0.0, 2.0, 12.0),
+                Point3::new(0.0, 1.5, 0.0),
+                50.0,
+                aspect_ratio,
+            ),
+        ),
+        "delta_lights" => (delta_lights(&mut lights), row_camera(aspect_ratio)),
+        "ies" => (
+            ies(&mut lights),
+            pinhole(
+                Point3::new(0.0, 3.0, 14.0),
+                Point3::new(0.0, 2.0, -2.0),
+                50.0,
+                aspect_ratio,
+            ),
+        ),
+        "cornell" => {
+            let mut world = HittableList::new_default();
+            world.add(Arc::new(cornell_box(&mut lights)));
+            (
+                world,
+                pinhole(
+                    Point3::new(278.0, 278.0, -800.0),
+                    Point3::new(278.0, 278.0, 0.0),
+                    40.0,
+                    aspect_ratio,
+                ),
+            )
+        }
+        "caustics" => (
+            caustics(&mut lights),
+            pinhole(
+                Point3::new(0.0, 3.0, 9.0),
+                Point3::new(0.0, 1.0, 0.0),
+                40.0,
+                aspect_ratio,
+            ),
+        ),
+        "nebula" => (
+            nebula(&mut lights),
+            pinhole(
+                Point3::new(0.0, 4.0, 24.0),
+                Point3::zero(),
+                40.0,
+                aspect_ratio,
+            ),
+        ),
+        _ => return None,
+    };
+    lights.build();
+    Some(Scene {
+        world,
+        lights,
+        camera,
+    })
+}
+
+//one small bright light behind glass spheres of rising dispersion, the caustics on the floor only split into colors with SPECTRAL=true
+fn dispersion(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    objects.add(Arc::new(XZRect::new(
+        -20.0,
+        20.0,
+        -20.0,
+        20.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+    )));
+    objects.add(lights.add_sphere(
+        Point3::new(0.0, 6.0, -6.0),
+        0.3,
+        Arc::new(DiffuseLight::new_by_color(Color::new(400.0, 400.0, 400.0))),
+    ));
+
+    //fixed ior, then crown glass from its sellmeier fit, then a dense flint
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-2.5, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new_bk7()),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(2.5, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new_cauchy(1.728, 0.01342)),
+    )));
+
+    objects
+}
+
+//unit spheres side by side on a gray floor under one round light, for comparing materials
+fn material_row(lights: &mut LightList, materials: Vec<Arc<dyn Material>>) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    objects.add(lights.add_sphere(
+        Point3::new(0.0, 10.0, 6.0),
+        2.0,
+        Arc::new(DiffuseLight::new_by_color(Color::new(8.0, 8.0, 8.0))),
+    ));
+
+    let first = -1.25 * (materials.len() as f64 - 1.0);
+    for (i, mat) in materials.into_iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(first + 2.5 * i as f64, 1.0, 0.0),
+            1.0,
+            mat,
+        )));
+    }
+
+    objects
+}
+
+//plastic, brushed metal, clearcoated paint, sheen cloth and rough glass from the one principled model
+fn disney(lights: &mut LightList) -> HittableList {
+    let value = |v: f64| Arc::new(SolidColor::new_by_value(v));
+    let plastic = Disney {
+        roughness: value(0.3),
+        ..Disney::new(Color::new(0.1, 0.3, 0.8))
+    };
+    let brushed = Disney {
+        metallic: value(1.0),
+        roughness: value(0.35),
+        anisotropic: value(0.8),
+        ..Disney::new(Color::new(0.95, 0.64, 0.54))
+    };
+    let paint = Disney {
+        roughness: value(0.6),
+        clearcoat: value(1.0),
+        specular_tint: value(0.5),
+        ..Disney::new(Color::new(0.7, 0.05, 0.05))
+    };
+    let cloth = Disney {
+        roughness: value(1.0),
+        specular: value(0.0),
+        sheen: value(1.0),
+        ..Disney::new(Color::new(0.2, 0.5, 0.2))
+    };
+    let glass = Disney {
+        roughness: value(0.05),
+        transmission: value(1.0),
+        ..Disney::new(Color::ones())
+    };
+    material_row(
+        lights,
+        vec![
+            Arc::new(plastic),
+            Arc::new(brushed),
+            Arc::new(paint),
+            Arc::new(cloth),
+            Arc::new(glass),
+        ],
+    )
+}
+
+//each base bare and then under a coat: glossy over matte paint, a thick amber coat over brushed metal
+fn layered(lights: &mut LightList) -> HittableList {
+    let paint: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.7, 0.1, 0.1)));
+    let brushed: Arc<dyn Material> = Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.3));
+    material_row(
+        lights,
+        vec![
+            paint.clone(),
+            Arc::new(Layered::new(paint, 1.5, 0.0)),
+            brushed.clone(),
+            Arc::new(Layered::new_tinted(
+                brushed,
+                1.5,
+                0.1,
+                0.2,
+                Color::new(0.5, 2.0, 6.0),
+            )),
+        ],
+    )
+}
+
+//the same clay from smooth to rough, then moon dust and a thin translucent shell
+fn diffuse(lights: &mut LightList) -> HittableList {
+    let clay = Color::new(0.8, 0.55, 0.4);
+    material_row(
+        lights,
+        vec![
+            Arc::new(Lambertian::new(clay)),
+            Arc::new(OrenNayar::new(clay, 20.0)),
+            Arc::new(OrenNayar::new(clay, 60.0)),
+            Arc::new(Hapke::new(Color::new(0.6, 0.6, 0.6))),
+            Arc::new(Translucent::new(
+                Color::new(0.3, 0.5, 0.2),
+                Color::new(0.4, 0.6, 0.1),
+            )),
+        ],
+    )
+}
+
+//a soap bubble and oil on steel with swirling thickness, an antireflection coat on crown glass and anodized titanium
+fn thin_film(lights: &mut LightList) -> HittableList {
+    let swirl = Arc::new(NoiseTexture::new(2.0));
+    let even = |t: f64| Arc::new(SolidColor::new_by_value(t));
+    material_row(
+        lights,
+        vec![
+            Arc::new(ThinFilm::new_on_dielectric(
+                Dielectric::new(1.0),
+                1.33,
+                swirl.clone(),
+                100.0,
+                900.0,
+            )),
+            Arc::new(ThinFilm::new_on_dielectric(
+                Dielectric::new_bk7(),
+                1.38,
+                even(1.0),
+                0.0,
+                100.0,
+            )),
+            Arc::new(ThinFilm::new_on_metal(
+                Metal::new(Color::new(0.6, 0.6, 0.62), 0.05),
+                1.5,
+                swirl,
+                200.0,
+                700.0,
+            )),
+            Arc::new(ThinFilm::new_on_metal(
+                Metal::new(Color::new(0.55, 0.5, 0.45), 0.0),
+                2.4,
+                even(0.5),
+                0.0,
+                300.0,
+            )),
+        ],
+    )
+}
+
+//rust eating into steel through a noise mask, an even blend, and paint that turns to mirror at grazing angles
+fn mix(lights: &mut LightList) -> HittableList {
+    let steel: Arc<dyn Material> = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.85), 0.1));
+    let rust: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.45, 0.2, 0.08)));
+    material_row(
+        lights,
+        vec![
+            Arc::new(MixMaterial::new(
+                steel.clone(),
+                rust.clone(),
+                Arc::new(NoiseTexture::new(3.0)),
+            )),
+            Arc::new(MixMaterial::new_by_ratio(steel.clone(), rust.clone(), 0.5)),
+            Arc::new(FresnelMixMaterial::new(rust, steel, 1.5)),
+        ],
+    )
+}
+
+//smooth clay, the same clay bumped by noise, and steel faceted by a checker of tilted tangent space normals
+fn normal_map(lights: &mut LightList) -> HittableList {
+    let clay: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.55, 0.4)));
+    let steel: Arc<dyn Material> = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.85), 0.05));
+    let facets = Arc::new(CheckerTexture::new(
+        Arc::new(SolidColor::new(Color::new(0.75, 0.5, 0.85))),
+        Arc::new(SolidColor::new(Color::new(0.25, 0.5, 0.85))),
+        4.0,
+    ));
+    material_row(
+        lights,
+        vec![
+            clay.clone(),
+            Arc::new(NormalMapped::new_bump_map(
+                clay,
+                Arc::new(NoiseTexture::new(4.0)),
+                0.05,
+            )),
+            Arc::new(NormalMapped::new_normal_map(steel, facets)),
+        ],
+    )
+}
+
+//white rgba texels with the given alpha per texel, rows from the top
+fn stencil(width: u32, height: u32, alpha: impl Fn(u32, u32) -> f32) -> ImageTexture {
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            data.extend_from_slice(&[1.0, 1.0, 1.0, alpha(x, y)]);
+        }
+    }
+    ImageTexture::from_data(width, height, data)
+}
+
+//opaque texels of the given linear color
+fn stencil_rgb(width: u32, height: u32, color: impl Fn(u32, u32) -> [f32; 3]) -> ImageTexture {
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = color(x, y);
+            data.extend_from_slice(&[r, g, b, 1.0]);
+        }
+    }
+    ImageTexture::from_data(width, height, data)
+}
+
+//a lattice cut out of a sphere, a sphere that keeps half of its hits, and a card with a round hole
+fn alpha_mask(lights: &mut LightList) -> HittableList {
+    let mut objects = material_row(lights, Vec::new());
+    let paint: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.6, 0.2)));
+    let sphere = |x: f64| -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(x, 1.0, 0.0), 1.0, paint.clone()))
+    };
+
+    let lattice = stencil(
+        64,
+        32,
+        |x, y| {
+            if x % 8 < 2 || y % 8 < 2 {
+                1.0
+            } else {
+                0.0
+            }
+        },
+    );
+    objects.add(Arc::new(AlphaMask::new(
+        sphere(-2.5),
+        Arc::new(lattice),
+        0.5,
+    )));
+    let half = stencil(1, 1, |_, _| 0.5);
+    objects.add(Arc::new(AlphaMask::new_stochastic(
+        sphere(0.0),
+        Arc::new(half),
+    )));
+
+    let hole = stencil(32, 32, |x, y| {
+        let (dx, dy) = (x as f32 - 15.5, y as f32 - 15.5);
+        if dx * dx + dy * dy > 100.0 {
+            1.0
+        } else {
+            0.0
+        }
+    });
+    let card = Arc::new(XYRect::new(1.5, 3.5, 0.0, 2.0, 0.0, paint.clone()));
+    objects.add(Arc::new(AlphaMask::new(card, Arc::new(hole), 0.5)));
+
+    objects
+}
+
+//the earth map as color, and read again without srgb decoding as the height of its own bump map
+fn earth(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let path = "raytracer/earthmap.jpg";
+    let color = ImageTexture::new(path).expect("failed to load raytracer/earthmap.jpg");
+    let height = ImageTexture::new_linear(path).expect("failed to load raytracer/earthmap.jpg");
+
+    objects.add(Arc::new(Sphere::new(
+        Point3::zero(),
+        2.0,
+        Arc::new(NormalMapped::new_bump_map(
+            Arc::new(Lambertian::new_by_pointer(Arc::new(color))),
+            Arc::new(height),
+            0.02,
+        )),
+    )));
+    objects.add(lights.add_sphere(
+        Point3::new(-10.0, 5.0, 10.0),
+        3.0,
+        Arc::new(DiffuseLight::new_by_color(Color::new(10.0, 10.0, 10.0))),
+    ));
+
+    objects
+}
+
+//a checker floor running to the horizon in four strips, left to right:
+//nearest without mips, bilinear trilinear, bicubic ewa, and a mirrored wrap of a checker with one marked corner
+fn filtering(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let checker = |mark: bool| {
+        stencil_rgb(8, 8, |x, y| {
+            if mark && x < 2 && y < 2 {
+                [0.8, 0.1, 0.1]
+            } else if (x / 2 + y / 2) % 2 == 0 {
+                [0.9, 0.9, 0.9]
+            } else {
+                [0.05, 0.05, 0.05]
+            }
+        })
+    };
+    let modes = [
+        (WrapMode::Repeat, Filter::Nearest, MipFilter::None, false),
+        (
+            WrapMode::Repeat,
+            Filter::Bilinear,
+            MipFilter::Trilinear,
+            false,
+        ),
+        (WrapMode::Repeat, Filter::Bicubic, MipFilter::Ewa, false),
+        (
+            WrapMode::Mirror,
+            Filter::Bilinear,
+            MipFilter::Trilinear,
+            true,
+        ),
+    ];
+    for (i, &(wrap, filter, mip_filter, mark)) in modes.iter().enumerate() {
+        let mut texture = checker(mark);
+        texture.wrap = wrap;
+        texture.filter = filter;
+        texture.mip_filter = mip_filter;
+        texture.transform = UvTransform::new((4.0, 100.0), (0.0, 0.0), 0.0);
+        let x0 = -20.0 + 10.0 * i as f64;
+        objects.add(Arc::new(XZRect::new(
+            x0,
+            x0 + 10.0,
+            -500.0,
+            10.0,
+            0.0,
+            Arc::new(Lambertian::new_by_pointer(Arc::new(texture))),
+        )));
+    }
+    objects.add(lights.add_sphere(
+        Point3::new(0.0, 30.0, -20.0),
+        10.0,
+        Arc::new(DiffuseLight::new_by_color(Color::new(4.0, 4.0, 4.0))),
+    ));
+
+    objects
+}
+
+//one sphere per procedural texture on a checkered floor, inputs of the textures are textures themselves
+fn procedural(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let solid = |c: Color| -> Arc<dyn Texture> { Arc::new(SolidColor::new(c)) };
+
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::new_by_pointer(Arc::new(
+            CheckerTexture::new_by_color(Color::new(0.2, 0.3, 0.1), Color::new(0.9, 0.9, 0.9)),
+        ))),
+    )));
+    objects.add(lights.add_sphere(
+        Point3::new(0.0, 12.0, 8.0),
+        2.5,
+        Arc::new(DiffuseLight::new_by_color(Color::new(8.0, 8.0, 8.0))),
+    ));
+
+    let marble = Arc::new(MarbleTexture::new(
+        4.0,
+        10.0,
+        7,
+        solid(Color::new(0.9, 0.9, 0.85)),
+        solid(Color::new(0.2, 0.2, 0.25)),
+    ));
+    let textures: Vec<Arc<dyn Texture>> = vec![
+        Arc::new(FbmTexture::new_by_color(
+            2.0,
+            6,
+            Color::new(0.1, 0.2, 0.5),
+            Color::new(0.9, 0.9, 1.0),
+        )),
+        Arc::new(RidgedTexture::new(
+            1.5,
+            6,
+            2.0,
+            0.5,
+            solid(Color::new(0.3, 0.2, 0.1)),
+            solid(Color::new(0.95, 0.95, 0.95)),
+        )),
+        Arc::new(WorleyTexture::new(
+            4.0,
+            WorleyMode::F2MinusF1,
+            solid(Color::new(0.1, 0.05, 0.0)),
+            solid(Color::new(0.9, 0.6, 0.2)),
+        )),
+        //nearest feature distance at the bottom, second nearest at the top
+        Arc::new(GradientTexture::new(
+            Gradient::V,
+            Arc::new(WorleyTexture::new(
+                4.0,
+                WorleyMode::F1,
+                solid(Color::new(0.9, 0.9, 0.9)),
+                solid(Color::new(0.1, 0.4, 0.2)),
+            )),
+            Arc::new(WorleyTexture::new(
+                4.0,
+                WorleyMode::F2,
+                solid(Color::new(0.9, 0.9, 0.9)),
+                solid(Color::new(0.1, 0.2, 0.4)),
+            )),
+        )),
+        Arc::new(WoodTexture::new_by_color(
+            3.0,
+            Color::new(0.8, 0.55, 0.3),
+            Color::new(0.4, 0.2, 0.08),
+        )),
+        marble.clone(),
+        Arc::new(UvCheckerTexture::new(
+            Arc::new(GradientTexture::new(
+                Gradient::U,
+                solid(Color::new(0.9, 0.1, 0.1)),
+                solid(Color::new(0.9, 0.8, 0.1)),
+            )),
+            Arc::new(UvCheckerTexture::new_by_color(
+                Color::new(0.9, 0.9, 0.9),
+                Color::new(0.6, 0.6, 0.6),
+                64.0,
+                32.0,
+            )),
+            16.0,
+            8.0,
+        )),
+        Arc::new(StripesTexture::new(
+            marble,
+            solid(Color::new(0.1, 0.3, 0.6)),
+            Vec3::new(1.0, 1.0, 0.0),
+            0.5,
+            0.5,
+        )),
+        Arc::new(GradientTexture::new(
+            Gradient::Spherical {
+                center: Point3::new(8.0, 0.9, 0.0),
+                radius: 0.9,
+            },
+            solid(Color::new(1.0, 0.8, 0.1)),
+            Arc::new(GradientTexture::new(
+                Gradient::Linear {
+                    from: Point3::new(8.0, 0.0, 0.0),
+                    to: Point3::new(8.0, 1.8, 0.0),
+                },
+                solid(Color::new(0.6, 0.0, 0.3)),
+                solid(Color::new(0.1, 0.1, 0.6)),
+            )),
+        )),
+    ];
+    for (i, texture) in textures.into_iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-8.0 + 2.0 * i as f64, 0.9, 0.0),
+            0.9,
+            Arc::new(Lambertian::new_by_pointer(texture)),
+        )));
+    }
+
+    objects
+}
+
+//texture graphs: a height ramp, clamped noise inverted over a uv ramp, hue shifted normals, uv with a lattice in blue, and mixes of those
+fn nodes(lights: &mut LightList) -> HittableList {
+    let channel = |input: Arc<dyn Texture>, channel| -> Arc<dyn Texture> {
+        Arc::new(ChannelTexture::new(input, channel))
+    };
+    let coordinate = |c| -> Arc<dyn Texture> { Arc::new(CoordinateTexture::new(c)) };
+    let noise: Arc<dyn Texture> = Arc::new(NoiseTexture::new(4.0));
+
+    let height = Arc::new(RampTexture::new(
+        Arc::new(RemapTexture::new(
+            channel(coordinate(Coordinate::Object), Channel::G),
+            (0.0, 2.0),
+            (0.0, 1.0),
+        )),
+        ColorRamp::new(vec![
+            (0.0, Color::new(0.05, 0.1, 0.4)),
+            (0.5, Color::new(0.1, 0.6, 0.2)),
+            (1.0, Color::new(0.95, 0.95, 0.95)),
+        ])
+        .expect("finite stops"),
+    ));
+    let veins = Arc::new(MultiplyTexture::new(
+        Arc::new(InvertTexture::new(Arc::new(ClampTexture::new(
+            noise.clone(),
+            0.3,
+            0.7,
+        )))),
+        Arc::new(RampTexture::new(
+            coordinate(Coordinate::Uv),
+            ColorRamp::new_by_colors(Color::new(0.9, 0.5, 0.1), Color::new(0.6, 0.05, 0.05)),
+        )),
+    ));
+    let hues = Arc::new(HsvTexture::new(
+        coordinate(Coordinate::Normal),
+        0.3,
+        1.5,
+        1.0,
+    ));
+    let lattice = Arc::new(stencil(64, 32, |x, y| {
+        if x % 8 < 2 || y % 8 < 2 {
+            1.0
+        } else {
+            0.0
+        }
+    }));
+    let uv = Arc::new(CombineTexture::new(
+        channel(coordinate(Coordinate::Uv), Channel::R),
+        channel(coordinate(Coordinate::Uv), Channel::G),
+        channel(lattice, Channel::Alpha),
+    ));
+    let stripes = Arc::new(AddTexture::new(
+        Arc::new(CombineTexture::new_gray(channel(
+            noise.clone(),
+            Channel::Luminance,
+        ))),
+        channel(coordinate(Coordinate::World), Channel::B),
+    ));
+    let mixed = Arc::new(MixTexture::new(
+        Arc::new(MixTexture::new_by_ratio(height.clone(), veins.clone(), 0.5)),
+        stripes,
+        noise,
+    ));
+
+    let textures: Vec<Arc<dyn Texture>> = vec![height, veins, hues, uv, mixed];
+    material_row(
+        lights,
+        textures
+            .into_iter()
+            .map(|t| Arc::new(Lambertian::new_by_pointer(t)) as Arc<dyn Material>)
+            .collect(),
+    )
+}
+
+//the same seeded fbm over the classic, improved and simplex bases, then the book's turbulence marble
+fn noise(lights: &mut LightList) -> HittableList {
+    let fbm = |basis| {
+        let mut texture =
+            FbmTexture::new_by_color(3.0, 6, Color::new(0.1, 0.1, 0.1), Color::new(0.9, 0.9, 0.9));
+        texture.noise = Perlin::with_seed(7);
+        texture.basis = basis;
+        Arc::new(Lambertian::new_by_pointer(Arc::new(texture))) as Arc<dyn Material>
+    };
+    material_row(
+        lights,
+        vec![
+            fbm(NoiseBasis::Classic),
+            fbm(NoiseBasis::Improved),
+            fbm(NoiseBasis::Simplex),
+            Arc::new(Lambertian::new_by_pointer(Arc::new(NoiseTexture::new(4.0)))),
+        ],
+    )
+}
+
+//no light but the emitters: a one sided panel of fixed power with a grid of colored cells, a two sided checkered
+//panel at plain radiance, and a candle, tungsten, daylight and clear sky blackbody at the same brightness
+fn emitters(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    let cells = stencil_rgb(4, 3, |x, y| match (x + y) % 3 {
+        0 => [1.0, 0.2, 0.2],
+        1 => [0.2, 1.0, 0.2],
+        _ => [0.2, 0.2, 1.0],
+    });
+    objects.add(lights.add_rect(
+        Point3::new(-5.0, 0.5, -3.0),
+        Point3::new(-1.0, 3.5, -3.0),
+        Arc::new(DiffuseLight::new_with_power(
+            Arc::new(cells),
+            150.0,
+            12.0,
+            false,
+        )),
+    ));
+    let checker =
+        CheckerTexture::new_by_color(Color::new(3.0, 3.0, 3.0), Color::new(0.3, 0.3, 0.3));
+    objects.add(lights.add_rect(
+        Point3::new(1.0, 0.5, -3.0),
+        Point3::new(5.0, 3.5, -3.0),
+        Arc::new(DiffuseLight::new_by_pointer(Arc::new(checker))),
+    ));
+
+    for (i, kelvin) in [1900.0, 2700.0, 5500.0, 10000.0].iter().enumerate() {
+        objects.add(lights.add_sphere(
+            Point3::new(-3.0 + 2.0 * i as f64, 0.5, 1.5),
+            0.5,
+            Arc::new(DiffuseLight::new_blackbody(*kelvin, 6.0)),
+        ));
+    }
+
+    objects
+}
+
+//a field of 900 small colored lights hovering over matte spheres, only the nearest few matter at any point
+fn many_lights(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+
+    let palette = [
+        Color::new(60.0, 12.0, 12.0),
+        Color::new(12.0, 60.0, 12.0),
+        Color::new(12.0, 12.0, 60.0),
+        Color::new(40.0, 40.0, 40.0),
+    ];
+    let n = 30;
+    let mut bulbs = HittableList::new_default();
+    for i in 0..n {
+        for j in 0..n {
+            let x = -15.0 + 30.0 * (i as f64 + 0.5) / n as f64;
+            let z = -15.0 + 30.0 * (j as f64 + 0.5) / n as f64;
+            bulbs.add(lights.add_sphere(
+                Point3::new(x, 0.6 + 0.4 * ((i * 7 + j * 3) % 5) as f64, z),
+                0.08,
+                Arc::new(DiffuseLight::new_by_color(palette[(i + 2 * j) % 4])),
+            ));
+        }
+    }
+    objects.add(Arc::new(BvhNode::new_(&mut bulbs, 0.0, 1.0)));
+
+    for k in 0..5 {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-8.0 + 4.0 * k as f64, 1.0, 2.0 - 3.0 * (k % 2) as f64),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        )));
+    }
+
+    objects
+}
+
+//a studio exr as the only light, shadows on the floor come from its softbox through importance sampling
+fn environment(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let studio = EnvironmentMap::new("raytracer/studio.exr", 30.0, 1.0)
+        .expect("failed to load raytracer/studio.exr");
+    lights.add(Arc::new(studio));
+
+    objects.add(Arc::new(XZRect::new(
+        -6.0,
+        6.0,
+        -6.0,
+        6.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6))),
+    )));
+    let materials: [Arc<dyn Material>; 3] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.3, 0.2))),
+        Arc::new(Metal::new(Color::new(0.95, 0.95, 0.95), 0.05)),
+        Arc::new(Dielectric::new(1.5)),
+    ];
+    for (i, mat) in materials.iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-2.2 + 2.2 * i as f64, 1.0, 0.0),
+            1.0,
+            mat.clone(),
+        )));
+    }
+
+    objects
+}
+
+//the book's pale blue background as a light, soft contact shadows and nothing else
+fn overcast(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    lights.add(Arc::new(UniformEnvironment::new(Color::new(0.7, 0.8, 1.0))));
+
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    let materials: [Arc<dyn Material>; 3] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.3)),
+        Arc::new(Dielectric::new(1.5)),
+    ];
+    for (i, mat) in materials.iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-2.5 + 2.5 * i as f64, 1.0, 0.0),
+            1.0,
+            mat.clone(),
+        )));
+    }
+
+    objects
+}
+
+//late afternoon daylight and its sun, low enough that the columns cast long shadows across the ground
+fn sky(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let daylight = PhysicalSky::new(
+        sun_direction(12.0, -30.0),
+        3.0,
+        Color::new(0.3, 0.3, 0.3),
+        0.1,
+    );
+    lights.add(Arc::new(daylight.sun(0.27, 8.0)));
+    lights.add(Arc::new(daylight));
+
+    objects.add(Arc::new(XZRect::new(
+        -200.0,
+        200.0,
+        -200.0,
+        200.0,
+        0.0,
+        Arc::new(Lambertian::new(Color::new(0.4, 0.38, 0.35))),
+    )));
+    let stone = Arc::new(Lambertian::new(Color::new(0.8, 0.78, 0.72)));
+    for i in 0..4 {
+        objects.add(Arc::new(_Box::new(
+            Point3::new(-5.5 + 3.0 * i as f64, 0.0, -3.0),
+            Point3::new(-4.5 + 3.0 * i as f64, 4.0, -2.0),
+            stone.clone(),
+        )));
+    }
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.5, 1.0, 2.0),
+        1.0,
+        Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.02)),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.5, 1.0, 2.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+
+    objects
+}
+
+//lights no ray can hit: a warm point light, a spot throwing a window gobo on the wall and a faint blue moonlight
+fn delta_lights(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    let gray = Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6)));
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        gray.clone(),
+    )));
+    objects.add(Arc::new(XYRect::new(-50.0, 50.0, 0.0, 50.0, -4.0, gray)));
+
+    lights.add(Arc::new(PointLight::new(
+        Point3::new(-3.5, 3.0, 2.0),
+        Color::new(30.0, 22.0, 12.0),
+    )));
+    //four panes behind cross bars
+    let window = stencil_rgb(16, 16, |x, y| {
+        if x == 0 || y == 0 || x == 15 || y == 15 || x == 7 || x == 8 || y == 7 || y == 8 {
+            [0.0, 0.0, 0.0]
+        } else {
+            [1.0, 1.0, 1.0]
+        }
+    });
+    let mut spot = SpotLight::new(
+        Point3::new(4.0, 6.0, 6.0),
+        Point3::new(2.0, 2.0, -4.0),
+        Color::new(200.0, 200.0, 200.0),
+        12.0,
+        10.0,
+    );
+    spot.gobo = Some(Arc::new(window));
+    lights.add(Arc::new(spot));
+    lights.add(Arc::new(DirectionalLight::new(
+        Vec3::new(0.3, -1.0, -0.4),
+        Color::new(0.05, 0.07, 0.15),
+    )));
+
+    let materials: [Arc<dyn Material>; 3] = [
+        Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        Arc::new(Metal::new(Color::new(0.9, 0.7, 0.4), 0.2)),
+        Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.8))),
+    ];
+    for (i, mat) in materials.iter().enumerate() {
+        objects.add(Arc::new(Sphere::new(
+            Point3::new(-2.5 + 2.5 * i as f64, 1.0, 0.0),
+            1.0,
+            mat.clone(),
+        )));
+    }
+
+    objects
+}
+
+//one batwing downlight profile on a point light, on a spot that trims it to a narrow cone and on a ceiling panel,
+//each throws its ring onto the floor and scallops on the wall behind
+fn ies(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+    let profile = Arc::new(
+        IesProfile::load("raytracer/downlight.ies")
+            .expect("failed to load raytracer/downlight.ies"),
+    );
+
+    let gray = Arc::new(Lambertian::new(Color::new(0.6, 0.6, 0.6)));
+    objects.add(Arc::new(XZRect::new(
+        -50.0,
+        50.0,
+        -50.0,
+        50.0,
+        0.0,
+        gray.clone(),
+    )));
+    objects.add(Arc::new(XYRect::new(-50.0, 50.0, 0.0, 50.0, -3.0, gray)));
+
+    let down = Vec3::new(0.0, -1.0, 0.0);
+    lights.add(Arc::new(PointLight::new_with_profile(
+        Point3::new(-4.5, 4.0, -2.0),
+        Color::new(8.0, 8.0, 8.0),
+        down,
+        profile.clone(),
+    )));
+    let mut spot = SpotLight::new(
+        Point3::new(0.0, 4.0, -2.0),
+        Point3::new(0.0, 0.0, -2.0),
+        Color::new(8.0, 8.0, 8.0),
+        30.0,
+        25.0,
+    );
+    spot.profile = Some(profile.clone());
+    lights.add(Arc::new(spot));
+    let mut panel = DiffuseLight::new_by_color(Color::new(6.0, 6.0, 6.0));
+    panel.profile = Some(profile);
+    objects.add(lights.add_rect(
+        Point3::new(4.0, 4.0, -2.5),
+        Point3::new(5.0, 4.0, -1.5),
+        Arc::new(panel),
+    ));
+
+    objects
+}
+
+//a small hot panel above a glass ball and a gold one, the focused spot under the glass is what sppm is for
+fn caustics(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    objects.add(Arc::new(XZRect::new(
+        -20.0,
+        20.0,
+        -20.0,
+        20.0,
+        0.0,
+        white.clone(),
+    )));
+    objects.add(Arc::new(XYRect::new(-20.0, 20.0, 0.0, 20.0, -3.0, white)));
+    objects.add(lights.add_rect(
+        Point3::new(-2.5, 5.0, -0.25),
+        Point3::new(-1.5, 5.0, 0.75),
+        Arc::new(DiffuseLight::new_by_color(Color::new(40.0, 40.0, 40.0))),
+    ));
+
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(-1.2, 1.0, 0.5),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    objects.add(Arc::new(Sphere::new(
+        Point3::new(1.5, 1.0, -0.5),
+        1.0,
+        Arc::new(Metal::new(Color::new(1.0, 0.78, 0.34), 0.0)),
+    )));
+
+    objects
+}
+
+//warped fbm baked into a voxel grid, thinning out toward the rim of its sphere and lit by a star inside it
+//and a second one off to the side
+fn nebula(lights: &mut LightList) -> HittableList {
+    let mut objects = HittableList::new_default();
+
+    let center = Point3::zero();
+    let radius = 7.0;
+    let perlin = Perlin::with_seed(5);
+    let half = Vec3::ones() * radius;
+    let grid = VoxelGrid::new_by_fn(AABB::new(center - half, center + half), (64, 64, 64), |p| {
+        let local = (p - center) / radius;
+        let falloff = (1.0 - local.length()).max(0.0);
+        let q = perlin.warp(NoiseBasis::Simplex, local * 2.0, 0.8, 3);
+        0.6 * falloff * (perlin.fbm(NoiseBasis::Simplex, q, 5, 2.0, 0.5) + 0.3)
+    });
+    objects.add(Arc::new(HeterogeneousMedium::new_by_grid(
+        Arc::new(Sphere::new(
+            center,
+            radius,
+            Arc::new(Lambertian::new(Color::zero())),
+        )),
+        grid,
+        Color::new(0.8, 0.5, 0.9),
+    )));
+
+    objects.add(lights.add_sphere(
+        Point3::new(1.0, -0.5, 1.0),
+        0.4,
+        Arc::new(DiffuseLight::new_blackbody(9000.0, 60.0)),
+    ));
+    objects.add(lights.add_sphere(
+        Point3::new(-10.0, 6.0, -4.0),
+        1.0,
+        Arc::new(DiffuseLight::new_blackbody(3500.0, 40.0)),
+    ));
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator;
+    use crate::integrator::IntegratorOptions;
+
+    //scenes that load images relative to the workspace root, tests run from the package
+    const FROM_FILES: [&str; 4] = ["default", "earth", "environment", "ies"];
+
+    #[test]
+    fn test_every_listed_name_builds_a_scene() {
+        for name in SCENE_NAMES.iter().filter(|name| !FROM_FILES.contains(name)) {
+            let scene = by_name(name, 1.0);
+            assert!(scene.is_some(), "{}", name);
+        }
+        assert!(by_name("cornell_smoke", 1.0).is_none());
+    }
+
+    #[test]
+    fn test_every_scene_renders_finite_radiance() {
+        for name in SCENE_NAMES.iter().filter(|name| !FROM_FILES.contains(name)) {
+            let scene = by_name(name, 1.0).unwrap();
+            let world = Arc::new(scene.world);
+            let lights = Arc::new(scene.lights);
+            let options = IntegratorOptions {
+                max_depth: 8,
+                film: (8.0, 8.0),
+                spectral: false,
+                rr_depth: 3,
+                max_indirect: None,
+            };
+            let path =
+                integrator::by_name("path", &scene.camera, &world, &lights, &options).unwrap();
+            let mut total = Color::zero();
+            for i in 0..64 {
+                let r = scene
+                    .camera
+                    .get_ray((i % 8) as f64 / 7.0, (i / 8) as f64 / 7.0);
+                let li = path.li(r, &mut Vec::new());
+                assert!(
+                    li.x.is_finite() && li.y.is_finite() && li.z.is_finite(),
+                    "{}",
+                    name
+                );
+                assert!(li.x >= 0.0 && li.y >= 0.0 && li.z >= 0.0, "{}", name);
+                total += li;
+            }
+            assert!(total.length() > 0.0, "{} renders black", name);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::light::{Light, LightSample};
+use crate::onb::Onb;
+use crate::rtweekend::{degrees_to_radians, INFINITY, PI};
+use crate::sampling::Distribution2D;
+use crate::spectrum::{blackbody_rgb, xyz_to_linear_srgb};
+use crate::vec3::{Color, Point3};
+use crate::{Ray, Vec3};
+
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+//direction toward the sun, elevation above the horizon and azimuth from +x toward +z in degrees
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let (elevation, azimuth) = (degrees_to_radians(elevation), degrees_to_radians(azimuth));
+    Vec3::new(
+        elevation.cos() * azimuth.cos(),
+        elevation.sin(),
+        elevation.cos() * azimuth.sin(),
+    )
+}
+
+//Perez distribution coefficients A..E
+#[derive(Clone, Copy, Debug)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    fn f(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.0;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta.max(1e-3)).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+//PhysicalSky
+//Preetham et al. analytic daylight, y is up and below the horizon is a diffuse ground lit by the sky alone
+pub struct PhysicalSky {
+    sun: Vec3,
+    turbidity: f64,
+    theta_sun: f64,
+    //zenith luminance and chromaticity, and the Perez functions of Y, x and y
+    zenith: Vec3,
+    perez: [Perez; 3],
+    ground: Color,
+    //luminance is in kcd/m^2, a clear noon sky reaches about 10 before scaling
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl PhysicalSky {
+    //turbidity runs from 2 (very clear) to about 10 (hazy)
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color, intensity: f64) -> Self {
+        let sun = sun_direction.unit();
+        let t = turbidity.max(1.0);
+        let theta_sun = sun.y.clamp(0.0, 1.0).acos();
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (th, th2, th3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let zenith_x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_yc = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let perez = [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ];
+
+        let mut sky = Self {
+            sun,
+            turbidity: t,
+            theta_sun,
+            zenith: Vec3::new(zenith_y, zenith_x, zenith_yc),
+            perez,
+            ground: Color::zero(),
+            intensity,
+            distribution: Distribution2D::new(&[1.0], 1, 1),
+        };
+
+        //tabulate the upper hemisphere to sample by luminance and to light the ground
+        let mut func = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        let mut irradiance = Color::zero();
+        for j in 0..TABLE_HEIGHT {
+            let theta = PI * (j as f64 + 0.5) / TABLE_HEIGHT as f64;
+            let sin_theta = theta.sin();
+            for i in 0..TABLE_WIDTH {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / TABLE_WIDTH as f64;
+                let d = Self::direction(theta, phi);
+                if d.y <= 0.0 {
+                    func.push(0.0);
+                    continue;
+                }
+                let l = sky.sky_radiance(d);
+                func.push((0.2126 * l.x + 0.7152 * l.y + 0.0722 * l.z) * sin_theta);
+                irradiance += l * (d.y * sin_theta);
+            }
+        }
+        irradiance *= 2.0 * PI * PI / (TABLE_WIDTH * TABLE_HEIGHT) as f64;
+        //the lower half keeps a little weight so paths toward the ground still get sampled
+        let ground = ground_albedo * irradiance / PI;
+        let ground_luminance = 0.2126 * ground.x + 0.7152 * ground.y + 0.0722 * ground.z;
+        for j in TABLE_HEIGHT / 2..TABLE_HEIGHT {
+            let sin_theta = (PI * (j as f64 + 0.5) / TABLE_HEIGHT as f64).sin();
+            for i in 0..TABLE_WIDTH {
+                func[j * TABLE_WIDTH + i] = ground_luminance * sin_theta;
+            }
+        }
+        sky.ground = ground;
+        sky.distribution = Distribution2D::new(&func, TABLE_WIDTH, TABLE_HEIGHT);
+        sky
+    }
+
+    //a sun disk matching the sky's sun direction and turbidity
+    pub fn sun(&self, angular_radius: f64, irradiance: f64) -> SunLight {
+        SunLight::new(self.sun, angular_radius, self.turbidity, irradiance)
+    }
+
+    //theta from +y, phi from +x toward +z
+    fn direction(theta: f64, phi: f64) -> Vec3 {
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+
+    fn angles(d: Vec3) -> (f64, f64) {
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+        let mut phi = d.z.atan2(d.x);
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        (theta, phi)
+    }
+
+    fn sky_radiance(&self, d: Vec3) -> Color {
+        let cos_gamma = Vec3::dot(d, self.sun).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let ratio = |k: usize| self.perez[k].f(d.y, gamma) / self.perez[k].f(1.0, self.theta_sun);
+        let luminance = self.zenith.x * ratio(0);
+        let x = self.zenith.y * ratio(1);
+        let y = self.zenith.z * ratio(2);
+        if y <= 0.0 {
+            return Color::zero();
+        }
+        let xyz = Vec3::new(luminance * x / y, luminance, luminance * (1.0 - x - y) / y);
+        let rgb = xyz_to_linear_srgb(xyz) * self.intensity;
+        Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    fn radiance(&self, d: Vec3) -> Color {
+        if d.y <= 0.0 {
+            return self.ground;
+        }
+        self.sky_radiance(d)
+    }
+}
+
+impl Light for PhysicalSky {
+    fn sample_li(&self, _p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let ((u, v), pdf_uv) = self.distribution.sample_continuous(u1, u2);
+        let (theta, phi) = (v * PI, u * 2.0 * PI);
+        let sin_theta = theta.sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let wi = Self::direction(theta, phi);
+        Some(LightSample {
+            wi,
+            li: self.radiance(wi),
+            pdf: pdf_uv / (2.0 * PI * PI * sin_theta),
+            dist: INFINITY,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, wi: Vec3) -> f64 {
+        let (theta, phi) = Self::angles(wi.unit());
+        let sin_theta = theta.sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(phi / (2.0 * PI), theta / PI) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        self.radiance(r.direction().unit())
+    }
+}
+
+//SunLight
+//uniform disk of the given angular radius in degrees, reddened by the atmosphere it crosses
+pub struct SunLight {
+    frame: Onb,
+    cos_max: f64,
+    radiance: Color,
+}
+
+impl SunLight {
+    //irradiance is what the disk delivers at normal incidence above the atmosphere
+    pub fn new(direction: Vec3, angular_radius: f64, turbidity: f64, irradiance: f64) -> Self {
+        let direction = direction.unit();
+        let cos_max = degrees_to_radians(angular_radius.max(1e-3)).cos();
+        let solid_angle = 2.0 * PI * (1.0 - cos_max);
+        let transmittance = Self::transmittance(direction.y, turbidity);
+        Self {
+            frame: Onb::build_from_w(direction),
+            cos_max,
+            radiance: blackbody_rgb(5778.0) * transmittance * (irradiance / solid_angle),
+        }
+    }
+
+    //Rayleigh and aerosol extinction from Preetham's appendix at 680, 550 and 440 nm
+    fn transmittance(cos_theta: f64, turbidity: f64) -> Color {
+        if cos_theta <= 0.0 {
+            return Color::zero();
+        }
+        let theta = cos_theta.acos().to_degrees();
+        let mass = 1.0 / (cos_theta + 0.15 * (93.885 - theta).powf(-1.253));
+        let beta = 0.04608 * turbidity - 0.04586;
+        let channel = |lambda: f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+            rayleigh * aerosol
+        };
+        Color::new(channel(0.68), channel(0.55), channel(0.44))
+    }
+}
+
+impl Light for SunLight {
+    fn sample_li(&self, _p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let cos_theta = 1.0 - u1 * (1.0 - self.cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        Some(LightSample {
+            wi: self.frame.local(Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            )),
+            li: self.radiance,
+            pdf: 1.0 / (2.0 * PI * (1.0 - self.cos_max)),
+            dist: INFINITY,
+        })
+    }
+
+    fn pdf_li(&self, _p: Point3, wi: Vec3) -> f64 {
+        if Vec3::dot(wi.unit(), self.frame.w()) < self.cos_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_max))
+    }
+
+    fn le(&self, r: &Ray) -> Color {
+        if Vec3::dot(r.direction().unit(), self.frame.w()) < self.cos_max {
+            return Color::zero();
+        }
+        self.radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_sky_samples_match_their_density() {
+        let mut rng = rand::thread_rng();
+        let sky = PhysicalSky::new(
+            sun_direction(30.0, 60.0),
+            3.0,
+            Color::new(0.3, 0.3, 0.3),
+            1.0,
+        );
+        let p = Point3::zero();
+        for _ in 0..200 {
+            if let Some(ls) = sky.sample_li(p, rng.gen::<f64>(), rng.gen::<f64>()) {
+                let pdf = sky.pdf_li(p, ls.wi);
+                assert!((ls.pdf - pdf).abs() <= 1e-6 * pdf.max(1.0));
+                assert!(ls.li.x >= 0.0 && ls.li.y >= 0.0 && ls.li.z >= 0.0);
+            }
+        }
+        //the sky is brighter around the sun than opposite it
+        let toward = sky.le(&Ray::new(p, sun_direction(35.0, 60.0), 0.0));
+        let away = sky.le(&Ray::new(p, sun_direction(35.0, 240.0), 0.0));
+        assert!(toward.y > away.y);
+    }
+
+    #[test]
+    fn test_sun_disk_is_sampled_inside_its_cone() {
+        let mut rng = rand::thread_rng();
+        let noon = SunLight::new(sun_direction(80.0, 0.0), 0.5, 3.0, 1.0);
+        let p = Point3::zero();
+        for _ in 0..100 {
+            let ls = noon
+                .sample_li(p, rng.gen::<f64>(), rng.gen::<f64>())
+                .unwrap();
+            assert!((ls.pdf - noon.pdf_li(p, ls.wi)).abs() < 1e-6 * ls.pdf);
+        }
+        assert_eq!(noon.pdf_li(p, Vec3::new(0.0, -1.0, 0.0)), 0.0);
+        //the low sun goes through more air and comes out redder
+        let dusk = SunLight::new(sun_direction(3.0, 0.0), 0.5, 3.0, 1.0);
+        let (a, b) = (noon.radiance, dusk.radiance);
+        assert!(b.x / b.z > a.x / a.z);
+        assert!(b.y < a.y);
+    }
+}
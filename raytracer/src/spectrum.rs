@@ -0,0 +1,379 @@
+use crate::rtweekend::clamp;
+use crate::vec3::Color;
+use crate::{Ray, Vec3};
+use std::ops::{Add, AddAssign, Div, Mul};
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+const CIE_Y_INTEGRAL: f64 = 106.856895;
+
+//SampledWavelengths
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct SampledWavelengths {
+    lambda: [f64; N_SPECTRUM_SAMPLES],
+    pdf: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    //hero wavelength sampling: one uniform sample, the rest evenly rotated through the range
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let delta = range / N_SPECTRUM_SAMPLES as f64;
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        lambda[0] = LAMBDA_MIN + u * range;
+        for i in 1..N_SPECTRUM_SAMPLES {
+            lambda[i] = lambda[i - 1] + delta;
+            if lambda[i] > LAMBDA_MAX {
+                lambda[i] -= range;
+            }
+        }
+        Self {
+            lambda,
+            pdf: [1.0 / range; N_SPECTRUM_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn lambda(&self, i: usize) -> f64 {
+        self.lambda[i]
+    }
+
+    pub fn pdf(&self, i: usize) -> f64 {
+        self.pdf[i]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|p| *p == 0.0)
+    }
+
+    //used by dispersive interfaces, only the hero wavelength keeps a valid path afterwards
+    //the film still sees the wavelengths the camera ray started with, so the hero is reweighted in the
+    //throughput of the terminating bounce, not here
+    pub fn terminate_secondary(&self) -> Self {
+        let mut tmp = *self;
+        for i in 1..N_SPECTRUM_SAMPLES {
+            tmp.pdf[i] = 0.0;
+        }
+        tmp
+    }
+}
+
+//throughput of a bounce in the wavelengths wl of the arriving ray, scattered carries them on unless the
+//material replaced them, only the hero wavelength survives a dispersive interface and stands in for all of them
+pub fn bounce_throughput(
+    attenuation: Color,
+    wl: &SampledWavelengths,
+    scattered: &mut Ray,
+) -> SampledSpectrum {
+    let mut throughput = SampledSpectrum::from_rgb(attenuation, wl);
+    match scattered.wavelengths() {
+        Some(next) if next.secondary_terminated() && !wl.secondary_terminated() => {
+            for i in 1..N_SPECTRUM_SAMPLES {
+                throughput.c[i] = 0.0;
+            }
+            throughput.c[0] *= N_SPECTRUM_SAMPLES as f64;
+        }
+        Some(_) => {}
+        None => *scattered = scattered.with_wavelengths(*wl),
+    }
+    throughput
+}
+
+//SampledSpectrum
+#[derive(Clone, Debug, PartialEq, Copy)]
+pub struct SampledSpectrum {
+    pub c: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(v: f64) -> Self {
+        Self {
+            c: [v; N_SPECTRUM_SAMPLES],
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::new(0.0)
+    }
+
+    pub fn from_rgb(rgb: Color, wl: &SampledWavelengths) -> Self {
+        let mut tmp = Self::zero();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            tmp.c[i] = rgb_to_spectrum(rgb, wl.lambda(i));
+        }
+        tmp
+    }
+
+    pub fn to_xyz(self, wl: &SampledWavelengths) -> Vec3 {
+        let mut xyz = Vec3::zero();
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if wl.pdf(i) == 0.0 {
+                continue;
+            }
+            xyz += cie_xyz(wl.lambda(i)) * (self.c[i] / wl.pdf(i));
+        }
+        xyz / (N_SPECTRUM_SAMPLES as f64 * CIE_Y_INTEGRAL)
+    }
+
+    pub fn to_rgb(self, wl: &SampledWavelengths) -> Color {
+        xyz_to_linear_srgb(self.to_xyz(wl))
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.c.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut tmp = self;
+        for i in 0..N_SPECTRUM_SAMPLES {
+            tmp.c[i] += other.c[i];
+        }
+        tmp
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut tmp = self;
+        for i in 0..N_SPECTRUM_SAMPLES {
+            tmp.c[i] *= other.c[i];
+        }
+        tmp
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: f64) -> Self {
+        let mut tmp = self;
+        for i in 0..N_SPECTRUM_SAMPLES {
+            tmp.c[i] *= other;
+        }
+        tmp
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn div(self, other: f64) -> Self {
+        self * (1.0 / other)
+    }
+}
+
+//CIE 1931 matching functions, multi-lobe gaussian fit (Wyman, Sloan and Shirley 2013)
+fn piecewise_gaussian(x: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+            + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+            + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+            + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+//linear sRGB, white balanced so that a constant spectrum maps to (1, 1, 1)
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    let r = 3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z;
+    let g = -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z;
+    let b = 0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z;
+    Color::new(
+        r / (3.2404542 - 1.5371385 - 0.4985314),
+        g / (-0.9692660 + 1.8760108 + 0.0415560),
+        b / (0.0556434 - 0.2040259 + 1.0572252),
+    )
+}
+
+//spectral radiance of a blackbody by Planck's law, lambda in nanometers, temperature in kelvin
+pub fn blackbody(lambda: f64, temperature: f64) -> f64 {
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+    let c = 299792458.0;
+    let h = 6.62606957e-34;
+    let kb = 1.3806488e-23;
+    let l = lambda * 1e-9;
+    2.0 * h * c * c / (l.powi(5) * ((h * c / (l * kb * temperature)).exp() - 1.0))
+}
+
+//chromaticity of a blackbody as linear RGB with unit luminance
+pub fn blackbody_rgb(temperature: f64) -> Color {
+    let n = 94;
+    let mut xyz = Vec3::zero();
+    let mut white = Vec3::zero();
+    for k in 0..n {
+        let lambda = LAMBDA_MIN + (LAMBDA_MAX - LAMBDA_MIN) * (k as f64 + 0.5) / n as f64;
+        let cmf = cie_xyz(lambda);
+        xyz += cmf * blackbody(lambda, temperature);
+        white += cmf;
+    }
+    let balanced = Vec3::new(xyz.x / white.x, xyz.y / white.y, xyz.z / white.z);
+    if balanced.y <= 0.0 {
+        return Color::zero();
+    }
+    let rgb = xyz_to_linear_srgb(balanced / balanced.y);
+    Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+//RGB to spectrum upsampling (Smits 1999), 10 bins over 380nm..720nm
+const SMITS_LAMBDA_MIN: f64 = 380.0;
+const SMITS_LAMBDA_MAX: f64 = 720.0;
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+fn smits_lookup(table: &[f64; 10], lambda: f64) -> f64 {
+    let x = clamp(
+        (lambda - SMITS_LAMBDA_MIN) / (SMITS_LAMBDA_MAX - SMITS_LAMBDA_MIN) * 9.0,
+        0.0,
+        9.0,
+    );
+    let i = (x as usize).min(8);
+    let t = x - i as f64;
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+pub fn rgb_to_spectrum(rgb: Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let white = smits_lookup(&SMITS_WHITE, lambda);
+    let cyan = smits_lookup(&SMITS_CYAN, lambda);
+    let magenta = smits_lookup(&SMITS_MAGENTA, lambda);
+    let yellow = smits_lookup(&SMITS_YELLOW, lambda);
+    let red = smits_lookup(&SMITS_RED, lambda);
+    let green = smits_lookup(&SMITS_GREEN, lambda);
+    let blue = smits_lookup(&SMITS_BLUE, lambda);
+
+    let ret = if r <= g && r <= b {
+        if g <= b {
+            r * white + (g - r) * cyan + (b - g) * blue
+        } else {
+            r * white + (b - r) * cyan + (g - b) * green
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            g * white + (r - g) * magenta + (b - r) * blue
+        } else {
+            g * white + (b - g) * magenta + (r - b) * red
+        }
+    } else if r <= g {
+        b * white + (r - b) * yellow + (g - r) * green
+    } else {
+        b * white + (g - b) * yellow + (r - g) * red
+    };
+    ret.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::luminance;
+
+    #[test]
+    fn test_terminated_hero_keeps_the_estimate() {
+        let n = 20_000;
+        let flat = SampledSpectrum::new(1.0);
+        //what a path carries past a dispersive bounce
+        let mut hero_only = SampledSpectrum::zero();
+        hero_only.c[0] = N_SPECTRUM_SAMPLES as f64;
+        let mut all = Vec3::zero();
+        let mut hero = Vec3::zero();
+        for i in 0..n {
+            let wl = SampledWavelengths::sample_uniform((i as f64 + 0.5) / n as f64);
+            all += flat.to_xyz(&wl);
+            hero += hero_only.to_xyz(&wl);
+        }
+        assert!((all.y / n as f64 - 1.0).abs() < 0.01);
+        assert!((hero.y / n as f64 - 1.0).abs() < 0.01);
+        assert!((hero - all).length() < 0.01 * n as f64);
+    }
+
+    #[test]
+    fn test_blackbody_peaks_at_wien_wavelength() {
+        //Wien's displacement law puts the peak of 5000 K near 580 nm
+        let peak = blackbody(580.0, 5000.0);
+        assert!(peak > blackbody(480.0, 5000.0) && peak > blackbody(680.0, 5000.0));
+        assert_eq!(blackbody(500.0, 0.0), 0.0);
+
+        let warm = blackbody_rgb(2700.0);
+        let daylight = blackbody_rgb(6500.0);
+        assert!(warm.x > warm.z);
+        //white here is equal energy, so 6500 K comes out slightly blue but far closer to gray than 2700 K
+        assert!((daylight.x - daylight.z).abs() < 0.3);
+        assert!(warm.x - warm.z > 1.0);
+        //unit luminance up to the white balance of the rgb conversion
+        for c in &[warm, daylight] {
+            assert!((luminance(*c) - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_bounce_throughput_follows_the_scattered_wavelengths() {
+        let wl = SampledWavelengths::sample_uniform(0.3);
+        let gray = Color::new(0.5, 0.5, 0.5);
+
+        //rays leaving plain materials pick up the path's wavelengths
+        let mut scattered = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let kept = bounce_throughput(gray, &wl, &mut scattered);
+        assert_eq!(scattered.wavelengths(), Some(wl));
+        assert!(kept.c.iter().all(|&c| c > 0.0));
+
+        //a dispersive bounce leaves only the hero, scaled to keep the estimate
+        let mut scattered = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0)
+            .with_wavelengths(wl.terminate_secondary());
+        let hero = bounce_throughput(gray, &wl, &mut scattered);
+        assert_eq!(hero.c[0], kept.c[0] * N_SPECTRUM_SAMPLES as f64);
+        assert!(hero.c[1..].iter().all(|&c| c == 0.0));
+
+        //once terminated nothing gets scaled again
+        let done = wl.terminate_secondary();
+        let mut scattered =
+            Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.0).with_wavelengths(done);
+        assert_eq!(
+            bounce_throughput(gray, &done, &mut scattered).c[0],
+            kept.c[0]
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use crate::aabb::AABB;
+use crate::color::luminance;
+use crate::hittable::{HitRecord, Hittable};
+use crate::light::{cosine_direction, uniform_sphere, EmissionSample, Light, LightSample};
 use crate::material::Material;
-use crate::rtweekend::PI;
+use crate::onb::Onb;
+use crate::rtweekend::{INFINITY, PI};
 use crate::Point3;
 use crate::Ray;
 use crate::Vec3;
@@ -28,6 +32,15 @@ impl Sphere {
         *u = phi / (2.0 * PI);
         *v = theta / PI;
     }
+
+    //partial derivatives of the hit point along u and v, p is on the unit sphere
+    pub fn get_sphere_partials(p: Point3, radius: f64) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - p.y * p.y).max(1e-8).sqrt();
+        let dpdu = Vec3::new(p.z, 0.0, -p.x) * (2.0 * PI * radius);
+        let dpdv =
+            Vec3::new(-p.x * p.y / sin_theta, sin_theta, -p.y * p.z / sin_theta) * (PI * radius);
+        (dpdu, dpdv)
+    }
 }
 
 impl crate::hittable::Hittable for Sphere {
@@ -43,9 +56,15 @@ impl crate::hittable::Hittable for Sphere {
             if t > t_min && t < t_max {
                 rec.t = t;
                 rec.p = r.at(t);
+                rec.p_object = rec.p;
                 let mut outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(&r, &mut outward_normal);
                 Sphere::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
+                let (dpdu, dpdv) = Sphere::get_sphere_partials(outward_normal, self.radius);
+                rec.dpdu = dpdu;
+                rec.dpdv = dpdv;
+                rec.dndu = dpdu / self.radius;
+                rec.dndv = dpdv / self.radius;
                 rec.mat_ptr = self.mat_ptr.clone();
                 return true;
             }
@@ -53,9 +72,15 @@ impl crate::hittable::Hittable for Sphere {
             if t > t_min && t < t_max {
                 rec.t = t;
                 rec.p = r.at(t);
+                rec.p_object = rec.p;
                 let mut outward_normal = (rec.p - self.center) / self.radius;
                 rec.set_face_normal(&r, &mut outward_normal);
                 Sphere::get_sphere_uv(outward_normal, &mut rec.u, &mut rec.v);
+                let (dpdu, dpdv) = Sphere::get_sphere_partials(outward_normal, self.radius);
+                rec.dpdu = dpdu;
+                rec.dpdv = dpdv;
+                rec.dndu = dpdu / self.radius;
+                rec.dndv = dpdv / self.radius;
                 rec.mat_ptr = self.mat_ptr.clone();
                 return true;
             }
@@ -71,3 +96,175 @@ impl crate::hittable::Hittable for Sphere {
         true
     }
 }
+
+//SphereLight
+//emissive sphere that can also be sampled as a light, registered through LightList::add_sphere
+pub struct SphereLight {
+    sphere: Sphere,
+    index: usize,
+    power: f64,
+}
+
+impl SphereLight {
+    pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>, index: usize) -> Self {
+        let mut light = Self {
+            sphere: Sphere::new(center, radius, material),
+            index,
+            power: 0.0,
+        };
+        //average the emission over a spiral of points looking straight at the surface
+        let n = 64;
+        let mut sum = 0.0;
+        for k in 0..n {
+            let z = 1.0 - 2.0 * (k as f64 + 0.5) / n as f64;
+            let r = (1.0 - z * z).sqrt();
+            let phi = k as f64 * PI * (3.0 - 5.0_f64.sqrt());
+            let d = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            let ray = Ray::new(center + d * (2.0 * radius), -d, 0.0);
+            let mut rec = HitRecord::new();
+            if light.sphere.hit(ray, 0.0, INFINITY, &mut rec) {
+                sum += luminance(rec.mat_ptr.emitted(ray, &rec));
+            }
+        }
+        light.power = sum / n as f64 * PI * 4.0 * PI * radius * radius;
+        light
+    }
+
+    //1 - cos of the half angle the sphere covers from p, None from inside
+    fn cone(&self, p: Point3) -> Option<f64> {
+        let dist2 = (self.sphere.center - p).squared_length();
+        let radius2 = self.sphere.radius * self.sphere.radius;
+        if dist2 <= radius2 * (1.0 + 1e-6) {
+            return None;
+        }
+        let sin2 = radius2 / dist2;
+        let cos_max = (1.0 - sin2).max(0.0).sqrt();
+        Some(sin2 / (1.0 + cos_max))
+    }
+
+    fn trace(&self, p: Point3, wi: Vec3) -> Option<(Ray, HitRecord)> {
+        let ray = Ray::new(p, wi, 0.0);
+        let mut rec = HitRecord::new();
+        if !self.sphere.hit(ray, 1e-6, INFINITY, &mut rec) {
+            return None;
+        }
+        Some((ray, rec))
+    }
+}
+
+impl Hittable for SphereLight {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if !self.sphere.hit(r, t_min, t_max, rec) {
+            return false;
+        }
+        rec.light = Some(self.index);
+        true
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64, output_box: &mut AABB) -> bool {
+        self.sphere.bounding_box(time0, time1, output_box)
+    }
+}
+
+impl Light for SphereLight {
+    fn sample_li(&self, p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let (wi, pdf) = match self.cone(p) {
+            //uniform over the cone of directions the sphere subtends
+            Some(one_minus_cos_max) => {
+                let cos_theta = 1.0 - u1 * one_minus_cos_max;
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+                let frame = Onb::build_from_w(self.sphere.center - p);
+                let wi = frame.local(Vec3::new(
+                    sin_theta * phi.cos(),
+                    sin_theta * phi.sin(),
+                    cos_theta,
+                ));
+                (wi, 1.0 / (2.0 * PI * one_minus_cos_max))
+            }
+            //uniform over the area, converted to solid angle
+            None => {
+                let z = 1.0 - 2.0 * u1;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+                let q = self.sphere.center
+                    + Vec3::new(r * phi.cos(), r * phi.sin(), z) * self.sphere.radius;
+                let wi = (q - p).unit();
+                (wi, self.pdf_li(p, wi))
+            }
+        };
+        let (ray, rec) = self.trace(p, wi)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            wi,
+            li: rec.mat_ptr.emitted(ray, &rec),
+            pdf,
+            dist: rec.t,
+        })
+    }
+
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        let wi = wi.unit();
+        match self.cone(p) {
+            Some(one_minus_cos_max) => {
+                let cos_theta = Vec3::dot(wi, (self.sphere.center - p).unit());
+                if 1.0 - cos_theta > one_minus_cos_max {
+                    return 0.0;
+                }
+                1.0 / (2.0 * PI * one_minus_cos_max)
+            }
+            None => match self.trace(p, wi) {
+                Some((_, rec)) => {
+                    let cosine = Vec3::dot(rec.normal, wi).abs();
+                    let area = 4.0 * PI * self.sphere.radius * self.sphere.radius;
+                    if cosine <= 0.0 {
+                        return 0.0;
+                    }
+                    rec.t * rec.t / (cosine * area)
+                }
+                None => 0.0,
+            },
+        }
+    }
+
+    fn bounds(&self) -> Option<AABB> {
+        let mut output_box = AABB::default_new();
+        self.sphere.bounding_box(0.0, 0.0, &mut output_box);
+        Some(output_box)
+    }
+
+    fn power(&self) -> f64 {
+        self.power
+    }
+
+    //uniform over the surface, cosine weighted around the outward normal
+    fn sample_le(&self, u: [f64; 4]) -> Option<EmissionSample> {
+        let n = uniform_sphere(u[0], u[1]);
+        let p = self.sphere.center + n * self.sphere.radius;
+        let dir = Onb::build_from_w(n).local(cosine_direction(u[2], u[3]));
+        let cosine = Vec3::dot(n, dir);
+        if cosine <= 0.0 {
+            return None;
+        }
+        //look back at the point from outside to evaluate the emission
+        let probe = Ray::new(p + dir * self.sphere.radius, -dir, 0.0);
+        let mut rec = HitRecord::new();
+        if !self.sphere.hit(probe, 0.0, INFINITY, &mut rec) {
+            return None;
+        }
+        Some(EmissionSample {
+            ray: Ray::new(p, dir, 0.0),
+            le: rec.mat_ptr.emitted(probe, &rec),
+            normal: n,
+            pdf_pos: 1.0 / (4.0 * PI * self.sphere.radius * self.sphere.radius),
+            pdf_dir: cosine / PI,
+        })
+    }
+
+    fn pdf_le(&self, _p: Point3, n: Vec3, dir: Vec3) -> (f64, f64) {
+        let pdf_pos = 1.0 / (4.0 * PI * self.sphere.radius * self.sphere.radius);
+        (pdf_pos, Vec3::dot(n, dir.unit()).max(0.0) / PI)
+    }
+}
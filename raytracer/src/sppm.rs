@@ -0,0 +1,336 @@
+use crate::camera::Camera;
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::estimate_direct;
+use crate::light::LightList;
+use crate::photon_map::{Photon, PhotonMap};
+use crate::rtweekend::{INFINITY, PI};
+use crate::vec3::Color;
+use crate::{emitted, escaped, sample_lights};
+use crate::{HittableList, Ray, Vec3};
+use indicatif::ProgressBar;
+use rand::Rng;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use threadpool::ThreadPool;
+
+//what one pixel keeps from iteration to iteration
+#[derive(Clone, Copy)]
+struct PixelState {
+    radius: f64,
+    //photons the estimate is made of so far
+    n: f64,
+    //photon flux gathered inside the current radius
+    tau: Color,
+    //emission and direct lighting the camera paths picked up
+    ld: Color,
+}
+
+impl PixelState {
+    //folds m new photons carrying phi into the estimate, the radius shrinks so only alpha of them count
+    fn gather(&mut self, alpha: f64, phi: Color, m: f64) {
+        if m <= 0.0 {
+            return;
+        }
+        let n = self.n + alpha * m;
+        let radius = self.radius * (n / (self.n + m)).sqrt();
+        self.tau = (self.tau + phi) * (radius * radius) / (self.radius * self.radius);
+        self.n = n;
+        self.radius = radius;
+    }
+}
+
+//Sppm
+//stochastic progressive photon mapping, each iteration traces one camera path per pixel to its first
+//non specular surface and gathers the photons of that iteration around it with a radius that shrinks
+//direct lighting comes from shadow rays, photons only carry light after their first bounce
+//media can't be gathered from without knowing their density, camera paths light them by shadow rays and
+//walk on through them to a surface, photons pass through them and are only kept on surfaces
+//only bounded lights that can sample emission send out photons, lights at infinity light directly only
+#[derive(Clone)]
+pub struct Sppm {
+    camera: Camera,
+    world: Arc<HittableList>,
+    lights: Arc<LightList>,
+    max_depth: usize,
+    photons_per_iteration: usize,
+    initial_radius: f64,
+    //fraction of the new photons kept when the radius shrinks
+    pub alpha: f64,
+}
+
+impl Sppm {
+    pub fn new(
+        camera: Camera,
+        world: Arc<HittableList>,
+        lights: Arc<LightList>,
+        max_depth: usize,
+        photons_per_iteration: usize,
+        initial_radius: f64,
+    ) -> Self {
+        Self {
+            camera,
+            world,
+            lights,
+            max_depth,
+            photons_per_iteration,
+            initial_radius,
+            alpha: 2.0 / 3.0,
+        }
+    }
+
+    //radiance of every pixel, row by row from the bottom of the film
+    pub fn render(
+        &self,
+        pool: &ThreadPool,
+        n_jobs: usize,
+        (image_width, image_height): (usize, usize),
+        iterations: usize,
+        bar: &ProgressBar,
+    ) -> Vec<Color> {
+        let mut states = vec![
+            PixelState {
+                radius: self.initial_radius,
+                n: 0.0,
+                tau: Color::zero(),
+                ld: Color::zero(),
+            };
+            image_width * image_height
+        ];
+
+        for _ in 0..iterations {
+            let (tx, rx) = channel();
+            for i in 0..n_jobs {
+                let tx = tx.clone();
+                let sppm = self.clone();
+                let count = self.photons_per_iteration * (i + 1) / n_jobs
+                    - self.photons_per_iteration * i / n_jobs;
+                pool.execute(move || {
+                    let mut photons = Vec::new();
+                    for _ in 0..count {
+                        sppm.trace_photon(&mut photons);
+                    }
+                    tx.send(photons).expect("failed to send photons");
+                });
+            }
+            let photons: Vec<Photon> = rx.iter().take(n_jobs).flatten().collect();
+            let map = Arc::new(PhotonMap::new(photons));
+
+            let (tx, rx) = channel();
+            for i in 0..n_jobs {
+                let tx = tx.clone();
+                let sppm = self.clone();
+                let map = map.clone();
+                let row_begin = image_height * i / n_jobs;
+                let row_end = image_height * (i + 1) / n_jobs;
+                let mut rows = states[row_begin * image_width..row_end * image_width].to_vec();
+                pool.execute(move || {
+                    let mut rng = rand::thread_rng();
+                    for (y, row) in (row_begin..row_end).zip(rows.chunks_mut(image_width)) {
+                        for (x, state) in row.iter_mut().enumerate() {
+                            let u_ = (x as f64 + rng.gen::<f64>()) / (image_width as f64 - 1.0);
+                            let v_ = (y as f64 + rng.gen::<f64>()) / (image_height as f64 - 1.0);
+                            let r_ = sppm
+                                .camera
+                                .get_ray_differential(
+                                    u_,
+                                    v_,
+                                    1.0 / (image_width as f64 - 1.0),
+                                    1.0 / (image_height as f64 - 1.0),
+                                )
+                                .scale_differentials(1.0 / (iterations as f64).sqrt());
+                            sppm.update(r_, &map, state);
+                        }
+                    }
+                    tx.send((row_begin, rows)).expect("failed to send result");
+                });
+            }
+            for (row_begin, rows) in rx.iter().take(n_jobs) {
+                let at = row_begin * image_width;
+                states[at..at + rows.len()].copy_from_slice(&rows);
+            }
+            bar.inc(1);
+        }
+
+        let emitted_photons = (iterations * self.photons_per_iteration) as f64;
+        states
+            .iter()
+            .map(|s| {
+                s.ld / iterations as f64 + s.tau / (emitted_photons * PI * s.radius * s.radius)
+            })
+            .collect()
+    }
+
+    fn trace_photon(&self, photons: &mut Vec<Photon>) {
+        let mut rng = rand::thread_rng();
+        let (index, pmf) = match self.lights.pick_emitter(rng.gen::<f64>()) {
+            Some(picked) => picked,
+            None => return,
+        };
+        let u = [rng.gen(), rng.gen(), rng.gen(), rng.gen()];
+        let es = match self.lights.lights[index].sample_le(u) {
+            Some(es) if es.pdf_pos > 0.0 && es.pdf_dir > 0.0 => es,
+            _ => return,
+        };
+        let cosine = if es.normal.squared_length() > 0.0 {
+            Vec3::dot(es.normal, es.ray.direction().unit()).abs()
+        } else {
+            1.0
+        };
+        let mut beta = es.le * (cosine / (pmf * es.pdf_pos * es.pdf_dir));
+        let mut ray = es.ray;
+
+        for depth in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+            if beta.near_zero() || !self.world.hit(ray, 0.001, INFINITY, &mut rec) {
+                return;
+            }
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !rec
+                .mat_ptr
+                .scatter(ray, &rec, &mut attenuation, &mut scattered)
+            {
+                return;
+            }
+            //specular surfaces can't be gathered from, the first hit is lit by shadow rays
+            if depth > 0
+                && rec.normal.squared_length() > 0.0
+                && rec.mat_ptr.scattering_pdf(ray, &rec, scattered) > 0.0
+            {
+                photons.push(Photon {
+                    p: rec.p,
+                    wi: -ray.direction().unit(),
+                    beta,
+                });
+            }
+            beta = beta * attenuation;
+            ray = scattered;
+        }
+    }
+
+    //one camera path for the pixel, then the photon estimate at its visible point
+    fn update(&self, mut r: Ray, map: &PhotonMap, state: &mut PixelState) {
+        let mut beta = Color::ones();
+        //density the current ray was scattered with inside a medium, 0 otherwise
+        let mut medium_pdf = 0.0;
+        for _ in 0..self.max_depth {
+            let mut rec = HitRecord::new();
+            if !self.world.hit(r, 0.001, INFINITY, &mut rec) {
+                state.ld += beta * escaped(r, &self.lights, medium_pdf);
+                return;
+            }
+            rec.compute_differentials(&r);
+            state.ld += beta * emitted(r, &rec, &self.lights, medium_pdf);
+
+            let mut attenuation = Color::zero();
+            let mut scattered = Ray::default_new();
+            if !rec
+                .mat_ptr
+                .scatter(r, &rec, &mut attenuation, &mut scattered)
+            {
+                return;
+            }
+            let bsdf_pdf = rec.mat_ptr.scattering_pdf(r, &rec, scattered);
+            if bsdf_pdf <= 0.0 {
+                beta = beta * attenuation;
+                r = scattered;
+                medium_pdf = 0.0;
+                continue;
+            }
+            if rec.normal.squared_length() <= 0.0 {
+                state.ld += beta * sample_lights(r, &rec, &self.lights, &self.world);
+                beta = beta * attenuation;
+                r = scattered;
+                medium_pdf = bsdf_pdf;
+                continue;
+            }
+
+            //the visible point, direct lighting from both strategies like ray_color
+            state.ld += beta
+                * estimate_direct(
+                    r,
+                    &rec,
+                    (attenuation, scattered),
+                    bsdf_pdf,
+                    &self.world,
+                    &self.lights,
+                );
+
+            let mut phi = Color::zero();
+            let mut m = 0.0;
+            map.lookup(rec.p, state.radius, &mut |photon: &Photon| {
+                let wi = Ray::new(rec.p, photon.wi, r.time());
+                let cosine = Vec3::dot(rec.normal, photon.wi).abs();
+                if cosine > 1e-4 {
+                    phi += photon.beta * rec.mat_ptr.eval_bsdf(r, &rec, wi) / cosine;
+                    m += 1.0;
+                }
+            });
+            state.gather(self.alpha, beta * phi, m);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene;
+
+    #[test]
+    fn test_radius_shrinks_as_photons_are_gathered() {
+        let mut state = PixelState {
+            radius: 1.0,
+            n: 0.0,
+            tau: Color::zero(),
+            ld: Color::zero(),
+        };
+        state.gather(2.0 / 3.0, Color::new(3.0, 3.0, 3.0), 3.0);
+        assert!((state.n - 2.0).abs() < 1e-12);
+        assert!((state.radius * state.radius - 2.0 / 3.0).abs() < 1e-12);
+        assert!((state.tau.x - 2.0).abs() < 1e-12);
+
+        //no photons leave everything as it was
+        let before = (state.radius, state.n);
+        state.gather(2.0 / 3.0, Color::zero(), 0.0);
+        assert_eq!((state.radius, state.n), before);
+
+        //the area keeps shrinking like (n + alpha m) / (n + m) and never reaches zero
+        for _ in 0..100 {
+            let r2 = state.radius * state.radius;
+            let n = state.n;
+            state.gather(2.0 / 3.0, Color::ones(), 10.0);
+            let expected = r2 * (n + 2.0 / 3.0 * 10.0) / (n + 10.0);
+            assert!((state.radius * state.radius - expected).abs() < 1e-12);
+        }
+        assert!(state.radius > 0.0 && state.radius < 0.5);
+    }
+
+    #[test]
+    fn test_rect_light_photons_reach_the_floor_under_the_glass() {
+        let scene = scene::by_name("caustics", 1.0).unwrap();
+        let sppm = Sppm::new(
+            scene.camera,
+            Arc::new(scene.world),
+            Arc::new(scene.lights),
+            8,
+            0,
+            0.1,
+        );
+        let mut photons = Vec::new();
+        for _ in 0..20_000 {
+            sppm.trace_photon(&mut photons);
+        }
+        assert!(!photons.is_empty());
+        let mut under_glass = 0;
+        for photon in photons.iter() {
+            assert!(photon.beta.x.is_finite() && photon.beta.x >= 0.0);
+            let on_floor = photon.p.y.abs() < 1e-6;
+            let below = (photon.p - Vec3::new(-1.2, 0.0, 0.5)).length() < 1.0;
+            if on_floor && below {
+                under_glass += 1;
+            }
+        }
+        assert!(under_glass > 0);
+    }
+}
@@ -1,12 +1,65 @@
+use crate::color::srgb_to_linear;
+use crate::hittable::HitRecord;
+use crate::mipmap::{Filter, MipFilter, MipMap, WrapMode};
 use crate::perlin::Perlin;
-use crate::rtweekend::clamp;
+use crate::rtweekend::degrees_to_radians;
 use crate::Point3;
 use crate::{Color, Vec3};
-use image::GenericImageView;
+use image::error::{DecodingError, ImageFormatHint};
+use image::hdr::HdrDecoder;
+use image::{DynamicImage, GenericImageView, ImageError, ImageResult};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 use std::sync::Arc;
 
 pub trait Texture {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+
+    //read by alpha masks only
+    fn alpha(&self, _u: f64, _v: f64, _p: Point3) -> f64 {
+        1.0
+    }
+
+    //lookup at a hit, filtered textures use the uv footprint of the record
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, rec.p)
+    }
+
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.alpha(rec.u, rec.v, rec.p)
+    }
+}
+
+//where a texture is evaluated, inputs are looked up at the same place so they see the hit too
+#[derive(Clone, Copy)]
+pub enum Lookup<'a> {
+    Point { u: f64, v: f64, p: Point3 },
+    Hit(&'a HitRecord),
+}
+
+impl Lookup<'_> {
+    pub fn eval(self, t: &Arc<dyn Texture>) -> Color {
+        match self {
+            Lookup::Point { u, v, p } => t.value(u, v, p),
+            Lookup::Hit(rec) => t.value_at(rec),
+        }
+    }
+
+    pub fn scalar(self, t: &Arc<dyn Texture>) -> f64 {
+        self.eval(t).x
+    }
+
+    pub fn uvp(self) -> (f64, f64, Point3) {
+        match self {
+            Lookup::Point { u, v, p } => (u, v, p),
+            Lookup::Hit(rec) => (rec.u, rec.v, rec.p),
+        }
+    }
+}
+
+pub fn lookup_point(u: f64, v: f64, p: Point3) -> Lookup<'static> {
+    Lookup::Point { u, v, p }
 }
 
 //SolidColor
@@ -18,6 +71,10 @@ impl SolidColor {
     pub fn new(c: Color) -> Self {
         Self { color_value: c }
     }
+
+    pub fn new_by_value(v: f64) -> Self {
+        Self::new(Color::new(v, v, v))
+    }
 }
 
 impl Texture for SolidColor {
@@ -27,29 +84,43 @@ impl Texture for SolidColor {
 }
 
 //CheckerTexture
+//3d checker from the sign of sines, scale is the frequency along each axis
 pub struct CheckerTexture {
     odd: Arc<dyn Texture>,
     even: Arc<dyn Texture>,
+    scale: f64,
 }
 
 impl CheckerTexture {
-    /*
+    pub fn new(even: Arc<dyn Texture>, odd: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { odd, even, scale }
+    }
+
     pub fn new_by_color(c1: Color, c2: Color) -> Self {
-        Self {
-            even: Arc::new(SolidColor::new(c1)),
-            odd: Arc::new(SolidColor::new(c2)),
+        Self::new(
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+            10.0,
+        )
+    }
+
+    fn eval(&self, at: Lookup) -> Color {
+        let (_, _, p) = at.uvp();
+        let sines = (self.scale * p.x).sin() * (self.scale * p.y).sin() * (self.scale * p.z).sin();
+        if sines < 0.0 {
+            return at.eval(&self.odd);
         }
+        at.eval(&self.even)
     }
-    */
 }
 
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
-        let sines = (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
-        if sines < 0.0 {
-            return self.odd.value(u, v, p);
-        }
-        self.even.value(u, v, p)
+        self.eval(lookup_point(u, v, p))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.eval(Lookup::Hit(rec))
     }
 }
 
@@ -61,12 +132,10 @@ pub struct NoiseTexture {
 
 impl NoiseTexture {
     pub fn new(sc: f64) -> Self {
-        let mut tmp = Self {
+        Self {
             noise: Perlin::new(),
             scale: sc,
-        };
-        tmp.noise.init();
-        tmp
+        }
     }
 }
 
@@ -79,49 +148,285 @@ impl Texture for NoiseTexture {
 }
 
 //ImageTexture
-pub struct ImageTexture {
-    data: Vec<u8>,
-    width: u32,
-    height: u32,
-    bytes_per_scanline: u32,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorSpace {
+    //8 and 16 bit color images are sRGB, grayscale and HDR images are linear
+    Auto,
+    Srgb,
+    Linear,
 }
 
-impl ImageTexture {
-    pub fn new(filename: &str) -> Self {
-        let img = image::open(filename).unwrap();
-        let buf = img.clone().into_bytes();
-        let width_tmp = img.width();
-        let height_tmp = img.height();
+//scale, then counterclockwise rotation in degrees, then offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvTransform {
+    pub scale: (f64, f64),
+    pub offset: (f64, f64),
+    pub rotation: f64,
+}
 
+impl UvTransform {
+    pub fn new(scale: (f64, f64), offset: (f64, f64), rotation: f64) -> Self {
         Self {
-            data: buf,
-            width: width_tmp,
-            height: height_tmp,
-            bytes_per_scanline: 3 * width_tmp,
+            scale,
+            offset,
+            rotation,
         }
     }
+
+    pub fn identity() -> Self {
+        Self::new((1.0, 1.0), (0.0, 0.0), 0.0)
+    }
+
+    //transforms a difference of uv coordinates, without the offset
+    pub fn apply_vector(&self, du: f64, dv: f64) -> (f64, f64) {
+        let (su, sv) = (du * self.scale.0, dv * self.scale.1);
+        let theta = degrees_to_radians(self.rotation);
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        (
+            cos_theta * su - sin_theta * sv,
+            sin_theta * su + cos_theta * sv,
+        )
+    }
+
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let (tu, tv) = self.apply_vector(u, v);
+        (tu + self.offset.0, tv + self.offset.1)
+    }
 }
 
-impl Texture for ImageTexture {
-    fn value(&self, mut u_: f64, mut v_: f64, _p: Vec3) -> Vec3 {
-        u_ = clamp(u_, 0.0, 1.0);
-        v_ = 1.0 - clamp(v_, 0.0, 1.0);
+pub struct ImageTexture {
+    mipmap: MipMap,
+    pub wrap: WrapMode,
+    pub filter: Filter,
+    pub mip_filter: MipFilter,
+    pub transform: UvTransform,
+}
+
+impl ImageTexture {
+    pub fn new(filename: &str) -> ImageResult<Self> {
+        Self::load(filename, ColorSpace::Auto)
+    }
 
-        let mut i = (u_ * self.width as f64) as usize;
-        let mut j = (v_ * self.height as f64) as usize;
+    //for data textures such as normal, roughness or height maps
+    pub fn new_linear(filename: &str) -> ImageResult<Self> {
+        Self::load(filename, ColorSpace::Linear)
+    }
+
+    //linear RGBA rows from the top
+    pub fn from_data(width: u32, height: u32, data: Vec<f32>) -> Self {
+        Self {
+            mipmap: MipMap::new(width as usize, height as usize, data),
+            wrap: WrapMode::Clamp,
+            filter: Filter::Bilinear,
+            mip_filter: MipFilter::Trilinear,
+            transform: UvTransform::identity(),
+        }
+    }
 
-        if i >= self.width as usize {
-            i = (self.width - 1) as usize;
+    pub fn load(filename: &str, color_space: ColorSpace) -> ImageResult<Self> {
+        let has_extension = |ext: &str| matches!(Path::new(filename).extension(), Some(e) if e.eq_ignore_ascii_case(ext));
+        if has_extension("exr") {
+            return Self::load_exr(filename, color_space);
         }
-        if j >= self.height as usize {
-            j = (self.height - 1) as usize;
+        if has_extension("hdr") {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(filename)?))?;
+            let width = decoder.metadata().width;
+            let height = decoder.metadata().height;
+            let decode = color_space == ColorSpace::Srgb;
+            let mut data = Vec::with_capacity((width * height * 4) as usize);
+            for pixel in decoder.read_image_hdr()? {
+                for c in pixel.0.iter() {
+                    data.push(if decode {
+                        srgb_to_linear(*c as f64) as f32
+                    } else {
+                        *c
+                    });
+                }
+                data.push(1.0);
+            }
+            return Ok(Self::from_data(width, height, data));
         }
 
-        let color_scale = 1.0 / 255.0;
-        Color::new(
-            color_scale * self.data[j * self.bytes_per_scanline as usize + i * 3] as f64,
-            color_scale * self.data[j * self.bytes_per_scanline as usize + i * 3 + 1] as f64,
-            color_scale * self.data[j * self.bytes_per_scanline as usize + i * 3 + 2] as f64,
+        let img = image::open(filename)?;
+        let (width, height) = img.dimensions();
+        let decode = match color_space {
+            ColorSpace::Srgb => true,
+            ColorSpace::Linear => false,
+            ColorSpace::Auto => !matches!(
+                img,
+                DynamicImage::ImageLuma8(_)
+                    | DynamicImage::ImageLumaA8(_)
+                    | DynamicImage::ImageLuma16(_)
+                    | DynamicImage::ImageLumaA16(_)
+            ),
+        };
+        //16 bit images keep their precision, the rest is read at 8 bits, widening shifts and would leave white short of 1
+        let channels: Vec<f64> = match img {
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => img
+                .to_rgba16()
+                .into_raw()
+                .iter()
+                .map(|c| *c as f64 / 65535.0)
+                .collect(),
+            _ => img
+                .to_rgba8()
+                .into_raw()
+                .iter()
+                .map(|c| *c as f64 / 255.0)
+                .collect(),
+        };
+        let data = channels
+            .iter()
+            .enumerate()
+            .map(|(k, value)| {
+                if decode && k % 4 < 3 {
+                    srgb_to_linear(*value) as f32
+                } else {
+                    *value as f32
+                }
+            })
+            .collect();
+        Ok(Self::from_data(width, height, data))
+    }
+
+    //OpenEXR holds linear floats like .hdr does, the first rgba layer at full resolution is read
+    fn load_exr(filename: &str, color_space: ColorSpace) -> ImageResult<Self> {
+        let decode = color_space == ColorSpace::Srgb;
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            filename,
+            |resolution, _| (resolution.width(), vec![0.0f32; resolution.area() * 4]),
+            move |(width, data), position, (r, g, b, a): (f32, f32, f32, f32)| {
+                let at = (position.y() * *width + position.x()) * 4;
+                for (k, c) in [r, g, b].iter().enumerate() {
+                    data[at + k] = if decode {
+                        srgb_to_linear(*c as f64) as f32
+                    } else {
+                        *c
+                    };
+                }
+                data[at + 3] = a;
+            },
         )
+        .map_err(|e| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Name("OpenEXR".to_string()),
+                e,
+            ))
+        })?;
+        let size = image.layer_data.size;
+        let (_, data) = image.layer_data.channel_data.pixels;
+        Ok(Self::from_data(
+            size.width() as u32,
+            size.height() as u32,
+            data,
+        ))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.mipmap.width() as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.mipmap.height() as u32
+    }
+
+    //linear RGBA of the texel at column i, row j counted from the top
+    pub fn texel(&self, i: usize, j: usize) -> [f64; 4] {
+        self.mipmap.texel(0, i as i64, j as i64, WrapMode::Clamp)
+    }
+
+    //filtered RGBA, the footprint is given by the screen space derivatives of u and v
+    pub fn lookup(&self, u: f64, v: f64, duvdx: (f64, f64), duvdy: (f64, f64)) -> [f64; 4] {
+        let (s, t) = self.transform.apply(u, v);
+        //image rows run from the top, v from the bottom
+        let t = 1.0 - t;
+        let (dsdx, dtdx) = self.transform.apply_vector(duvdx.0, duvdx.1);
+        let (dsdy, dtdy) = self.transform.apply_vector(duvdy.0, duvdy.1);
+
+        match self.mip_filter {
+            MipFilter::None => self.mipmap.filtered(0, s, t, self.filter, self.wrap),
+            MipFilter::Trilinear => {
+                let width = 2.0 * dsdx.abs().max(dtdx.abs()).max(dsdy.abs()).max(dtdy.abs());
+                self.mipmap.trilinear(s, t, width, self.filter, self.wrap)
+            }
+            MipFilter::Ewa => self
+                .mipmap
+                .ewa(s, t, (dsdx, -dtdx), (dsdy, -dtdy), self.wrap),
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u_: f64, v_: f64, _p: Vec3) -> Vec3 {
+        let texel = self.lookup(u_, v_, (0.0, 0.0), (0.0, 0.0));
+        Color::new(texel[0], texel[1], texel[2])
+    }
+
+    fn alpha(&self, u_: f64, v_: f64, _p: Vec3) -> f64 {
+        self.lookup(u_, v_, (0.0, 0.0), (0.0, 0.0))[3]
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        let texel = self.lookup(rec.u, rec.v, (rec.dudx, rec.dvdx), (rec.dudy, rec.dvdy));
+        Color::new(texel[0], texel[1], texel[2])
+    }
+
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.lookup(rec.u, rec.v, (rec.dudx, rec.dvdx), (rec.dudy, rec.dvdy))[3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::srgb_to_linear;
+
+    #[test]
+    fn test_color_images_are_decoded_from_srgb() {
+        let path = std::env::temp_dir().join("raytracer_texture_test.png");
+        image::RgbImage::from_raw(2, 1, vec![255, 128, 0, 0, 64, 255])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let path = path.to_str().unwrap();
+
+        let srgb = ImageTexture::new(path).unwrap();
+        assert_eq!((srgb.width(), srgb.height()), (2, 1));
+        let texel = srgb.texel(0, 0);
+        assert!((texel[0] - 1.0).abs() < 1e-6);
+        assert!((texel[1] - srgb_to_linear(128.0 / 255.0)).abs() < 1e-6);
+        assert_eq!(texel[3], 1.0);
+
+        let linear = ImageTexture::new_linear(path).unwrap();
+        assert!((linear.texel(1, 0)[1] - 64.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_grayscale_16_bit_images_stay_linear() {
+        let path = std::env::temp_dir().join("raytracer_texture_test_16.png");
+        image::ImageBuffer::<image::Luma<u16>, _>::from_raw(2, 1, vec![65535u16, 16384])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let gray = ImageTexture::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(gray.texel(0, 0), [1.0, 1.0, 1.0, 1.0]);
+        assert!((gray.texel(1, 0)[0] - 16384.0 / 65535.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_exr_images_keep_their_linear_floats() {
+        let path = std::env::temp_dir().join("raytracer_texture_test.exr");
+        exr::prelude::write_rgba_file(&path, 2, 2, |x, y| {
+            let v = (1 + x + 2 * y) as f32;
+            (v * 4.0, v * 0.25, v, 0.5)
+        })
+        .unwrap();
+        let texture = ImageTexture::new(path.to_str().unwrap()).unwrap();
+        assert_eq!((texture.width(), texture.height()), (2, 2));
+        assert_eq!(texture.texel(0, 0), [4.0, 0.25, 1.0, 0.5]);
+        assert_eq!(texture.texel(1, 1), [16.0, 1.0, 4.0, 0.5]);
     }
 }
@@ -0,0 +1,315 @@
+use crate::hittable::HitRecord;
+use crate::material::{Dielectric, Ior, Material, Metal};
+use crate::rtweekend::clamp;
+use crate::spectrum::{cie_xyz, rgb_to_spectrum, xyz_to_linear_srgb, SampledWavelengths};
+use crate::texture::Texture;
+use crate::vec3::Color;
+use crate::{Ray, Vec3};
+use num_complex::Complex64;
+use rand::Rng;
+use std::sync::Arc;
+
+const FILM_SAMPLES: usize = 16;
+const FILM_LAMBDA_MIN: f64 = 380.0;
+const FILM_LAMBDA_MAX: f64 = 780.0;
+
+//the coated material keeps its own index, dispersion and fuzz
+enum Substrate {
+    Dielectric(Dielectric),
+    Metal(Metal),
+}
+
+//thin film coating, thickness in nanometers is thickness_min..thickness_max mapped from the texture
+pub struct ThinFilm {
+    substrate: Substrate,
+    film_ior: f64,
+    thickness: Arc<dyn Texture>,
+    thickness_min: f64,
+    thickness_max: f64,
+}
+
+impl ThinFilm {
+    pub fn new_on_dielectric(
+        base: Dielectric,
+        film_ior: f64,
+        thickness: Arc<dyn Texture>,
+        thickness_min: f64,
+        thickness_max: f64,
+    ) -> Self {
+        Self {
+            substrate: Substrate::Dielectric(base),
+            film_ior,
+            thickness,
+            thickness_min,
+            thickness_max,
+        }
+    }
+
+    pub fn new_on_metal(
+        base: Metal,
+        film_ior: f64,
+        thickness: Arc<dyn Texture>,
+        thickness_min: f64,
+        thickness_max: f64,
+    ) -> Self {
+        Self {
+            substrate: Substrate::Metal(base),
+            film_ior,
+            thickness,
+            thickness_min,
+            thickness_max,
+        }
+    }
+
+    fn film_thickness(&self, rec: &HitRecord) -> f64 {
+        let t = clamp(self.thickness.value_at(rec).x, 0.0, 1.0);
+        self.thickness_min + (self.thickness_max - self.thickness_min) * t
+    }
+
+    //complex index of refraction of the substrate at one wavelength
+    fn substrate_ior(&self, lambda: f64) -> Complex64 {
+        match &self.substrate {
+            Substrate::Dielectric(base) => Complex64::new(base.ior().at(lambda), 0.0),
+            Substrate::Metal(base) => {
+                //Gulbrandsen's artist friendly metallic fresnel, edge tint equal to the reflectivity
+                let r = clamp(rgb_to_spectrum(base.albedo(), lambda), 0.0, 0.99);
+                let g = r;
+                let n_min = (1.0 - r) / (1.0 + r);
+                let n_max = (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+                let n = g * n_min + (1.0 - g) * n_max;
+                let k2 = (r * (n + 1.0).powi(2) - (n - 1.0).powi(2)) / (1.0 - r);
+                Complex64::new(n, k2.max(0.0).sqrt())
+            }
+        }
+    }
+
+    //film reflectance at one wavelength in nanometers
+    fn reflectance_at(
+        &self,
+        cos_i: f64,
+        n_outside: f64,
+        thickness: f64,
+        front_face: bool,
+        lambda: f64,
+    ) -> f64 {
+        let n_substrate = self.substrate_ior(lambda);
+        let (n1, n3) = if front_face {
+            (Complex64::new(n_outside, 0.0), n_substrate)
+        } else {
+            (n_substrate, Complex64::new(n_outside, 0.0))
+        };
+        airy_reflectance(
+            cos_i,
+            lambda,
+            thickness,
+            n1,
+            Complex64::new(self.film_ior, 0.0),
+            n3,
+        )
+    }
+
+    //film reflectance integrated against the CIE matching functions, as linear RGB
+    fn reflectance(&self, cos_i: f64, n_outside: f64, thickness: f64, front_face: bool) -> Color {
+        let mut xyz = Vec3::zero();
+        let mut white = Vec3::zero();
+        for k in 0..FILM_SAMPLES {
+            let lambda = FILM_LAMBDA_MIN
+                + (FILM_LAMBDA_MAX - FILM_LAMBDA_MIN) * (k as f64 + 0.5) / FILM_SAMPLES as f64;
+            let r = self.reflectance_at(cos_i, n_outside, thickness, front_face, lambda);
+            let cmf = cie_xyz(lambda);
+            xyz += cmf * r;
+            white += cmf;
+        }
+        let rgb = xyz_to_linear_srgb(Vec3::new(xyz.x / white.x, xyz.y / white.y, xyz.z / white.z));
+        Color::new(
+            clamp(rgb.x, 0.0, 1.0),
+            clamp(rgb.y, 0.0, 1.0),
+            clamp(rgb.z, 0.0, 1.0),
+        )
+    }
+
+    //spectral rays see the film at their hero wavelength alone, like dispersive glass, rgb rays get the integrated color
+    fn reflectance_for(
+        &self,
+        r_in: &Ray,
+        cos_i: f64,
+        thickness: f64,
+        front_face: bool,
+    ) -> (Color, Option<SampledWavelengths>) {
+        match r_in.wavelengths() {
+            Some(wl) => (
+                Color::ones() * self.reflectance_at(cos_i, 1.0, thickness, front_face, wl.hero()),
+                Some(wl.terminate_secondary()),
+            ),
+            None => (self.reflectance(cos_i, 1.0, thickness, front_face), None),
+        }
+    }
+}
+
+fn fresnel_amplitudes(
+    n_i: Complex64,
+    cos_i: Complex64,
+    n_t: Complex64,
+    cos_t: Complex64,
+) -> (Complex64, Complex64) {
+    let rs = (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t);
+    let rp = (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t);
+    (rs, rp)
+}
+
+//reflectance of the stack n1 | film n2 of given thickness | n3, averaged over both polarizations
+pub fn airy_reflectance(
+    cos_1: f64,
+    lambda: f64,
+    thickness: f64,
+    n1: Complex64,
+    n2: Complex64,
+    n3: Complex64,
+) -> f64 {
+    let one = Complex64::new(1.0, 0.0);
+    let cos_1 = Complex64::new(clamp(cos_1, 0.0, 1.0), 0.0);
+    let sin2_1 = one - cos_1 * cos_1;
+    let cos_2 = (one - sin2_1 * (n1 / n2) * (n1 / n2)).sqrt();
+    let cos_3 = (one - sin2_1 * (n1 / n3) * (n1 / n3)).sqrt();
+
+    let (rs12, rp12) = fresnel_amplitudes(n1, cos_1, n2, cos_2);
+    let (rs23, rp23) = fresnel_amplitudes(n2, cos_2, n3, cos_3);
+
+    let delta = n2 * cos_2 * (4.0 * std::f64::consts::PI * thickness / lambda);
+    let phase = (Complex64::new(0.0, 1.0) * delta).exp();
+
+    let rs = (rs12 + rs23 * phase) / (one + rs12 * rs23 * phase);
+    let rp = (rp12 + rp23 * phase) / (one + rp12 * rp23 * phase);
+    clamp((rs.norm_sqr() + rp.norm_sqr()) / 2.0, 0.0, 1.0)
+}
+
+impl Material for ThinFilm {
+    fn scatter(
+        &self,
+        r_in: Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> bool {
+        let unit_direction = r_in.direction().unit();
+        let cos_theta = Vec3::dot(-unit_direction, rec.normal).min(1.0);
+        let thickness = self.film_thickness(rec);
+
+        match &self.substrate {
+            Substrate::Metal(base) => {
+                //the metal picks the fuzzed direction, the film replaces its color
+                if !base.scatter(r_in, rec, attenuation, scattered) {
+                    return false;
+                }
+                let (reflectance, wavelengths) =
+                    self.reflectance_for(&r_in, cos_theta, thickness, true);
+                if let Some(wl) = wavelengths {
+                    *scattered = scattered.with_wavelengths(wl);
+                }
+                *attenuation = reflectance;
+                true
+            }
+            Substrate::Dielectric(base) => {
+                let (reflectance, wavelengths) =
+                    self.reflectance_for(&r_in, cos_theta, thickness, rec.front_face);
+                let ir = match wavelengths {
+                    Some(wl) => base.ior().at(wl.hero()),
+                    None => base.ior().at(Ior::REFERENCE_LAMBDA),
+                };
+                let p_reflect = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+                let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let cannot_refract = refraction_ratio * sin_theta > 1.0;
+
+                let mut rng = rand::thread_rng();
+                let (direction, diff) = if cannot_refract || rng.gen::<f64>() < p_reflect {
+                    *attenuation = if cannot_refract {
+                        Color::ones()
+                    } else {
+                        reflectance / p_reflect
+                    };
+                    let reflected = Vec3::reflect(unit_direction, rec.normal);
+                    (reflected, rec.reflected_differentials(&r_in, reflected))
+                } else {
+                    *attenuation = (Color::ones() - reflectance) / (1.0 - p_reflect);
+                    let refracted = Vec3::refract(unit_direction, rec.normal, refraction_ratio);
+                    (
+                        refracted,
+                        rec.refracted_differentials(&r_in, refracted, refraction_ratio),
+                    )
+                };
+                *scattered = Ray::new(rec.p, direction, r_in.time());
+                if let Some(diff) = diff {
+                    *scattered = scattered.with_differentials(diff);
+                }
+                if let Some(wl) = wavelengths {
+                    *scattered = scattered.with_wavelengths(wl);
+                }
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::SolidColor;
+
+    #[test]
+    fn test_spectral_rays_see_the_film_at_their_hero_wavelength() {
+        let film = ThinFilm::new_on_metal(
+            Metal::new(Color::new(0.9, 0.6, 0.3), 0.0),
+            1.4,
+            Arc::new(SolidColor::new_by_value(0.5)),
+            200.0,
+            600.0,
+        );
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        let thickness = film.film_thickness(&rec);
+        let cos_i = 0.5f64.sqrt();
+        let wl = SampledWavelengths::sample_uniform(0.3);
+        let r_in = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0)
+            .with_wavelengths(wl);
+
+        let mut attenuation = Color::zero();
+        let mut scattered = Ray::default_new();
+        assert!(film.scatter(r_in, &rec, &mut attenuation, &mut scattered));
+        let expected = film.reflectance_at(cos_i, 1.0, thickness, true, wl.hero());
+        assert!((attenuation - Color::ones() * expected).length() < 1e-9);
+        assert!(scattered.wavelengths().unwrap().secondary_terminated());
+
+        //rgb rays keep the color integrated over the visible range
+        let r_in = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0), 0.0);
+        assert!(film.scatter(r_in, &rec, &mut attenuation, &mut scattered));
+        let expected = film.reflectance(cos_i, 1.0, thickness, true);
+        assert!((attenuation - expected).length() < 1e-9);
+        assert!(scattered.wavelengths().is_none());
+    }
+
+    #[test]
+    fn test_film_sees_the_dispersion_of_the_glass_below() {
+        let film = ThinFilm::new_on_dielectric(
+            Dielectric::new_bk7(),
+            1.33,
+            Arc::new(SolidColor::new_by_value(1.0)),
+            0.0,
+            400.0,
+        );
+        let bk7 = Dielectric::new_bk7().ior();
+        for &lambda in [420.0, 550.0, 680.0].iter() {
+            let expected = airy_reflectance(
+                0.8,
+                lambda,
+                400.0,
+                Complex64::new(1.0, 0.0),
+                Complex64::new(1.33, 0.0),
+                Complex64::new(bk7.at(lambda), 0.0),
+            );
+            assert!((film.reflectance_at(0.8, 1.0, 400.0, true, lambda) - expected).abs() < 1e-12);
+        }
+        assert!(bk7.at(420.0) > bk7.at(680.0));
+    }
+}
@@ -99,6 +99,18 @@ impl Vec3 {
         r_out_perp + r_out_parallel
     }
 
+    pub fn random_cosine_direction() -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        Vec3::new(
+            phi.cos() * r2.sqrt(),
+            phi.sin() * r2.sqrt(),
+            (1.0 - r2).sqrt(),
+        )
+    }
+
     pub fn random_in_unit_disk() -> Vec3 {
         let mut rng = rand::thread_rng();
         loop {